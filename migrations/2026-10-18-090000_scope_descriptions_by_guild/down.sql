@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE descriptions DROP CONSTRAINT descriptions_guild_id_key_key;
+
+ALTER TABLE descriptions
+    DROP COLUMN id,
+    DROP COLUMN guild_id,
+    DROP COLUMN user_id,
+    DROP COLUMN timestamp;
+
+ALTER TABLE descriptions ADD PRIMARY KEY (key);
@@ -0,0 +1,13 @@
+-- Scope descriptions to a guild and record who last edited each entry.
+-- Entries created before this migration have no known guild and land in guild 0.
+
+ALTER TABLE descriptions DROP CONSTRAINT descriptions_pkey;
+
+ALTER TABLE descriptions
+    ADD COLUMN id BIGSERIAL PRIMARY KEY,
+    ADD COLUMN guild_id BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN user_id BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN timestamp TIMESTAMP NOT NULL DEFAULT NOW();
+
+ALTER TABLE descriptions
+    ADD CONSTRAINT descriptions_guild_id_key_key UNIQUE (guild_id, key);
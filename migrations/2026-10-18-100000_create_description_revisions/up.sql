@@ -0,0 +1,16 @@
+-- Every value written to `descriptions` is kept here so it can be listed and restored.
+
+CREATE TABLE description_revisions (
+    id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    key VARCHAR NOT NULL,
+    rev INTEGER NOT NULL,
+    value VARCHAR NOT NULL,
+    user_id BIGINT NOT NULL,
+    timestamp TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (guild_id, key, rev)
+);
+
+-- Seed the history with the current value of every existing entry.
+INSERT INTO description_revisions (guild_id, key, rev, value, user_id, timestamp)
+SELECT guild_id, key, 1, value, user_id, timestamp FROM descriptions;
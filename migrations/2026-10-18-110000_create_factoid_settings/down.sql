@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE factoid_channels;
+DROP TABLE factoid_settings;
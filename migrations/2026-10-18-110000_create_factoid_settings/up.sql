@@ -0,0 +1,15 @@
+-- Per-guild configuration for replying to `key?` and `!key` messages.
+
+CREATE TABLE factoid_settings (
+    guild_id BIGINT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    cooldown_seconds INTEGER NOT NULL DEFAULT 30,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Channels factoids may reply in. A guild with no rows here allows every channel.
+CREATE TABLE factoid_channels (
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    PRIMARY KEY (guild_id, channel_id)
+);
@@ -0,0 +1,3 @@
+-- Number of times each entry has been read, exposed to templates as `{count}`.
+
+ALTER TABLE descriptions ADD COLUMN reads BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE response_pool_settings;
+DROP TABLE response_pools;
@@ -0,0 +1,19 @@
+-- Guild-specific entries for the /food, /drink, /ball and /botsnack response lists.
+
+CREATE TABLE response_pools (
+    id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    pool VARCHAR NOT NULL,
+    item VARCHAR NOT NULL,
+    user_id BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (guild_id, pool, item)
+);
+
+-- Whether a guild's entries extend (default) or replace the built-in list.
+CREATE TABLE response_pool_settings (
+    guild_id BIGINT NOT NULL,
+    pool VARCHAR NOT NULL,
+    replace_defaults BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (guild_id, pool)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE shuffle_bags;
+ALTER TABLE response_pool_settings DROP COLUMN selection;
+ALTER TABLE response_pools DROP COLUMN weight;
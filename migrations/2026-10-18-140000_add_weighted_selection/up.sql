@@ -0,0 +1,15 @@
+-- Per-entry weights and a per-pool selection mode for response pools.
+
+ALTER TABLE response_pools ADD COLUMN weight INTEGER NOT NULL DEFAULT 1;
+
+-- 'random' draws independently every time; 'shuffle' serves every entry before repeating.
+ALTER TABLE response_pool_settings ADD COLUMN selection VARCHAR NOT NULL DEFAULT 'random';
+
+-- Remaining draws of each guild's shuffle bag, stored as JSON.
+CREATE TABLE shuffle_bags (
+    guild_id BIGINT NOT NULL,
+    pool VARCHAR NOT NULL,
+    remaining TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (guild_id, pool)
+);
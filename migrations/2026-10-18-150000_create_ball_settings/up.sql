@@ -0,0 +1,8 @@
+-- Per-guild configuration for /ball. In consistent mode the same question
+-- asked on the same day always gets the same answer.
+
+CREATE TABLE ball_settings (
+    guild_id BIGINT PRIMARY KEY,
+    consistent BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
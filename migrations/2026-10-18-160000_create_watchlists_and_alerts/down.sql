@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE price_alerts;
+DROP TABLE watchlists;
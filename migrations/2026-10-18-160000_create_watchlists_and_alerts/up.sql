@@ -0,0 +1,24 @@
+-- Tickers each user follows with /watch, and the price alerts they set with
+-- /alert. Alerts with no channel are sent by DM; triggered_at is set once the
+-- poller has fired an alert so it is never sent twice.
+
+CREATE TABLE watchlists (
+    user_id BIGINT NOT NULL,
+    symbol VARCHAR NOT NULL,
+    added_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, symbol)
+);
+
+CREATE TABLE price_alerts (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    channel_id BIGINT,
+    symbol VARCHAR NOT NULL,
+    direction VARCHAR NOT NULL,
+    threshold DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    triggered_at TIMESTAMP,
+    triggered_price DOUBLE PRECISION
+);
+
+CREATE INDEX price_alerts_pending_idx ON price_alerts (symbol) WHERE triggered_at IS NULL;
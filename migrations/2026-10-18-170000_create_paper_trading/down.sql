@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE paper_trades;
+DROP TABLE paper_positions;
+DROP TABLE paper_accounts;
@@ -0,0 +1,33 @@
+-- Paper trading: each user gets a virtual cash account per guild, positions
+-- held with their total cost, and a ledger of every trade. Money is stored
+-- in cents.
+
+CREATE TABLE paper_accounts (
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    cash_cents BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE paper_positions (
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    symbol VARCHAR NOT NULL,
+    shares BIGINT NOT NULL,
+    cost_cents BIGINT NOT NULL,
+    PRIMARY KEY (guild_id, user_id, symbol)
+);
+
+CREATE TABLE paper_trades (
+    id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    symbol VARCHAR NOT NULL,
+    side VARCHAR NOT NULL,
+    shares BIGINT NOT NULL,
+    price_cents BIGINT NOT NULL,
+    executed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX paper_trades_account_idx ON paper_trades (guild_id, user_id, executed_at);
@@ -0,0 +1,19 @@
+-- Scheduled market summary posts: at most one open and one close summary
+-- per guild, posted to a channel at a local time on the chosen weekdays.
+-- Weekdays are a bitmask with Monday as bit 0.
+
+CREATE TABLE scheduled_posts (
+    id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    channel_id BIGINT NOT NULL,
+    kind VARCHAR NOT NULL,
+    post_time TIME NOT NULL,
+    timezone VARCHAR NOT NULL,
+    weekdays SMALLINT NOT NULL DEFAULT 31,
+    skip_holidays BOOLEAN NOT NULL DEFAULT TRUE,
+    symbols TEXT NOT NULL,
+    created_by BIGINT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_posted_at TIMESTAMP,
+    UNIQUE (guild_id, kind)
+);
@@ -1,44 +1,175 @@
-use serde::Deserialize;
+use self::client::{random_slip, search_slips, slip_by_id, AdviceError, Slip, Source};
+use poise::serenity_prelude::CreateAllowedMentions;
+use poise::CreateReply;
 
-#[derive(Deserialize)]
-struct Slip {
-    id: i32,
-    advice: String,
+pub mod client;
+
+/// Most search results listed in one reply.
+pub(crate) const SEARCH_LIMIT: usize = 5;
+/// Longest search term accepted.
+pub(crate) const MAX_TERM_LEN: usize = 50;
+
+/// Format a slip as `advice - #id`, noting when it came from the cache.
+fn format_slip(slip: &Slip, source: Source) -> String {
+    match source {
+        Source::Api => format!("{} - #{}", slip.advice, slip.id),
+        Source::Cache => format!("{} - #{} (from cache)", slip.advice, slip.id),
+    }
+}
+
+/// List search results, at most [`SEARCH_LIMIT`] of them.
+fn format_results(term: &str, slips: &[Slip], source: Source) -> String {
+    let mut lines: Vec<String> = slips
+        .iter()
+        .take(SEARCH_LIMIT)
+        .map(|slip| format!("#{}: {}", slip.id, slip.advice))
+        .collect();
+    if slips.len() > SEARCH_LIMIT {
+        lines.push(format!("…and {} more", slips.len() - SEARCH_LIMIT));
+    }
+    let heading = match source {
+        Source::Api => format!("Advice about '{}':", term),
+        Source::Cache => format!("Advice about '{}' (from cache):", term),
+    };
+    format!("{}\n{}", heading, lines.join("\n"))
+}
+
+/// A friendly reply for a failed lookup.
+fn describe_error(e: &AdviceError) -> String {
+    match e {
+        AdviceError::NotFound => "No advice found.".to_string(),
+        e if e.is_transient() => {
+            "The advice service is unavailable right now, try again later.".to_string()
+        }
+        _ => "The advice service sent something I couldn't read.".to_string(),
+    }
+}
+
+async fn reply(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    content: String,
+) -> Result<(), crate::Error> {
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
 }
 
-#[derive(Deserialize)]
-struct Advice {
-    slip: Slip,
+async fn random_advice(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<(), crate::Error> {
+    let data = ctx.data();
+    let content = match random_slip(&data.advice_client, &data.advice_cache).await {
+        Ok((slip, source)) => format_slip(&slip, source),
+        Err(e) => {
+            tracing::warn!("Advice lookup failed: {}", e);
+            describe_error(&e)
+        }
+    };
+    reply(ctx, content).await
 }
 
 /// Get a random piece of advice.
 /// Usage: /advice
-#[poise::command(slash_command, prefix_command)]
+#[poise::command(slash_command, prefix_command, subcommands("random", "search", "id"))]
 pub async fn advice(
     ctx: poise::Context<'_, crate::Data, crate::Error>,
 ) -> Result<(), crate::Error> {
-    const ENDPOINT: &str = "https://api.adviceslip.com/advice";
-    let advice = reqwest::get(ENDPOINT).await?.json::<Advice>().await?;
-    let results = format!("{} - #{}", advice.slip.advice, advice.slip.id);
-    ctx.say(results).await?;
-    Ok(())
+    random_advice(ctx).await
+}
+
+/// Get a random piece of advice.
+/// Usage: /advice random
+#[poise::command(slash_command, prefix_command)]
+pub async fn random(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<(), crate::Error> {
+    random_advice(ctx).await
+}
+
+/// Find advice containing a word or phrase.
+/// Usage: /advice search water
+#[poise::command(slash_command, prefix_command)]
+pub async fn search(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[rest] term: String,
+) -> Result<(), crate::Error> {
+    let term = term.trim();
+    if term.is_empty() || term.chars().count() > MAX_TERM_LEN {
+        ctx.say(format!(
+            "Search terms must be between 1 and {} characters.",
+            MAX_TERM_LEN
+        ))
+        .await?;
+        return Ok(());
+    }
+    let data = ctx.data();
+    let content = match search_slips(&data.advice_client, &data.advice_cache, term).await {
+        Ok((slips, source)) => format_results(term, &slips, source),
+        Err(e) => {
+            tracing::warn!("Advice search for '{}' failed: {}", term, e);
+            describe_error(&e)
+        }
+    };
+    reply(ctx, content).await
+}
+
+/// Get a specific piece of advice by its number.
+/// Usage: /advice id 42
+#[poise::command(slash_command, prefix_command)]
+pub async fn id(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[min = 1] id: i32,
+) -> Result<(), crate::Error> {
+    let data = ctx.data();
+    let content = match slip_by_id(&data.advice_client, &data.advice_cache, id).await {
+        Ok((slip, source)) => format_slip(&slip, source),
+        Err(AdviceError::NotFound) => format!("There's no advice #{}.", id),
+        Err(e) => {
+            tracing::warn!("Advice lookup for #{} failed: {}", id, e);
+            describe_error(&e)
+        }
+    };
+    reply(ctx, content).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
+
+    fn slip(id: i32, advice: &str) -> Slip {
+        Slip {
+            id,
+            advice: advice.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_slip() {
+        let slip = slip(123, "Test advice.");
+        assert_eq!(format_slip(&slip, Source::Api), "Test advice. - #123");
+        assert_eq!(
+            format_slip(&slip, Source::Cache),
+            "Test advice. - #123 (from cache)"
+        );
+    }
+
+    #[test]
+    fn test_format_results_truncates() {
+        let slips: Vec<Slip> = (1..=7).map(|id| slip(id, "Drink water.")).collect();
+        let out = format_results("water", &slips, Source::Api);
+        assert!(out.starts_with("Advice about 'water':\n#1: Drink water."));
+        assert!(out.ends_with("…and 2 more"));
+        assert_eq!(out.lines().count(), SEARCH_LIMIT + 2);
+    }
 
     #[test]
-    fn test_advice_deserialize() {
-        let data = json!({
-            "slip": {
-                "id": 123,
-                "advice": "Test advice."
-            }
-        });
-        let advice: Advice = serde_json::from_value(data).unwrap();
-        assert_eq!(advice.slip.id, 123);
-        assert_eq!(advice.slip.advice, "Test advice.");
+    fn test_describe_error() {
+        assert_eq!(describe_error(&AdviceError::NotFound), "No advice found.");
+        assert!(describe_error(&AdviceError::Status(503)).contains("unavailable"));
+        assert!(describe_error(&AdviceError::BadPayload("x".to_string())).contains("couldn't read"));
     }
 }
@@ -0,0 +1,581 @@
+//! Access to the Advice Slip API (<https://api.adviceslip.com>).
+//!
+//! [`AdviceClient`] abstracts the three lookups the bot needs so commands can
+//! be exercised against [`FakeAdviceClient`] in tests. [`HttpAdviceClient`]
+//! talks to the real API with a timeout and retries transient failures with
+//! exponential backoff. [`AdviceCache`] remembers every slip seen so answers
+//! can still be given while the API is down.
+
+use rand::seq::IteratorRandom;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Used when `ADVICE_API_URL` is not set.
+pub const DEFAULT_BASE_URL: &str = "https://api.adviceslip.com";
+/// Used when `ADVICE_TIMEOUT_SECS` is not set.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Used when `ADVICE_RETRIES` is not set.
+pub const DEFAULT_RETRIES: u32 = 2;
+/// Delay before the first retry; each further retry waits twice as long.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(250);
+/// Most slips kept by [`AdviceCache`].
+pub const MAX_CACHED_SLIPS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Slip {
+    pub id: i32,
+    pub advice: String,
+}
+
+/// Error returned by an [`AdviceClient`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdviceError {
+    /// The request failed or timed out before a response arrived.
+    Unavailable(String),
+    /// The API answered with an unexpected HTTP status.
+    Status(u16),
+    /// The API had no slip matching the request.
+    NotFound,
+    /// The response couldn't be understood.
+    BadPayload(String),
+}
+
+impl AdviceError {
+    /// Whether trying again might succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AdviceError::Unavailable(_) => true,
+            AdviceError::Status(status) => *status == 429 || *status >= 500,
+            AdviceError::NotFound | AdviceError::BadPayload(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for AdviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdviceError::Unavailable(reason) => {
+                write!(f, "The advice service is unavailable ({})", reason)
+            }
+            AdviceError::Status(status) => {
+                write!(f, "The advice service returned HTTP {}", status)
+            }
+            AdviceError::NotFound => write!(f, "No advice found"),
+            AdviceError::BadPayload(reason) => {
+                write!(
+                    f,
+                    "The advice service sent an unexpected reply ({})",
+                    reason
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AdviceError {}
+
+/// The lookups the bot makes against an advice source.
+pub trait AdviceClient {
+    /// A random slip.
+    fn random(&self) -> impl Future<Output = Result<Slip, AdviceError>> + Send;
+    /// The slip with the given id.
+    fn by_id(&self, id: i32) -> impl Future<Output = Result<Slip, AdviceError>> + Send;
+    /// Every slip containing `term`. No matches is [`AdviceError::NotFound`].
+    fn search(&self, term: &str) -> impl Future<Output = Result<Vec<Slip>, AdviceError>> + Send;
+}
+
+#[derive(Deserialize)]
+struct SlipResponse {
+    slip: Slip,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    slips: Vec<Slip>,
+}
+
+/// The API reports "not found" as a 200 with a `message` object.
+#[derive(Deserialize)]
+struct MessageResponse {
+    message: ApiMessage,
+}
+
+#[derive(Deserialize)]
+struct ApiMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    text: String,
+}
+
+/// Decode an API body, mapping the API's notice messages to errors.
+fn decode<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, AdviceError> {
+    if let Ok(response) = serde_json::from_str::<MessageResponse>(body) {
+        return Err(match response.message.kind.as_str() {
+            "notice" | "error" if response.message.text.contains("found") => AdviceError::NotFound,
+            _ => AdviceError::BadPayload(response.message.text),
+        });
+    }
+    serde_json::from_str(body).map_err(|e| AdviceError::BadPayload(e.to_string()))
+}
+
+/// Settings for [`HttpAdviceClient`].
+#[derive(Debug, Clone)]
+pub struct AdviceConfig {
+    pub base_url: String,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl Default for AdviceConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+        }
+    }
+}
+
+impl AdviceConfig {
+    /// Read `ADVICE_API_URL`, `ADVICE_TIMEOUT_SECS` and `ADVICE_RETRIES`,
+    /// falling back to the defaults for anything unset or invalid.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            base_url: std::env::var("ADVICE_API_URL").unwrap_or(defaults.base_url),
+            timeout: std::env::var("ADVICE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.timeout),
+            retries: std::env::var("ADVICE_RETRIES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.retries),
+            backoff: defaults.backoff,
+        }
+    }
+}
+
+/// How long to wait before retry number `attempt` (starting at 0).
+pub(crate) fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt))
+}
+
+/// [`AdviceClient`] backed by the Advice Slip HTTP API.
+#[derive(Debug, Clone)]
+pub struct HttpAdviceClient {
+    http: reqwest::Client,
+    config: AdviceConfig,
+}
+
+impl HttpAdviceClient {
+    pub fn new(config: AdviceConfig) -> Result<Self, AdviceError> {
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| AdviceError::Unavailable(e.to_string()))?;
+        Ok(Self { http, config })
+    }
+
+    async fn get_once(&self, url: &str) -> Result<String, AdviceError> {
+        let response = crate::telemetry::send(self.http.get(url))
+            .await
+            .map_err(|e| AdviceError::Unavailable(e.to_string()))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(AdviceError::NotFound);
+        }
+        if !status.is_success() {
+            return Err(AdviceError::Status(status.as_u16()));
+        }
+        // The API labels its JSON as text/html, so parse the body ourselves.
+        response
+            .text()
+            .await
+            .map_err(|e| AdviceError::Unavailable(e.to_string()))
+    }
+
+    /// Fetch and decode `path`, retrying transient failures.
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, AdviceError> {
+        let url = format!("{}{}", self.config.base_url.trim_end_matches('/'), path);
+        let mut attempt = 0;
+        loop {
+            match self.get_once(&url).await.and_then(|body| decode(&body)) {
+                Err(e) if e.is_transient() && attempt < self.config.retries => {
+                    tokio::time::sleep(backoff_delay(self.config.backoff, attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl AdviceClient for HttpAdviceClient {
+    async fn random(&self) -> Result<Slip, AdviceError> {
+        self.get::<SlipResponse>("/advice").await.map(|r| r.slip)
+    }
+
+    async fn by_id(&self, id: i32) -> Result<Slip, AdviceError> {
+        self.get::<SlipResponse>(&format!("/advice/{}", id))
+            .await
+            .map(|r| r.slip)
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<Slip>, AdviceError> {
+        let path = format!("/advice/search/{}", urlencode(term));
+        let slips = self.get::<SearchResponse>(&path).await?.slips;
+        if slips.is_empty() {
+            return Err(AdviceError::NotFound);
+        }
+        Ok(slips)
+    }
+}
+
+/// Percent-encode a path segment.
+fn urlencode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// In-memory [`AdviceClient`] for tests. When `available` is false every call
+/// fails as if the API were down.
+#[derive(Debug, Default)]
+pub struct FakeAdviceClient {
+    pub slips: Vec<Slip>,
+    pub available: bool,
+}
+
+impl FakeAdviceClient {
+    pub fn new(slips: Vec<Slip>) -> Self {
+        Self {
+            slips,
+            available: true,
+        }
+    }
+
+    fn check(&self) -> Result<(), AdviceError> {
+        if self.available {
+            Ok(())
+        } else {
+            Err(AdviceError::Unavailable("fake is offline".to_string()))
+        }
+    }
+}
+
+impl AdviceClient for FakeAdviceClient {
+    async fn random(&self) -> Result<Slip, AdviceError> {
+        self.check()?;
+        self.slips
+            .iter()
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .ok_or(AdviceError::NotFound)
+    }
+
+    async fn by_id(&self, id: i32) -> Result<Slip, AdviceError> {
+        self.check()?;
+        self.slips
+            .iter()
+            .find(|slip| slip.id == id)
+            .cloned()
+            .ok_or(AdviceError::NotFound)
+    }
+
+    async fn search(&self, term: &str) -> Result<Vec<Slip>, AdviceError> {
+        self.check()?;
+        let found = matching(self.slips.iter(), term);
+        if found.is_empty() {
+            return Err(AdviceError::NotFound);
+        }
+        Ok(found)
+    }
+}
+
+/// Slips whose text contains `term`, ignoring case, ordered by id.
+fn matching<'a>(slips: impl Iterator<Item = &'a Slip>, term: &str) -> Vec<Slip> {
+    let term = term.to_lowercase();
+    let mut found: Vec<Slip> = slips
+        .filter(|slip| slip.advice.to_lowercase().contains(&term))
+        .cloned()
+        .collect();
+    found.sort_by_key(|slip| slip.id);
+    found
+}
+
+/// Every slip the bot has seen, keyed by id.
+#[derive(Debug, Default)]
+pub struct AdviceCache {
+    slips: Mutex<HashMap<i32, String>>,
+}
+
+impl AdviceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember `slips`. Once full, new slips are only kept if they replace
+    /// an existing entry.
+    pub fn remember<'a>(&self, slips: impl IntoIterator<Item = &'a Slip>) {
+        let mut cached = self.slips.lock().unwrap();
+        for slip in slips {
+            if cached.len() < MAX_CACHED_SLIPS || cached.contains_key(&slip.id) {
+                cached.insert(slip.id, slip.advice.clone());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slips.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn random(&self) -> Option<Slip> {
+        self.slips
+            .lock()
+            .unwrap()
+            .iter()
+            .choose(&mut rand::thread_rng())
+            .map(|(id, advice)| Slip {
+                id: *id,
+                advice: advice.clone(),
+            })
+    }
+
+    pub fn by_id(&self, id: i32) -> Option<Slip> {
+        self.slips.lock().unwrap().get(&id).map(|advice| Slip {
+            id,
+            advice: advice.clone(),
+        })
+    }
+
+    pub fn search(&self, term: &str) -> Vec<Slip> {
+        let cached = self.slips.lock().unwrap();
+        let slips: Vec<Slip> = cached
+            .iter()
+            .map(|(id, advice)| Slip {
+                id: *id,
+                advice: advice.clone(),
+            })
+            .collect();
+        matching(slips.iter(), term)
+    }
+}
+
+/// Where an answer came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Api,
+    Cache,
+}
+
+/// Call `fetch`, caching what it returns. If the API can't be reached, answer
+/// from `cached` instead; other errors are returned as they are.
+async fn with_fallback<T, F, C>(
+    cache: &AdviceCache,
+    fetch: F,
+    slips_of: fn(&T) -> Vec<&Slip>,
+    cached: C,
+) -> Result<(T, Source), AdviceError>
+where
+    F: Future<Output = Result<T, AdviceError>>,
+    C: FnOnce(&AdviceCache) -> Option<T>,
+{
+    match fetch.await {
+        Ok(value) => {
+            cache.remember(slips_of(&value));
+            Ok((value, Source::Api))
+        }
+        Err(e) if e.is_transient() => cached(cache).map(|v| (v, Source::Cache)).ok_or(e),
+        Err(e) => Err(e),
+    }
+}
+
+/// A random slip, falling back to a cached one if the API is down.
+pub async fn random_slip<C: AdviceClient>(
+    client: &C,
+    cache: &AdviceCache,
+) -> Result<(Slip, Source), AdviceError> {
+    with_fallback(cache, client.random(), |s| vec![s], AdviceCache::random).await
+}
+
+/// The slip with `id`, falling back to the cache if the API is down.
+pub async fn slip_by_id<C: AdviceClient>(
+    client: &C,
+    cache: &AdviceCache,
+    id: i32,
+) -> Result<(Slip, Source), AdviceError> {
+    with_fallback(cache, client.by_id(id), |s| vec![s], |c| c.by_id(id)).await
+}
+
+/// Slips matching `term`, falling back to the cache if the API is down.
+pub async fn search_slips<C: AdviceClient>(
+    client: &C,
+    cache: &AdviceCache,
+    term: &str,
+) -> Result<(Vec<Slip>, Source), AdviceError> {
+    with_fallback(
+        cache,
+        client.search(term),
+        |slips| slips.iter().collect(),
+        |c| Some(c.search(term)).filter(|found| !found.is_empty()),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slip(id: i32, advice: &str) -> Slip {
+        Slip {
+            id,
+            advice: advice.to_string(),
+        }
+    }
+
+    fn fake() -> FakeAdviceClient {
+        FakeAdviceClient::new(vec![
+            slip(1, "Drink more water."),
+            slip(2, "Never trust a water cooler rumour."),
+            slip(3, "Call your mother."),
+        ])
+    }
+
+    #[test]
+    fn test_advice_deserialize() {
+        let data = serde_json::json!({
+            "slip": {
+                "id": 123,
+                "advice": "Test advice."
+            }
+        });
+        let advice: SlipResponse = decode(&data.to_string()).unwrap();
+        assert_eq!(advice.slip.id, 123);
+        assert_eq!(advice.slip.advice, "Test advice.");
+    }
+
+    #[test]
+    fn test_decode_search() {
+        let body = r#"{"total_results": "1", "query": "tea",
+            "slips": [{"id": 7, "advice": "Have some tea.", "date": "2014-06-03"}]}"#;
+        let response: SearchResponse = decode(body).unwrap();
+        assert_eq!(response.slips, vec![slip(7, "Have some tea.")]);
+    }
+
+    #[test]
+    fn test_decode_messages() {
+        let body = r#"{"message": {"type": "notice",
+            "text": "No advice slips found matching that search term."}}"#;
+        assert_eq!(
+            decode::<SearchResponse>(body).err(),
+            Some(AdviceError::NotFound)
+        );
+        let body = r#"{"message": {"type": "error", "text": "Something broke."}}"#;
+        assert_eq!(
+            decode::<SlipResponse>(body).err(),
+            Some(AdviceError::BadPayload("Something broke.".to_string()))
+        );
+        assert!(matches!(
+            decode::<SlipResponse>("<html>"),
+            Err(AdviceError::BadPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(AdviceError::Unavailable("timeout".to_string()).is_transient());
+        assert!(AdviceError::Status(503).is_transient());
+        assert!(AdviceError::Status(429).is_transient());
+        assert!(!AdviceError::Status(400).is_transient());
+        assert!(!AdviceError::NotFound.is_transient());
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let base = Duration::from_millis(100);
+        assert_eq!(backoff_delay(base, 0), Duration::from_millis(100));
+        assert_eq!(backoff_delay(base, 1), Duration::from_millis(200));
+        assert_eq!(backoff_delay(base, 3), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn test_urlencode() {
+        assert_eq!(urlencode("water cooler"), "water%20cooler");
+        assert_eq!(urlencode("a/b?c"), "a%2Fb%3Fc");
+    }
+
+    #[tokio::test]
+    async fn test_results_are_cached() {
+        let client = fake();
+        let cache = AdviceCache::new();
+        let (found, source) = search_slips(&client, &cache, "WATER").await.unwrap();
+        assert_eq!(source, Source::Api);
+        assert_eq!(found.iter().map(|s| s.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(cache.len(), 2);
+        let (slip, _) = slip_by_id(&client, &cache, 3).await.unwrap();
+        assert_eq!(slip.advice, "Call your mother.");
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_cache_when_unavailable() {
+        let mut client = fake();
+        let cache = AdviceCache::new();
+        slip_by_id(&client, &cache, 2).await.unwrap();
+        client.available = false;
+
+        let (slip, source) = slip_by_id(&client, &cache, 2).await.unwrap();
+        assert_eq!((slip.id, source), (2, Source::Cache));
+        let (slip, source) = random_slip(&client, &cache).await.unwrap();
+        assert_eq!((slip.id, source), (2, Source::Cache));
+        let (found, _) = search_slips(&client, &cache, "rumour").await.unwrap();
+        assert_eq!(found.len(), 1);
+
+        assert!(matches!(
+            slip_by_id(&client, &cache, 1).await,
+            Err(AdviceError::Unavailable(_))
+        ));
+        assert!(search_slips(&client, &cache, "mother").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_not_found_skips_cache() {
+        let client = fake();
+        let cache = AdviceCache::new();
+        cache.remember(&[slip(99, "Cached only.")]);
+        assert_eq!(
+            slip_by_id(&client, &cache, 99).await,
+            Err(AdviceError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let cache = AdviceCache::new();
+        let slips: Vec<Slip> = (0..MAX_CACHED_SLIPS as i32 + 10)
+            .map(|id| slip(id, "advice"))
+            .collect();
+        cache.remember(&slips);
+        assert_eq!(cache.len(), MAX_CACHED_SLIPS);
+        cache.remember(&[slip(0, "updated")]);
+        assert_eq!(cache.by_id(0).unwrap().advice, "updated");
+    }
+}
@@ -1,34 +1,235 @@
-use crate::utils::random_choice;
+use crate::commands::pool::{load_responses, pick_response, PoolKind};
+use crate::commands::require_guild;
+use crate::schema::ball_settings;
+use crate::utils::random::{fnv1a, seeded_choice};
+use chrono::{NaiveDate, Utc};
+use diesel::prelude::*;
+use poise::serenity_prelude::{Colour, CreateEmbed};
+use poise::CreateReply;
+use tracing::warn;
 
-pub(crate) static RESPONSES: [&str; 20] = [
+/// Longest question echoed back in the answer embed.
+pub(crate) const MAX_QUESTION_LEN: usize = 256;
+
+/// The built-in answers that lean towards yes.
+pub(crate) const POSITIVE: [&str; 10] = [
     "As I see it, yes.",
-    "Ask again later.",
-    "Better not tell you now.",
-    "Cannot predict now.",
-    "Concentrate and ask again.",
-    "Don't count on it.",
     "It is certain.",
     "It is decidedly so.",
     "Most likely.",
-    "My reply is no.",
-    "My sources say no.",
-    "Outlook not so good.",
     "Outlook good.",
-    "Reply hazy, try again.",
     "Signs point to yes.",
-    "Very doubtful.",
     "Without a doubt.",
     "Yes.",
     "Yes – definitely.",
     "You may rely on it.",
 ];
 
+/// The built-in answers that dodge the question.
+pub(crate) const NEUTRAL: [&str; 5] = [
+    "Ask again later.",
+    "Better not tell you now.",
+    "Cannot predict now.",
+    "Concentrate and ask again.",
+    "Reply hazy, try again.",
+];
+
+/// The built-in answers that lean towards no.
+pub(crate) const NEGATIVE: [&str; 5] = [
+    "Don't count on it.",
+    "My reply is no.",
+    "My sources say no.",
+    "Outlook not so good.",
+    "Very doubtful.",
+];
+
+const RESPONSE_COUNT: usize = POSITIVE.len() + NEUTRAL.len() + NEGATIVE.len();
+
+/// Every built-in answer, built from the three pools above.
+pub(crate) static RESPONSES: [&str; RESPONSE_COUNT] = {
+    let mut all = [""; RESPONSE_COUNT];
+    let mut i = 0;
+    while i < RESPONSE_COUNT {
+        all[i] = if i < POSITIVE.len() {
+            POSITIVE[i]
+        } else if i < POSITIVE.len() + NEUTRAL.len() {
+            NEUTRAL[i - POSITIVE.len()]
+        } else {
+            NEGATIVE[i - POSITIVE.len() - NEUTRAL.len()]
+        };
+        i += 1;
+    }
+    all
+};
+
+/// Which way an answer leans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sentiment {
+    Positive,
+    Neutral,
+    Negative,
+}
+
+impl Sentiment {
+    /// The category of a built-in answer. Custom answers have none.
+    pub fn of(answer: &str) -> Option<Self> {
+        if POSITIVE.contains(&answer) {
+            Some(Sentiment::Positive)
+        } else if NEUTRAL.contains(&answer) {
+            Some(Sentiment::Neutral)
+        } else if NEGATIVE.contains(&answer) {
+            Some(Sentiment::Negative)
+        } else {
+            None
+        }
+    }
+
+    pub fn colour(self) -> Colour {
+        match self {
+            Sentiment::Positive => Colour::DARK_GREEN,
+            Sentiment::Neutral => Colour::GOLD,
+            Sentiment::Negative => Colour::RED,
+        }
+    }
+}
+
+/// How /ball picks its answer in a guild.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum BallMode {
+    /// A fresh answer every time.
+    #[name = "random"]
+    Random,
+    /// The same question gets the same answer for the rest of the day.
+    #[name = "consistent"]
+    Consistent,
+}
+
+/// Lowercase a question and collapse whitespace and trailing punctuation,
+/// so trivially different phrasings get the same consistent answer.
+pub(crate) fn normalize_question(question: &str) -> String {
+    question
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['?', '!', '.'])
+        .trim_end()
+        .to_lowercase()
+}
+
+/// Seed for a consistent answer: stable for a guild, question and day.
+pub(crate) fn daily_seed(guild: i64, question: &str, day: NaiveDate) -> u64 {
+    let key = format!("{}\n{}\n{}", guild, day, normalize_question(question));
+    fnv1a(key.as_bytes())
+}
+
+fn consistent_mode(conn: &mut PgConnection, guild: i64) -> QueryResult<bool> {
+    Ok(ball_settings::table
+        .filter(ball_settings::guild_id.eq(guild))
+        .select(ball_settings::consistent)
+        .first::<bool>(conn)
+        .optional()?
+        .unwrap_or(false))
+}
+
+/// Today's answer to `question`, if the guild has consistent mode on.
+fn consistent_answer(
+    conn: &mut PgConnection,
+    guild: i64,
+    question: &str,
+) -> QueryResult<Option<String>> {
+    if !consistent_mode(conn, guild)? {
+        return Ok(None);
+    }
+    let (items, _) = load_responses(conn, guild, PoolKind::Ball)?;
+    let seed = daily_seed(guild, question, Utc::now().date_naive());
+    Ok(seeded_choice(&items, seed).cloned())
+}
+
+/// Truncate a question to fit in the answer embed.
+fn clip_question(question: &str) -> String {
+    if question.chars().count() <= MAX_QUESTION_LEN {
+        return question.to_string();
+    }
+    let mut clipped: String = question.chars().take(MAX_QUESTION_LEN - 1).collect();
+    clipped.push('…');
+    clipped
+}
+
 /// Ask the magic 8-ball a question.
 /// Usage: /ball Will I win the lottery?
 #[poise::command(slash_command, prefix_command)]
-pub async fn ball(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
-    let choice = random_choice(&RESPONSES).unwrap();
-    ctx.say(*choice).await?;
+pub async fn ball(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "What do you want to know?"]
+    #[rest]
+    question: Option<String>,
+) -> Result<(), crate::Error> {
+    let question = question.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    let consistent = match (ctx.guild_id(), question) {
+        (Some(guild), Some(question)) => ctx
+            .data()
+            .db_pool
+            .get()
+            .map_err(crate::Error::from)
+            .and_then(|mut conn| {
+                consistent_answer(&mut conn, guild.get() as i64, question)
+                    .map_err(crate::Error::from)
+            })
+            .unwrap_or_else(|e| {
+                warn!("Couldn't check consistent /ball mode: {}", e);
+                None
+            }),
+        _ => None,
+    };
+    let answer = consistent.unwrap_or_else(|| pick_response(&ctx, PoolKind::Ball));
+
+    let mut embed = CreateEmbed::new().title("Magic 8-ball");
+    if let Some(question) = question {
+        embed = embed.field("Question", clip_question(question), false);
+    }
+    embed = embed.field("Answer", answer.as_str(), false);
+    if let Some(sentiment) = Sentiment::of(&answer) {
+        embed = embed.colour(sentiment.colour());
+    }
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Make /ball answer at random or the same way per question and day.
+/// Usage: /ballmode consistent
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn ballmode(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    mode: BallMode,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let consistent = mode == BallMode::Consistent;
+    let now = Utc::now().naive_utc();
+    let mut conn = ctx.data().db_pool.get()?;
+    diesel::insert_into(ball_settings::table)
+        .values((
+            ball_settings::guild_id.eq(guild),
+            ball_settings::consistent.eq(consistent),
+            ball_settings::updated_at.eq(now),
+        ))
+        .on_conflict(ball_settings::guild_id)
+        .do_update()
+        .set((
+            ball_settings::consistent.eq(consistent),
+            ball_settings::updated_at.eq(now),
+        ))
+        .execute(&mut conn)?;
+    let msg = if consistent {
+        "The 8-ball now gives the same answer to the same question all day."
+    } else {
+        "The 8-ball now answers at random."
+    };
+    ctx.say(msg).await?;
     Ok(())
 }
 
@@ -47,4 +248,58 @@ mod tests {
             .iter()
             .any(|&s| s.contains("yes") || s.contains("Yes")));
     }
+
+    #[test]
+    fn test_every_response_has_one_sentiment() {
+        assert_eq!(
+            POSITIVE.len() + NEUTRAL.len() + NEGATIVE.len(),
+            RESPONSES.len()
+        );
+        for response in RESPONSES {
+            assert!(Sentiment::of(response).is_some(), "{}", response);
+        }
+        assert_eq!(Sentiment::of("Yes."), Some(Sentiment::Positive));
+        assert_eq!(Sentiment::of("Very doubtful."), Some(Sentiment::Negative));
+        assert_eq!(Sentiment::of("Ask again later."), Some(Sentiment::Neutral));
+        assert_eq!(Sentiment::of("Ask the cat."), None);
+    }
+
+    #[test]
+    fn test_normalize_question() {
+        assert_eq!(
+            normalize_question("  Will I  win the LOTTERY?? "),
+            "will i win the lottery"
+        );
+        assert_eq!(normalize_question("Really?!."), "really");
+    }
+
+    #[test]
+    fn test_daily_seed() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let next = day.succ_opt().unwrap();
+        let seed = daily_seed(1, "Will I win?", day);
+        assert_eq!(seed, daily_seed(1, "will i   win", day));
+        assert_ne!(seed, daily_seed(1, "Will I win?", next));
+        assert_ne!(seed, daily_seed(2, "Will I win?", day));
+        assert_ne!(seed, daily_seed(1, "Will I lose?", day));
+    }
+
+    #[test]
+    fn test_consistent_answer_is_pinned() {
+        // Seeds and picks mustn't change between releases, or every
+        // question asked today gets a new answer after an upgrade.
+        let items: Vec<(&str, u32)> = RESPONSES.iter().map(|&answer| (answer, 1)).collect();
+        let day = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let seed = daily_seed(1, "Will I win?", day);
+        assert_eq!(seeded_choice(&items, seed), Some(&"Don't count on it."));
+    }
+
+    #[test]
+    fn test_clip_question() {
+        assert_eq!(clip_question("short"), "short");
+        let long = "a".repeat(MAX_QUESTION_LEN + 10);
+        let clipped = clip_question(&long);
+        assert_eq!(clipped.chars().count(), MAX_QUESTION_LEN);
+        assert!(clipped.ends_with('…'));
+    }
 }
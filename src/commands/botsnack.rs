@@ -1,4 +1,4 @@
-use crate::utils::random_choice;
+use crate::commands::pool::{pick_response, PoolKind};
 
 pub(crate) static RESPONSES: [&str; 5] = ["Yum!", "*cronch*", "MOAR", "*Smiles*", "Nice."];
 
@@ -8,8 +8,8 @@ pub(crate) static RESPONSES: [&str; 5] = ["Yum!", "*cronch*", "MOAR", "*Smiles*"
 pub async fn botsnack(
     ctx: poise::Context<'_, crate::Data, crate::Error>,
 ) -> Result<(), crate::Error> {
-    let response = random_choice(&RESPONSES).unwrap();
-    ctx.say(*response).await?;
+    let response = pick_response(&ctx, PoolKind::Botsnack);
+    ctx.say(response).await?;
     Ok(())
 }
 
@@ -1,54 +1,298 @@
-use crate::models::{Description, NewDescription};
+use crate::commands::require_guild;
+use crate::models::{Description, DescriptionRevision, NewDescription, NewDescriptionRevision};
+use crate::schema::{description_revisions, descriptions};
+use chrono::Utc;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
-use poise::Context;
+use poise::serenity_prelude::CreateAllowedMentions;
+use poise::{Context, CreateReply};
+use template::{Template, TemplateVars};
+
+pub mod template;
 
 type PgPool = diesel::r2d2::Pool<ConnectionManager<PgConnection>>;
 
+/// Number of keys shown per page of `/keys`.
+pub(crate) const KEYS_PAGE_SIZE: i64 = 20;
+
+/// Longest key accepted by `/set`.
+pub(crate) const MAX_KEY_LEN: usize = 100;
+
+/// Number of revisions shown by `/history`.
+pub(crate) const HISTORY_LIMIT: i64 = 10;
+
+/// Longest value preview shown by `/history`.
+const HISTORY_PREVIEW_LEN: usize = 80;
+
+/// Times an edit is tried when a concurrent edit takes its revision number.
+const SAVE_ATTEMPTS: usize = 3;
+
 fn get_conn(pool: &PgPool) -> PooledConnection<ConnectionManager<PgConnection>> {
     pool.get().expect("Failed to get DB connection from pool")
 }
 
-/// Set a key-value pair in the bot's database.
+/// Keys are case-insensitive and ignore surrounding whitespace.
+pub(crate) fn normalize_key(key: &str) -> String {
+    key.trim().to_lowercase()
+}
+
+/// Escape `LIKE` wildcards so a user-supplied prefix matches literally.
+pub(crate) fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Look up a single entry in a guild's knowledge base.
+pub(crate) fn find_description(
+    conn: &mut PgConnection,
+    guild: i64,
+    key: &str,
+) -> QueryResult<Option<Description>> {
+    descriptions::table
+        .filter(descriptions::guild_id.eq(guild))
+        .filter(descriptions::key.eq(key))
+        .first::<Description>(conn)
+        .optional()
+}
+
+/// Bump an entry's read counter, returning the new count.
+pub(crate) fn record_read(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+    diesel::update(descriptions::table.find(id))
+        .set(descriptions::reads.eq(descriptions::reads + 1))
+        .returning(descriptions::reads)
+        .get_result(conn)
+}
+
+/// Expand the placeholders in a stored value. Values that don't parse as a
+/// template, such as ones saved before templates existed, are shown as-is.
+pub(crate) fn render_value(value: &str, vars: &TemplateVars<'_>) -> String {
+    match Template::parse(value) {
+        Ok(template) => template.render(vars, &mut rand::thread_rng()),
+        Err(_) => value.to_string(),
+    }
+}
+
+/// Insert or overwrite an entry, recording the editor and edit time.
+/// The new value is also appended to the entry's revision history.
+pub(crate) fn upsert_description(
+    conn: &mut PgConnection,
+    guild: i64,
+    user: i64,
+    key: &str,
+    value: &str,
+) -> QueryResult<Description> {
+    let mut attempt = 1;
+    loop {
+        match save_description(conn, guild, user, key, value) {
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) if attempt < SAVE_ATTEMPTS => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// One attempt at [`upsert_description`]. The revision number is the latest
+/// plus one, so two edits racing on a new key can pick the same number; the
+/// loser's transaction fails on the unique index and is retried.
+fn save_description(
+    conn: &mut PgConnection,
+    guild: i64,
+    user: i64,
+    key: &str,
+    value: &str,
+) -> QueryResult<Description> {
+    conn.transaction(|conn| {
+        let now = Utc::now().naive_utc();
+        let new_desc = NewDescription {
+            key,
+            value,
+            guild_id: guild,
+            user_id: user,
+            timestamp: now,
+        };
+        let desc = diesel::insert_into(descriptions::table)
+            .values(&new_desc)
+            .on_conflict((descriptions::guild_id, descriptions::key))
+            .do_update()
+            .set(&new_desc)
+            .get_result::<Description>(conn)?;
+
+        let latest = description_revisions::table
+            .filter(description_revisions::guild_id.eq(guild))
+            .filter(description_revisions::key.eq(key))
+            .select(diesel::dsl::max(description_revisions::rev))
+            .first::<Option<i32>>(conn)?;
+        diesel::insert_into(description_revisions::table)
+            .values(&NewDescriptionRevision {
+                guild_id: guild,
+                key,
+                rev: latest.unwrap_or(0) + 1,
+                value,
+                user_id: user,
+                timestamp: now,
+            })
+            .execute(conn)?;
+        Ok(desc)
+    })
+}
+
+/// Most recent revisions of an entry, newest first.
+pub(crate) fn list_revisions(
+    conn: &mut PgConnection,
+    guild: i64,
+    key: &str,
+    limit: i64,
+) -> QueryResult<Vec<DescriptionRevision>> {
+    description_revisions::table
+        .filter(description_revisions::guild_id.eq(guild))
+        .filter(description_revisions::key.eq(key))
+        .order(description_revisions::rev.desc())
+        .limit(limit)
+        .select(DescriptionRevision::as_select())
+        .load(conn)
+}
+
+/// Look up a single revision of an entry.
+pub(crate) fn find_revision(
+    conn: &mut PgConnection,
+    guild: i64,
+    key: &str,
+    rev: i32,
+) -> QueryResult<Option<DescriptionRevision>> {
+    description_revisions::table
+        .filter(description_revisions::guild_id.eq(guild))
+        .filter(description_revisions::key.eq(key))
+        .filter(description_revisions::rev.eq(rev))
+        .select(DescriptionRevision::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Remove an entry, returning the number of rows deleted.
+pub(crate) fn delete_description(
+    conn: &mut PgConnection,
+    guild: i64,
+    key: &str,
+) -> QueryResult<usize> {
+    diesel::delete(
+        descriptions::table
+            .filter(descriptions::guild_id.eq(guild))
+            .filter(descriptions::key.eq(key)),
+    )
+    .execute(conn)
+}
+
+/// List one page of keys starting with `prefix`, along with the total match count.
+pub(crate) fn list_keys(
+    conn: &mut PgConnection,
+    guild: i64,
+    prefix: &str,
+    page: i64,
+) -> QueryResult<(Vec<String>, i64)> {
+    let pattern = format!("{}%", escape_like(prefix));
+    let total = descriptions::table
+        .filter(descriptions::guild_id.eq(guild))
+        .filter(descriptions::key.like(&pattern).escape('\\'))
+        .count()
+        .get_result::<i64>(conn)?;
+    let keys = descriptions::table
+        .filter(descriptions::guild_id.eq(guild))
+        .filter(descriptions::key.like(&pattern).escape('\\'))
+        .order(descriptions::key.asc())
+        .select(descriptions::key)
+        .limit(KEYS_PAGE_SIZE)
+        .offset((page - 1) * KEYS_PAGE_SIZE)
+        .load::<String>(conn)?;
+    Ok((keys, total))
+}
+
+/// Number of pages needed to show `total` keys.
+pub(crate) fn page_count(total: i64) -> i64 {
+    ((total + KEYS_PAGE_SIZE - 1) / KEYS_PAGE_SIZE).max(1)
+}
+
+/// Shorten a value to `max` characters for list output.
+pub(crate) fn preview(value: &str, max: usize) -> String {
+    let single_line = value.replace('\n', " ");
+    if single_line.chars().count() <= max {
+        return single_line;
+    }
+    let mut shortened: String = single_line.chars().take(max.saturating_sub(1)).collect();
+    shortened.push('…');
+    shortened
+}
+
+/// Set a key-value pair in this server's knowledge base.
 /// Usage: /set foo bar
-#[poise::command(slash_command, prefix_command)]
+#[poise::command(slash_command, prefix_command, guild_only)]
 pub async fn set(
     ctx: Context<'_, crate::Data, crate::Error>,
     key: String,
-    value: String,
+    #[rest] value: String,
 ) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let key = normalize_key(&key);
+    if key.is_empty() || key.chars().count() > MAX_KEY_LEN {
+        ctx.say(format!(
+            "Keys must be between 1 and {} characters.",
+            MAX_KEY_LEN
+        ))
+        .await?;
+        return Ok(());
+    }
+    if let Err(e) = Template::parse(&value) {
+        ctx.say(format!("Invalid template: {}", e)).await?;
+        return Ok(());
+    }
     let pool = &ctx.data().db_pool;
     let mut conn = get_conn(pool);
-    let new_desc = NewDescription {
-        key: &key,
-        value: &value,
-    };
-    diesel::insert_into(crate::schema::descriptions::table)
-        .values(&new_desc)
-        .on_conflict(crate::schema::descriptions::key)
-        .do_update()
-        .set(&new_desc)
-        .execute(&mut conn)?;
+    upsert_description(&mut conn, guild, ctx.author().id.get() as i64, &key, &value)?;
     ctx.say(format!("Set {} = {}", key, value)).await?;
     Ok(())
 }
 
-/// Get the value for a key from the bot's database.
-/// Usage: /get foo
-#[poise::command(slash_command, prefix_command)]
+/// Get the value for a key from this server's knowledge base.
+/// Usage: /get foo [arg]
+///
+/// Any extra text is substituted for `{arg}` in the stored value.
+#[poise::command(slash_command, prefix_command, guild_only)]
 pub async fn get(
     ctx: Context<'_, crate::Data, crate::Error>,
     key: String,
+    #[description = "Text substituted for {arg}"]
+    #[rest]
+    arg: Option<String>,
 ) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let key = normalize_key(&key);
     let pool = &ctx.data().db_pool;
     let mut conn = get_conn(pool);
-    let result = crate::schema::descriptions::table
-        .filter(crate::schema::descriptions::key.eq(&key))
-        .first::<Description>(&mut conn)
-        .optional()?;
-    match result {
+    match find_description(&mut conn, guild, &key)? {
         Some(desc) => {
-            ctx.say(format!("{} = {}", desc.key, desc.value)).await?;
+            let count = record_read(&mut conn, desc.id)?;
+            let user = format!("<@{}>", ctx.author().id);
+            let channel = format!("<#{}>", ctx.channel_id());
+            let vars = TemplateVars {
+                user: &user,
+                channel: &channel,
+                count,
+                arg: arg.as_deref().unwrap_or_default(),
+            };
+            let value = render_value(&desc.value, &vars);
+            ctx.send(
+                CreateReply::default()
+                    .content(format!("{} = {}", desc.key, value))
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
         }
         None => {
             ctx.say(format!("No value found for key '{}'.", key))
@@ -58,27 +302,213 @@ pub async fn get(
     Ok(())
 }
 
+/// Remove a key from this server's knowledge base.
+/// Usage: /unset foo
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn unset(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    key: String,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let key = normalize_key(&key);
+    let pool = &ctx.data().db_pool;
+    let mut conn = get_conn(pool);
+    if delete_description(&mut conn, guild, &key)? > 0 {
+        ctx.say(format!("Removed '{}'.", key)).await?;
+    } else {
+        ctx.say(format!("No value found for key '{}'.", key))
+            .await?;
+    }
+    Ok(())
+}
+
+/// List the keys stored in this server's knowledge base.
+/// Usage: /keys [prefix] [page]
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn keys(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    #[description = "Only show keys starting with this"] prefix: Option<String>,
+    #[description = "Page number"]
+    #[min = 1]
+    page: Option<i64>,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let prefix = prefix.map(|p| normalize_key(&p)).unwrap_or_default();
+    let page = page.unwrap_or(1).max(1);
+    let pool = &ctx.data().db_pool;
+    let mut conn = get_conn(pool);
+    let (keys, total) = list_keys(&mut conn, guild, &prefix, page)?;
+    if keys.is_empty() {
+        if total == 0 {
+            ctx.say("No keys found.").await?;
+        } else {
+            ctx.say(format!(
+                "Page {} is empty; there are {} page(s).",
+                page,
+                page_count(total)
+            ))
+            .await?;
+        }
+        return Ok(());
+    }
+    ctx.say(format!(
+        "Keys ({} total, page {}/{}): {}",
+        total,
+        page,
+        page_count(total),
+        keys.join(", ")
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Show the edit history of a key.
+/// Usage: /history foo
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn history(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    key: String,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let key = normalize_key(&key);
+    let pool = &ctx.data().db_pool;
+    let mut conn = get_conn(pool);
+    let revisions = list_revisions(&mut conn, guild, &key, HISTORY_LIMIT)?;
+    if revisions.is_empty() {
+        ctx.say(format!("No history found for key '{}'.", key))
+            .await?;
+        return Ok(());
+    }
+    let mut msg = format!("History for '{}':\n", key);
+    for revision in revisions {
+        msg.push_str(&format!(
+            "#{} by <@{}> at {}: {}\n",
+            revision.rev,
+            revision.user_id,
+            revision.timestamp.format("%Y-%m-%d %H:%M UTC"),
+            preview(&revision.value, HISTORY_PREVIEW_LEN)
+        ));
+    }
+    // Authors are listed as mentions for readability, but shouldn't be pinged.
+    ctx.send(
+        CreateReply::default()
+            .content(msg)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Restore a key to an earlier revision (moderators only).
+/// Usage: /revert foo 2
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_MESSAGES"
+)]
+pub async fn revert(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    key: String,
+    #[description = "Revision number from /history"]
+    #[min = 1]
+    rev: i32,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let key = normalize_key(&key);
+    let pool = &ctx.data().db_pool;
+    let mut conn = get_conn(pool);
+    let Some(revision) = find_revision(&mut conn, guild, &key, rev)? else {
+        ctx.say(format!("Key '{}' has no revision #{}.", key, rev))
+            .await?;
+        return Ok(());
+    };
+    upsert_description(
+        &mut conn,
+        guild,
+        ctx.author().id.get() as i64,
+        &key,
+        &revision.value,
+    )?;
+    ctx.say(format!("Reverted {} to revision #{}.", key, rev))
+        .await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::models::{Description, NewDescription};
+    use super::*;
 
     #[test]
     fn test_new_description_fields() {
+        let now = Utc::now().naive_utc();
         let new_desc = NewDescription {
             key: "foo",
             value: "bar",
+            guild_id: 1,
+            user_id: 2,
+            timestamp: now,
         };
         assert_eq!(new_desc.key, "foo");
         assert_eq!(new_desc.value, "bar");
+        assert_eq!(new_desc.guild_id, 1);
+        assert_eq!(new_desc.user_id, 2);
     }
 
     #[test]
     fn test_description_fields() {
         let desc = Description {
+            id: 1,
             key: "foo".to_string(),
             value: "bar".to_string(),
+            guild_id: 1,
+            user_id: 2,
+            timestamp: Utc::now().naive_utc(),
+            reads: 0,
         };
         assert_eq!(desc.key, "foo");
         assert_eq!(desc.value, "bar");
     }
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("  Foo "), "foo");
+        assert_eq!(normalize_key("BAR"), "bar");
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("foo"), "foo");
+        assert_eq!(escape_like("50%_off"), "50\\%\\_off");
+        assert_eq!(escape_like("a\\b"), "a\\\\b");
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview("short", 10), "short");
+        assert_eq!(preview("line one\nline two", 20), "line one line two");
+        assert_eq!(preview("abcdefghij", 5), "abcd…");
+    }
+
+    #[test]
+    fn test_render_value() {
+        let vars = TemplateVars {
+            user: "<@1>",
+            channel: "<#2>",
+            count: 3,
+            arg: "there",
+        };
+        assert_eq!(render_value("hi {arg}, {user}", &vars), "hi there, <@1>");
+        assert_eq!(render_value("seen {count}x", &vars), "seen 3x");
+        // Values that aren't valid templates are returned unchanged.
+        assert_eq!(render_value("legacy {value", &vars), "legacy {value");
+    }
+
+    #[test]
+    fn test_page_count() {
+        assert_eq!(page_count(0), 1);
+        assert_eq!(page_count(1), 1);
+        assert_eq!(page_count(KEYS_PAGE_SIZE), 1);
+        assert_eq!(page_count(KEYS_PAGE_SIZE + 1), 2);
+    }
 }
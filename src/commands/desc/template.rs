@@ -0,0 +1,284 @@
+//! A small template language for values stored with `/set`.
+//!
+//! Placeholders are expanded each time a value is read:
+//!
+//! - `{user}` mentions whoever asked for the value
+//! - `{channel}` mentions the channel it was asked in
+//! - `{count}` is the number of times the key has been read
+//! - `{arg}` is any extra text given after the key
+//! - `{random:a|b|c}` picks one of the alternatives
+//!
+//! Braces around anything else, like the ones in a code snippet, are kept
+//! as they are, doubled ones included. To show a placeholder's name
+//! literally, double its braces: `{{user}}` shows `{user}`.
+
+use rand::Rng;
+use std::fmt;
+
+/// Error returned when a value can't be parsed as a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    UnknownPlaceholder(String),
+    EmptyRandom,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownPlaceholder(name) => {
+                write!(f, "Unknown placeholder '{{{}}}'", name)
+            }
+            TemplateError::EmptyRandom => write!(f, "'{{random:...}}' needs at least one choice"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    User,
+    Channel,
+    Count,
+    Arg,
+    Random(Vec<String>),
+}
+
+/// Values substituted into a template when it is rendered.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars<'a> {
+    pub user: &'a str,
+    pub channel: &'a str,
+    pub count: i64,
+    pub arg: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse a stored value into a template. Only placeholders with a known
+    /// name are checked; any other braces are plain text.
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = source;
+
+        while let Some(c) = rest.chars().next() {
+            if let Some(body) = rest
+                .strip_prefix('{')
+                .and_then(braced)
+                .filter(|body| rest[body.len() + 3..].starts_with('}'))
+            {
+                text.push('{');
+                text.push_str(body);
+                text.push('}');
+                rest = &rest[body.len() + 4..];
+                continue;
+            }
+            if c == '{' {
+                if let Some(body) = braced(rest) {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(parse_placeholder(body)?);
+                    rest = &rest[body.len() + 2..];
+                    continue;
+                }
+            }
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self { segments })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Expand every placeholder, picking `{random:...}` alternatives with `rng`.
+    pub fn render<R: Rng + ?Sized>(&self, vars: &TemplateVars<'_>, rng: &mut R) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::User => out.push_str(vars.user),
+                Segment::Channel => out.push_str(vars.channel),
+                Segment::Count => out.push_str(&vars.count.to_string()),
+                Segment::Arg => out.push_str(vars.arg),
+                Segment::Random(choices) => {
+                    out.push_str(&choices[rng.gen_range(0..choices.len())]);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Names that can appear between braces.
+const PLACEHOLDERS: [&str; 5] = ["user", "channel", "count", "arg", "random"];
+
+/// The text inside the placeholder `rest` starts with, if it starts with one.
+fn braced(rest: &str) -> Option<&str> {
+    let inner = rest.strip_prefix('{')?;
+    let end = inner.find(['{', '}'])?;
+    let body = &inner[..end];
+    (inner[end..].starts_with('}') && is_placeholder(body)).then_some(body)
+}
+
+/// Whether the text between a pair of braces names a placeholder.
+fn is_placeholder(body: &str) -> bool {
+    let name = body.split_once(':').map_or(body, |(name, _)| name);
+    PLACEHOLDERS.contains(&name.trim())
+}
+
+fn parse_placeholder(body: &str) -> Result<Segment, TemplateError> {
+    let (name, rest) = match body.split_once(':') {
+        Some((name, rest)) => (name.trim(), Some(rest)),
+        None => (body.trim(), None),
+    };
+    match (name, rest) {
+        ("user", None) => Ok(Segment::User),
+        ("channel", None) => Ok(Segment::Channel),
+        ("count", None) => Ok(Segment::Count),
+        ("arg", None) => Ok(Segment::Arg),
+        ("random", Some(choices)) => {
+            let choices: Vec<String> = choices
+                .split('|')
+                .map(|choice| choice.trim().to_string())
+                .collect();
+            if choices.iter().all(|choice| choice.is_empty()) {
+                return Err(TemplateError::EmptyRandom);
+            }
+            Ok(Segment::Random(choices))
+        }
+        ("random", None) => Err(TemplateError::EmptyRandom),
+        _ => Err(TemplateError::UnknownPlaceholder(body.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn vars() -> TemplateVars<'static> {
+        TemplateVars {
+            user: "<@1>",
+            channel: "<#2>",
+            count: 7,
+            arg: "world",
+        }
+    }
+
+    fn render(source: &str) -> String {
+        let mut rng = StdRng::seed_from_u64(0);
+        Template::parse(source).unwrap().render(&vars(), &mut rng)
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let template = Template::parse("just text").unwrap();
+        assert_eq!(
+            template.segments(),
+            &[Segment::Text("just text".to_string())]
+        );
+        assert_eq!(render("just text"), "just text");
+    }
+
+    #[test]
+    fn test_empty_template() {
+        assert!(Template::parse("").unwrap().segments().is_empty());
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(render("hi {user} in {channel}"), "hi <@1> in <#2>");
+        assert_eq!(render("read {count} times"), "read 7 times");
+        assert_eq!(render("hello {arg}!"), "hello world!");
+        assert_eq!(render("{ user }"), "<@1>");
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        assert_eq!(render("{{user}}"), "{user}");
+        assert_eq!(render("{{random:a|b}}"), "{random:a|b}");
+        // Doubled braces around anything else are kept as they are.
+        assert_eq!(render("a {{ b }} c"), "a {{ b }} c");
+        assert_eq!(render("format!(\"{{}}\")"), "format!(\"{{}}\")");
+        assert_eq!(render("{{user}"), "{<@1>");
+    }
+
+    #[test]
+    fn test_literal_braces() {
+        assert_eq!(
+            render("fn main() { println!(\"hi\"); }"),
+            "fn main() { println!(\"hi\"); }"
+        );
+        assert_eq!(render("{nope} and {user"), "{nope} and {user");
+        assert_eq!(render("oops }"), "oops }");
+        assert_eq!(render("{a{user}}"), "{a<@1>}");
+        assert_eq!(render("{ {arg} }"), "{ world }");
+    }
+
+    #[test]
+    fn test_random_choices() {
+        let template = Template::parse("{random:a|b|c}").unwrap();
+        assert_eq!(
+            template.segments(),
+            &[Segment::Random(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string()
+            ])]
+        );
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            let out = template.render(&vars(), &mut rng);
+            assert!(["a", "b", "c"].contains(&out.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_random_covers_all_choices() {
+        let template = Template::parse("{random:a|b}").unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let outputs: std::collections::HashSet<String> = (0..50)
+            .map(|_| template.render(&vars(), &mut rng))
+            .collect();
+        assert_eq!(outputs.len(), 2);
+    }
+
+    #[test]
+    fn test_single_random_choice() {
+        assert_eq!(render("{random:only}"), "only");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Template::parse("{user:nope}"),
+            Err(TemplateError::UnknownPlaceholder("user:nope".to_string()))
+        );
+        assert_eq!(
+            Template::parse("{random:}"),
+            Err(TemplateError::EmptyRandom)
+        );
+        assert_eq!(Template::parse("{random}"), Err(TemplateError::EmptyRandom));
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            TemplateError::UnknownPlaceholder("user:nope".to_string()).to_string(),
+            "Unknown placeholder '{user:nope}'"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::utils::random_choice;
+use crate::commands::pool::{pick_response, PoolKind};
 
 pub(crate) static RESPONSES: [&str; 14] = [
     "Water.",
@@ -21,8 +21,8 @@ pub(crate) static RESPONSES: [&str; 14] = [
 /// Usage: /drink
 #[poise::command(slash_command, prefix_command)]
 pub async fn drink(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
-    let drink = random_choice(&RESPONSES).unwrap();
-    ctx.say(*drink).await?;
+    let drink = pick_response(&ctx, PoolKind::Drink);
+    ctx.say(drink).await?;
     Ok(())
 }
 
@@ -0,0 +1,407 @@
+use crate::commands::desc::template::TemplateVars;
+use crate::commands::desc::{
+    find_description, normalize_key, record_read, render_value, MAX_KEY_LEN,
+};
+use crate::commands::require_guild;
+use crate::models::{FactoidSettings, NewFactoidChannel};
+use crate::schema::{factoid_channels, factoid_settings};
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use poise::serenity_prelude::{
+    self as serenity, CreateAllowedMentions, CreateMessage, GuildChannel, Message,
+};
+use poise::Context;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+/// Longest cooldown accepted by `/factoids cooldown`.
+pub(crate) const MAX_COOLDOWN_SECONDS: i32 = 3600;
+
+/// A factoid lookup requested by a chat message.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Trigger<'a> {
+    pub key: &'a str,
+    pub arg: &'a str,
+}
+
+/// Parse a `key?` or `!key [arg]` message.
+pub(crate) fn parse_trigger(content: &str) -> Option<Trigger<'_>> {
+    let content = content.trim();
+    let (key, arg) = if let Some(rest) = content.strip_prefix('!') {
+        let rest = rest.trim_start();
+        match rest.split_once(char::is_whitespace) {
+            Some((key, arg)) => (key, arg.trim()),
+            None => (rest, ""),
+        }
+    } else if let Some(rest) = content.strip_suffix('?') {
+        (rest.trim_end(), "")
+    } else {
+        return None;
+    };
+    if key.is_empty() || key.ends_with('?') || key.chars().count() > MAX_KEY_LEN {
+        return None;
+    }
+    Some(Trigger { key, arg })
+}
+
+/// Tracks when each factoid last fired so a channel isn't flooded with repeats.
+#[derive(Default)]
+pub struct FactoidCooldowns {
+    last_fired: Mutex<HashMap<(u64, u64, String), Instant>>,
+}
+
+impl FactoidCooldowns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` and starts a new cooldown if `key` may fire in this channel.
+    pub fn try_acquire(&self, guild: u64, channel: u64, key: &str, cooldown: Duration) -> bool {
+        let mut last_fired = self.last_fired.lock().unwrap();
+        let now = Instant::now();
+        let entry = (guild, channel, key.to_string());
+        if let Some(fired) = last_fired.get(&entry) {
+            if now.duration_since(*fired) < cooldown {
+                return false;
+            }
+        }
+        last_fired.insert(entry, now);
+        // Drop entries older than the longest possible cooldown to keep the map small.
+        let max = Duration::from_secs(MAX_COOLDOWN_SECONDS as u64);
+        last_fired.retain(|_, fired| now.duration_since(*fired) < max);
+        true
+    }
+}
+
+fn find_settings(conn: &mut PgConnection, guild: i64) -> QueryResult<Option<FactoidSettings>> {
+    factoid_settings::table
+        .find(guild)
+        .select(FactoidSettings::as_select())
+        .first(conn)
+        .optional()
+}
+
+fn set_enabled(conn: &mut PgConnection, guild: i64, enabled: bool) -> QueryResult<usize> {
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(factoid_settings::table)
+        .values((
+            factoid_settings::guild_id.eq(guild),
+            factoid_settings::enabled.eq(enabled),
+            factoid_settings::updated_at.eq(now),
+        ))
+        .on_conflict(factoid_settings::guild_id)
+        .do_update()
+        .set((
+            factoid_settings::enabled.eq(enabled),
+            factoid_settings::updated_at.eq(now),
+        ))
+        .execute(conn)
+}
+
+fn set_cooldown(conn: &mut PgConnection, guild: i64, seconds: i32) -> QueryResult<usize> {
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(factoid_settings::table)
+        .values((
+            factoid_settings::guild_id.eq(guild),
+            factoid_settings::cooldown_seconds.eq(seconds),
+            factoid_settings::updated_at.eq(now),
+        ))
+        .on_conflict(factoid_settings::guild_id)
+        .do_update()
+        .set((
+            factoid_settings::cooldown_seconds.eq(seconds),
+            factoid_settings::updated_at.eq(now),
+        ))
+        .execute(conn)
+}
+
+fn allowed_channels(conn: &mut PgConnection, guild: i64) -> QueryResult<Vec<i64>> {
+    factoid_channels::table
+        .filter(factoid_channels::guild_id.eq(guild))
+        .select(factoid_channels::channel_id)
+        .load(conn)
+}
+
+/// Reply to a `key?` or `!key` message with the stored value, if the guild has opted in.
+pub async fn handle_message(
+    ctx: &serenity::Context,
+    pool: &PgPool,
+    cooldowns: &FactoidCooldowns,
+    msg: &Message,
+) -> Result<(), crate::Error> {
+    if msg.author.bot {
+        return Ok(());
+    }
+    let Some(guild) = msg.guild_id else {
+        return Ok(());
+    };
+    let Some(trigger) = parse_trigger(&msg.content) else {
+        return Ok(());
+    };
+    let key = normalize_key(trigger.key);
+    let guild_id = guild.get() as i64;
+    let channel_id = msg.channel_id.get() as i64;
+
+    let value = {
+        let mut conn = pool.get()?;
+        let Some(settings) = find_settings(&mut conn, guild_id)? else {
+            return Ok(());
+        };
+        if !settings.enabled {
+            return Ok(());
+        }
+        let channels = allowed_channels(&mut conn, guild_id)?;
+        if !channels.is_empty() && !channels.contains(&channel_id) {
+            return Ok(());
+        }
+        let Some(desc) = find_description(&mut conn, guild_id, &key)? else {
+            return Ok(());
+        };
+        let cooldown = Duration::from_secs(settings.cooldown_seconds.max(0) as u64);
+        if !cooldowns.try_acquire(guild.get(), msg.channel_id.get(), &key, cooldown) {
+            return Ok(());
+        }
+        let count = record_read(&mut conn, desc.id)?;
+        let user = format!("<@{}>", msg.author.id);
+        let channel = format!("<#{}>", msg.channel_id);
+        let vars = TemplateVars {
+            user: &user,
+            channel: &channel,
+            count,
+            arg: trigger.arg,
+        };
+        render_value(&desc.value, &vars)
+    };
+
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(value)
+                .reference_message(msg)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Configure automatic replies to `key?` and `!key` messages.
+/// Usage: /factoids enable
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("enable", "disable", "cooldown", "allow", "disallow", "status"),
+    subcommand_required
+)]
+pub async fn factoids(_ctx: Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    Ok(())
+}
+
+/// Start replying to factoid triggers in this server.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn enable(ctx: Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    set_enabled(&mut conn, guild, true)?;
+    ctx.say("Factoid replies enabled.").await?;
+    Ok(())
+}
+
+/// Stop replying to factoid triggers in this server.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn disable(ctx: Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    set_enabled(&mut conn, guild, false)?;
+    ctx.say("Factoid replies disabled.").await?;
+    Ok(())
+}
+
+/// Set how long a factoid stays quiet in a channel after replying.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn cooldown(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    #[description = "Cooldown in seconds"]
+    #[min = 0]
+    #[max = 3600]
+    seconds: i32,
+) -> Result<(), crate::Error> {
+    if !(0..=MAX_COOLDOWN_SECONDS).contains(&seconds) {
+        ctx.say(format!(
+            "Cooldown must be between 0 and {} seconds.",
+            MAX_COOLDOWN_SECONDS
+        ))
+        .await?;
+        return Ok(());
+    }
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    set_cooldown(&mut conn, guild, seconds)?;
+    ctx.say(format!("Factoid cooldown set to {}s.", seconds))
+        .await?;
+    Ok(())
+}
+
+/// Allow factoid replies in a channel. Once any channel is allowed, all others are ignored.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn allow(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    channel: GuildChannel,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    diesel::insert_into(factoid_channels::table)
+        .values(&NewFactoidChannel {
+            guild_id: guild,
+            channel_id: channel.id.get() as i64,
+        })
+        .on_conflict_do_nothing()
+        .execute(&mut conn)?;
+    ctx.say(format!("Factoid replies allowed in <#{}>.", channel.id))
+        .await?;
+    Ok(())
+}
+
+/// Remove a channel from the factoid allowlist.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn disallow(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    channel: GuildChannel,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    let removed = diesel::delete(
+        factoid_channels::table
+            .filter(factoid_channels::guild_id.eq(guild))
+            .filter(factoid_channels::channel_id.eq(channel.id.get() as i64)),
+    )
+    .execute(&mut conn)?;
+    if removed > 0 {
+        ctx.say(format!("<#{}> removed from the allowlist.", channel.id))
+            .await?;
+    } else {
+        ctx.say(format!("<#{}> was not on the allowlist.", channel.id))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Show this server's factoid settings.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn status(ctx: Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    let settings = find_settings(&mut conn, guild)?;
+    let channels = allowed_channels(&mut conn, guild)?;
+    let (enabled, cooldown) = settings
+        .map(|s| (s.enabled, s.cooldown_seconds))
+        .unwrap_or((false, 30));
+    let channel_list = if channels.is_empty() {
+        "all channels".to_string()
+    } else {
+        channels
+            .iter()
+            .map(|id| format!("<#{}>", id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    ctx.say(format!(
+        "Factoid replies are {}. Cooldown: {}s. Channels: {}.",
+        if enabled { "enabled" } else { "disabled" },
+        cooldown,
+        channel_list
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger<'a>(key: &'a str, arg: &'a str) -> Option<Trigger<'a>> {
+        Some(Trigger { key, arg })
+    }
+
+    #[test]
+    fn test_parse_trigger_question() {
+        assert_eq!(parse_trigger("foo?"), trigger("foo", ""));
+        assert_eq!(parse_trigger("  foo bar ?  "), trigger("foo bar", ""));
+        assert_eq!(parse_trigger("?"), None);
+        assert_eq!(parse_trigger("foo??"), None);
+    }
+
+    #[test]
+    fn test_parse_trigger_bang() {
+        assert_eq!(parse_trigger("!foo"), trigger("foo", ""));
+        assert_eq!(parse_trigger("!foo and more"), trigger("foo", "and more"));
+        assert_eq!(parse_trigger("!"), None);
+        assert_eq!(parse_trigger("! foo"), trigger("foo", ""));
+    }
+
+    #[test]
+    fn test_parse_trigger_plain_message() {
+        assert_eq!(parse_trigger("hello there"), None);
+        assert_eq!(parse_trigger(""), None);
+    }
+
+    #[test]
+    fn test_parse_trigger_too_long() {
+        let long = format!("{}?", "a".repeat(MAX_KEY_LEN + 1));
+        assert_eq!(parse_trigger(&long), None);
+    }
+
+    #[test]
+    fn test_cooldown_blocks_repeat() {
+        let cooldowns = FactoidCooldowns::new();
+        let cooldown = Duration::from_secs(60);
+        assert!(cooldowns.try_acquire(1, 2, "foo", cooldown));
+        assert!(!cooldowns.try_acquire(1, 2, "foo", cooldown));
+        // Other keys and channels have their own cooldowns.
+        assert!(cooldowns.try_acquire(1, 2, "bar", cooldown));
+        assert!(cooldowns.try_acquire(1, 3, "foo", cooldown));
+    }
+
+    #[test]
+    fn test_zero_cooldown_always_fires() {
+        let cooldowns = FactoidCooldowns::new();
+        assert!(cooldowns.try_acquire(1, 2, "foo", Duration::ZERO));
+        assert!(cooldowns.try_acquire(1, 2, "foo", Duration::ZERO));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::utils::random_choice;
+use crate::commands::pool::{pick_response, PoolKind};
 
 pub(crate) static RESPONSES: [&str; 41] = [
     "Pizza",
@@ -48,8 +48,8 @@ pub(crate) static RESPONSES: [&str; 41] = [
 /// Usage: /food
 #[poise::command(slash_command, prefix_command)]
 pub async fn food(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
-    let item = random_choice(&RESPONSES).unwrap();
-    ctx.say(*item).await?;
+    let item = pick_response(&ctx, PoolKind::Food);
+    ctx.say(item).await?;
     Ok(())
 }
 
@@ -0,0 +1,341 @@
+use crate::commands::food::RESPONSES;
+use crate::interactions::ButtonClick;
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateAllowedMentions,
+    CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    EditMessage,
+};
+use poise::CreateReply;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Candidates offered when none is given.
+pub(crate) const DEFAULT_CANDIDATES: usize = 3;
+/// Most candidates a poll can offer; Discord allows five buttons per row.
+pub(crate) const MAX_CANDIDATES: usize = 5;
+/// Voting window when none is given.
+pub(crate) const DEFAULT_POLL_MINUTES: u64 = 5;
+/// Longest voting window allowed. This outlives the command's 15 minute
+/// interaction token, so a poll is closed through the channel instead.
+pub(crate) const MAX_POLL_MINUTES: u64 = 60;
+
+/// Votes cast in a running poll. Each user has one vote, which they may change.
+#[derive(Debug, Clone)]
+pub(crate) struct LunchPoll {
+    candidates: Vec<String>,
+    votes: HashMap<u64, usize>,
+}
+
+impl LunchPoll {
+    pub fn new(candidates: Vec<String>) -> Self {
+        Self {
+            candidates,
+            votes: HashMap::new(),
+        }
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    /// Record `user`'s vote for the candidate at `choice`. Returns false if
+    /// there is no such candidate.
+    pub fn vote(&mut self, user: u64, choice: usize) -> bool {
+        if choice >= self.candidates.len() {
+            return false;
+        }
+        self.votes.insert(user, choice);
+        true
+    }
+
+    /// Number of votes for each candidate, in candidate order.
+    pub fn tally(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.candidates.len()];
+        for &choice in self.votes.values() {
+            counts[choice] += 1;
+        }
+        counts
+    }
+
+    /// The candidates with the most votes, or none if nobody voted.
+    pub fn leaders(&self) -> Vec<&str> {
+        let counts = self.tally();
+        let best = counts.iter().copied().max().unwrap_or(0);
+        if best == 0 {
+            return Vec::new();
+        }
+        self.candidates
+            .iter()
+            .zip(counts)
+            .filter(|(_, count)| *count == best)
+            .map(|(candidate, _)| candidate.as_str())
+            .collect()
+    }
+}
+
+/// Custom id of the button for candidate `index` in poll `poll_id`.
+fn button_id(poll_id: u64, index: usize) -> String {
+    format!("lunchpoll:{}:{}", poll_id, index)
+}
+
+/// Custom id a poll's result is recorded under.
+fn outcome_id(poll_id: u64, winner: &str) -> String {
+    format!("lunchpoll:{}:winner:{}", poll_id, winner)
+}
+
+/// The candidate index a button belongs to, if it is one of this poll's buttons.
+fn parse_button_id(custom_id: &str, poll_id: u64) -> Option<usize> {
+    let rest = custom_id.strip_prefix("lunchpoll:")?;
+    let (id, index) = rest.split_once(':')?;
+    if id.parse::<u64>().ok()? != poll_id {
+        return None;
+    }
+    index.parse().ok()
+}
+
+fn buttons(poll_id: u64, poll: &LunchPoll, disabled: bool) -> Vec<CreateActionRow> {
+    let counts = poll.tally();
+    let buttons = poll
+        .candidates()
+        .iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(i, (candidate, count))| {
+            CreateButton::new(button_id(poll_id, i))
+                .label(format!("{} ({})", candidate, count))
+                .style(ButtonStyle::Primary)
+                .disabled(disabled)
+        })
+        .collect();
+    vec![CreateActionRow::Buttons(buttons)]
+}
+
+/// Describe the result of a finished poll.
+fn announcement(poll: &LunchPoll, winner: Option<&str>) -> String {
+    let leaders = poll.leaders();
+    let votes = poll.tally().into_iter().max().unwrap_or(0);
+    match (winner, leaders.len()) {
+        (Some(winner), 0) => format!("Nobody voted, so lunch is **{}**.", winner),
+        (Some(winner), 1) => format!(
+            "Lunch is **{}** with {} vote{}!",
+            winner,
+            votes,
+            if votes == 1 { "" } else { "s" }
+        ),
+        (Some(winner), _) => format!(
+            "It's a tie between {}. The coin says **{}**!",
+            leaders.join(", "),
+            winner
+        ),
+        (None, _) => "Nothing to vote on.".to_string(),
+    }
+}
+
+/// Start a lunch vote between a few random food suggestions.
+/// Usage: /lunchpoll [candidates] [minutes]
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn lunchpoll(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "How many options to vote on"]
+    #[min = 2]
+    #[max = 5]
+    candidates: Option<usize>,
+    #[description = "How long voting stays open, in minutes"]
+    #[min = 1]
+    #[max = 60]
+    minutes: Option<u64>,
+) -> Result<(), crate::Error> {
+    let count = candidates
+        .unwrap_or(DEFAULT_CANDIDATES)
+        .clamp(2, MAX_CANDIDATES);
+    let minutes = minutes
+        .unwrap_or(DEFAULT_POLL_MINUTES)
+        .clamp(1, MAX_POLL_MINUTES);
+    let picks: Vec<String> = RESPONSES
+        .choose_multiple(&mut rand::thread_rng(), count)
+        .map(|s| s.to_string())
+        .collect();
+    let mut poll = LunchPoll::new(picks);
+    let poll_id = ctx.id();
+    let prompt = format!(
+        "What's for lunch? Voting closes in {} minute{}.",
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    );
+
+    let mut message = ctx
+        .send(
+            CreateReply::default()
+                .content(prompt)
+                .components(buttons(poll_id, &poll, false)),
+        )
+        .await?
+        .into_message()
+        .await?;
+    let message_id = message.id;
+
+    let deadline = Instant::now() + Duration::from_secs(minutes * 60);
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let Some(press) = ComponentInteractionCollector::new(ctx.serenity_context())
+            .message_id(message_id)
+            .timeout(remaining)
+            .await
+        else {
+            break;
+        };
+        let Some(choice) = parse_button_id(&press.data.custom_id, poll_id) else {
+            continue;
+        };
+        if let Err(e) = ctx
+            .data()
+            .interaction_tracker
+            .read()
+            .await
+            .track_button_click(&press)
+            .await
+        {
+            warn!("Failed to record lunch poll vote: {}", e);
+        }
+        poll.vote(press.user.id.get(), choice);
+        press
+            .create_response(
+                ctx.serenity_context(),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .components(buttons(poll_id, &poll, false)),
+                ),
+            )
+            .await?;
+    }
+
+    let leaders = poll.leaders();
+    let winner = if leaders.is_empty() {
+        poll.candidates().choose(&mut rand::thread_rng()).cloned()
+    } else {
+        leaders
+            .choose(&mut rand::thread_rng())
+            .map(|s| s.to_string())
+    };
+    // Edit and announce over plain HTTP: by now the command's interaction
+    // token may have expired.
+    message
+        .edit(
+            ctx,
+            EditMessage::new()
+                .content("What's for lunch? Voting has closed.")
+                .components(buttons(poll_id, &poll, true)),
+        )
+        .await?;
+    if let Some(winner) = &winner {
+        record_outcome(ctx, poll_id, winner).await;
+    }
+    ctx.channel_id()
+        .send_message(
+            ctx,
+            CreateMessage::new()
+                .content(announcement(&poll, winner.as_deref()))
+                .reference_message(&message)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Record the winning candidate as a click on the poll, next to its votes.
+async fn record_outcome(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    poll_id: u64,
+    winner: &str,
+) {
+    let outcome = outcome_id(poll_id, winner);
+    let click = ButtonClick {
+        custom_id: &outcome,
+        user_id: ctx.author().id.get() as i64,
+        guild_id: ctx.guild_id().map(|id| id.get() as i64).unwrap_or(0),
+    };
+    if let Err(e) = ctx
+        .data()
+        .interaction_tracker
+        .read()
+        .await
+        .track_button_click(click)
+        .await
+    {
+        warn!("Failed to record lunch poll outcome: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poll() -> LunchPoll {
+        LunchPoll::new(vec![
+            "Pizza".to_string(),
+            "Sushi".to_string(),
+            "Tacos".to_string(),
+        ])
+    }
+
+    #[test]
+    fn test_votes_can_change() {
+        let mut poll = poll();
+        assert!(poll.vote(1, 0));
+        assert!(poll.vote(2, 1));
+        assert!(poll.vote(1, 1));
+        assert_eq!(poll.tally(), vec![0, 2, 0]);
+        assert_eq!(poll.leaders(), vec!["Sushi"]);
+    }
+
+    #[test]
+    fn test_vote_out_of_range() {
+        let mut poll = poll();
+        assert!(!poll.vote(1, 3));
+        assert_eq!(poll.tally(), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_leaders_tie_and_empty() {
+        let mut poll = poll();
+        assert!(poll.leaders().is_empty());
+        poll.vote(1, 0);
+        poll.vote(2, 2);
+        assert_eq!(poll.leaders(), vec!["Pizza", "Tacos"]);
+    }
+
+    #[test]
+    fn test_button_ids_round_trip() {
+        assert_eq!(parse_button_id(&button_id(42, 3), 42), Some(3));
+        assert_eq!(parse_button_id(&button_id(42, 3), 43), None);
+        assert_eq!(parse_button_id("other:42:3", 42), None);
+        assert_eq!(parse_button_id("lunchpoll:42:x", 42), None);
+        // A recorded result is never mistaken for a vote.
+        assert_eq!(parse_button_id(&outcome_id(42, "Pizza"), 42), None);
+    }
+
+    #[test]
+    fn test_announcement() {
+        let mut poll = poll();
+        assert_eq!(
+            announcement(&poll, Some("Pizza")),
+            "Nobody voted, so lunch is **Pizza**."
+        );
+        poll.vote(1, 1);
+        assert_eq!(
+            announcement(&poll, Some("Sushi")),
+            "Lunch is **Sushi** with 1 vote!"
+        );
+        poll.vote(2, 2);
+        assert_eq!(
+            announcement(&poll, Some("Tacos")),
+            "It's a tie between Sushi, Tacos. The coin says **Tacos**!"
+        );
+    }
+}
@@ -1,10 +1,6 @@
 use crate::db::DbPool;
 use crate::db::Pool;
-use crate::metrics::{
-    COMMAND_REQUESTS,
-    COMMAND_ERRORS,
-    COMMAND_DURATION,
-};
+use crate::metrics::{record_command_outcome, Outcome, COMMAND_REQUESTS};
 use crate::models::{CommandHistory, CommandStat};
 use crate::schema::{command_history, command_stats};
 use crate::utils::time::get_current_time;
@@ -31,10 +27,13 @@ pub mod ball;
 pub mod botsnack;
 pub mod desc;
 pub mod drink;
+pub mod factoids;
 pub mod food;
 pub mod github;
+pub mod lunchpoll;
 pub mod owner;
 pub mod pingpong;
+pub mod pool;
 pub mod random;
 pub mod stats;
 pub mod stonks;
@@ -42,19 +41,39 @@ pub mod stonks;
 // Re-export commonly used items
 pub use self::{
     advice::advice,
-    ball::ball,
+    ball::{ball, ballmode},
     botsnack::botsnack,
-    desc::set,
+    desc::{get, history, keys, revert, set, unset},
     drink::drink,
+    factoids::factoids,
     food::food,
     github::github,
-    owner::quit,
+    lunchpoll::lunchpoll,
+    owner::{quit, refreshsymbols},
     pingpong::ping,
+    pool::pool,
     random::random,
     stats::stats,
-    stonks::{graph, stonkcomp, stonks},
+    stonks::{
+        currency::{crypto, fx},
+        graph,
+        paper::{buy, leaderboard, portfolio, sell},
+        quote::quote,
+        stonkcomp, stonks,
+        summary::marketsummary,
+        watch::{alert, watch},
+    },
 };
 
+/// The invoking guild's id, or an error for commands used outside a server.
+pub(crate) fn require_guild(
+    ctx: &poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<i64, crate::Error> {
+    ctx.guild_id()
+        .map(|id| id.get() as i64)
+        .ok_or_else(|| "This command can only be used in a server.".into())
+}
+
 /// Command context with timing information
 pub struct CommandContext {
     pub command_name: String,
@@ -168,8 +187,7 @@ pub async fn execute_command(
     ctx.update_stats(&mut conn, user)?;
 
     // Record duration
-    let duration = ctx.duration().as_secs_f64();
-    COMMAND_DURATION.with_label_values(&[&ctx.command_name]).observe(duration);
+    record_command_outcome(&ctx.command_name, Outcome::Ok, Some(ctx.duration()));
 
     Ok(())
 }
@@ -182,6 +200,12 @@ mod tests {
     use std::sync::Arc;
     use tokio::sync::RwLock;
     use std::collections::HashMap;
+    use advice::client::{AdviceCache, AdviceConfig, HttpAdviceClient};
+    use crate::commands::stonks::market::{FixtureProvider, MarketData};
+    use crate::commands::stonks::quote::QuoteCache;
+    use crate::commands::stonks::symbols::SymbolDirectory;
+    use crate::logging::{LogLevel, DEFAULT_FILTER};
+    use crate::metrics::CommandTimers;
 
     fn create_test_pool() -> Pool<ConnectionManager<PgConnection>> {
         let database_url = "postgres://localhost/testbot_test";
@@ -266,11 +290,17 @@ mod tests {
         let ctx = CommandContext::new("test".to_string(), vec!["arg1".to_string()]);
         let data = Data {
             db_pool: Arc::new(pool),
-            command_timers: HashMap::new(),
+            command_timers: CommandTimers::new(),
+            log_level: LogLevel::new(DEFAULT_FILTER).unwrap().1,
             guilds: Arc::new(HashMap::new()),
             users: Arc::new(HashMap::new()),
             channels: Arc::new(HashMap::new()),
             interaction_tracker: RwLock::new(InteractionTracker::new(Arc::new(create_test_pool()))),
+            advice_client: HttpAdviceClient::new(AdviceConfig::default()).unwrap(),
+            advice_cache: AdviceCache::new(),
+            market_data: MarketData::Fixture(FixtureProvider::new()),
+            quote_cache: QuoteCache::default(),
+            symbols: SymbolDirectory::bundled(),
         };
 
         assert!(execute_command(&ctx, &data, &user).await.is_ok());
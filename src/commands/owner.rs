@@ -1,4 +1,5 @@
 // ... existing code ...
+use crate::commands::stonks::symbols::{listing_path, parse_listing, save_listing};
 
 /// Shut down the bot (owners only).
 /// Usage: /quit
@@ -12,6 +13,68 @@ pub async fn quit(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<
     Ok(())
 }
 
+/// Reload the ticker symbol list from the market data provider (owners only).
+/// Usage: /refreshsymbols
+///
+/// The new list is saved to `SYMBOL_LISTING_PATH` and loaded again when the
+/// bot restarts.
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub async fn refreshsymbols(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<(), crate::Error> {
+    ctx.defer().await?;
+    let data = ctx.data();
+    let (csv, listings) = match data
+        .market_data
+        .listing()
+        .await
+        .and_then(|csv| parse_listing(&csv).map(|listings| (csv, listings)))
+    {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            ctx.say(format!("Couldn't refresh the symbol directory: {}", e))
+                .await?;
+            return Ok(());
+        }
+    };
+    let count = listings.len();
+    data.symbols.replace(listings);
+    let path = listing_path();
+    let reply = match save_listing(&path, &csv) {
+        Ok(()) => format!("Symbol directory now has {} symbols.", count),
+        Err(e) => format!(
+            "Symbol directory now has {} symbols, but I couldn't save them to {}: {}",
+            count,
+            path.display(),
+            e
+        ),
+    };
+    ctx.say(reply).await?;
+    Ok(())
+}
+
+/// Show or change which log lines are written (owners only).
+/// Usage: /loglevel [filter]
+///
+/// The filter takes `RUST_LOG`-style directives, e.g.
+/// `/loglevel warn,testbot=debug`.
+#[poise::command(slash_command, prefix_command, owners_only)]
+pub async fn loglevel(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "New filter, e.g. info or warn,testbot=debug"] filter: Option<String>,
+) -> Result<(), crate::Error> {
+    let log_level = &ctx.data().log_level;
+    let reply = match filter {
+        None => format!("Log filter is `{}`", log_level.current()),
+        Some(filter) => match log_level.set(&filter) {
+            Ok(()) => format!("Log filter is now `{}`", log_level.current()),
+            Err(e) => e.to_string(),
+        },
+    };
+    ctx.say(reply).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     #[tokio::test]
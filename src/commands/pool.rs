@@ -0,0 +1,602 @@
+use crate::commands::require_guild;
+use crate::models::NewResponsePoolItem;
+use crate::schema::{response_pool_settings, response_pools, shuffle_bags};
+use crate::utils::random::{parse_weighted, weighted_choice, ShuffleBag};
+use crate::utils::random_choice;
+use chrono::Utc;
+use diesel::prelude::*;
+use poise::Context;
+use tracing::warn;
+
+/// Longest entry accepted by `/pool add`.
+pub(crate) const MAX_ITEM_LEN: usize = 200;
+/// Longest reply Discord will accept.
+const MAX_REPLY_LEN: usize = 2000;
+/// Room kept for the `…and N more` note when a list is cut short.
+const MORE_NOTE_LEN: usize = 20;
+
+/// Join `items` with commas, leaving off whatever doesn't fit in `limit`
+/// characters and saying how many were left off.
+fn join_within(items: &[String], limit: usize) -> String {
+    let mut out = String::new();
+    for (i, item) in items.iter().enumerate() {
+        let sep = if i == 0 { "" } else { ", " };
+        let reserve = if i + 1 == items.len() {
+            0
+        } else {
+            MORE_NOTE_LEN
+        };
+        if out.chars().count() + sep.len() + item.chars().count() + reserve > limit {
+            out.push_str(sep);
+            out.push_str(&format!("…and {} more", items.len() - i));
+            break;
+        }
+        out.push_str(sep);
+        out.push_str(item);
+    }
+    out
+}
+
+/// The response lists a guild can customise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum PoolKind {
+    #[name = "food"]
+    Food,
+    #[name = "drink"]
+    Drink,
+    #[name = "ball"]
+    Ball,
+    #[name = "botsnack"]
+    Botsnack,
+}
+
+impl PoolKind {
+    /// Name stored in the `pool` column.
+    pub fn key(self) -> &'static str {
+        match self {
+            PoolKind::Food => "food",
+            PoolKind::Drink => "drink",
+            PoolKind::Ball => "ball",
+            PoolKind::Botsnack => "botsnack",
+        }
+    }
+
+    /// The compiled-in responses for this pool.
+    pub fn defaults(self) -> &'static [&'static str] {
+        match self {
+            PoolKind::Food => &crate::commands::food::RESPONSES,
+            PoolKind::Drink => &crate::commands::drink::RESPONSES,
+            PoolKind::Ball => &crate::commands::ball::RESPONSES,
+            PoolKind::Botsnack => &crate::commands::botsnack::RESPONSES,
+        }
+    }
+}
+
+/// Whether a guild's entries are added to the built-in list or used instead of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum PoolMode {
+    #[name = "extend"]
+    Extend,
+    #[name = "replace"]
+    Replace,
+}
+
+/// How a pool picks its next response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Selection {
+    /// Every pick is independent, weighted by entry.
+    #[name = "random"]
+    Random,
+    /// Every entry is served (repeated by weight) before any repeats.
+    #[name = "shuffle"]
+    Shuffle,
+}
+
+impl Selection {
+    /// Name stored in the `selection` column.
+    pub fn key(self) -> &'static str {
+        match self {
+            Selection::Random => "random",
+            Selection::Shuffle => "shuffle",
+        }
+    }
+
+    fn from_key(key: &str) -> Self {
+        match key {
+            "shuffle" => Selection::Shuffle,
+            _ => Selection::Random,
+        }
+    }
+}
+
+/// Combine the built-in responses with a guild's own weighted entries.
+/// A custom entry matching a built-in one overrides its weight, and a
+/// replacing pool with no entries falls back to the built-in list.
+pub(crate) fn merge_responses(
+    defaults: &[&str],
+    custom: Vec<(String, u32)>,
+    replace: bool,
+) -> Vec<(String, u32)> {
+    if replace && !custom.is_empty() {
+        return custom;
+    }
+    let mut merged: Vec<(String, u32)> = defaults.iter().map(|s| (s.to_string(), 1)).collect();
+    for (item, weight) in custom {
+        match merged.iter_mut().find(|(existing, _)| *existing == item) {
+            Some(existing) => existing.1 = weight,
+            None => merged.push((item, weight)),
+        }
+    }
+    merged
+}
+
+fn custom_items(
+    conn: &mut PgConnection,
+    guild: i64,
+    kind: PoolKind,
+) -> QueryResult<Vec<(String, u32)>> {
+    let rows = response_pools::table
+        .filter(response_pools::guild_id.eq(guild))
+        .filter(response_pools::pool.eq(kind.key()))
+        .order(response_pools::id.asc())
+        .select((response_pools::item, response_pools::weight))
+        .load::<(String, i32)>(conn)?;
+    Ok(rows
+        .into_iter()
+        .map(|(item, weight)| (item, weight.max(1) as u32))
+        .collect())
+}
+
+/// The `(replace_defaults, selection)` settings for a pool.
+fn pool_settings(
+    conn: &mut PgConnection,
+    guild: i64,
+    kind: PoolKind,
+) -> QueryResult<(bool, Selection)> {
+    let settings = response_pool_settings::table
+        .filter(response_pool_settings::guild_id.eq(guild))
+        .filter(response_pool_settings::pool.eq(kind.key()))
+        .select((
+            response_pool_settings::replace_defaults,
+            response_pool_settings::selection,
+        ))
+        .first::<(bool, String)>(conn)
+        .optional()?;
+    Ok(settings
+        .map(|(replace, selection)| (replace, Selection::from_key(&selection)))
+        .unwrap_or((false, Selection::Random)))
+}
+
+/// The weighted responses a guild currently draws from for `kind`, and how it picks one.
+pub(crate) fn load_responses(
+    conn: &mut PgConnection,
+    guild: i64,
+    kind: PoolKind,
+) -> QueryResult<(Vec<(String, u32)>, Selection)> {
+    let custom = custom_items(conn, guild, kind)?;
+    let (replace, selection) = pool_settings(conn, guild, kind)?;
+    Ok((merge_responses(kind.defaults(), custom, replace), selection))
+}
+
+/// Draw the next entry from a guild's persisted shuffle bag.
+pub(crate) fn draw_from_bag(
+    conn: &mut PgConnection,
+    guild: i64,
+    kind: PoolKind,
+    items: &[(String, u32)],
+) -> QueryResult<Option<String>> {
+    conn.transaction(|conn| {
+        let stored = shuffle_bags::table
+            .filter(shuffle_bags::guild_id.eq(guild))
+            .filter(shuffle_bags::pool.eq(kind.key()))
+            .select(shuffle_bags::remaining)
+            .for_update()
+            .first::<String>(conn)
+            .optional()?;
+        let mut bag: ShuffleBag = stored
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        let pick = bag.draw(items, &mut rand::thread_rng());
+        let remaining = serde_json::to_string(&bag).unwrap_or_else(|_| "{}".to_string());
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(shuffle_bags::table)
+            .values((
+                shuffle_bags::guild_id.eq(guild),
+                shuffle_bags::pool.eq(kind.key()),
+                shuffle_bags::remaining.eq(&remaining),
+                shuffle_bags::updated_at.eq(now),
+            ))
+            .on_conflict((shuffle_bags::guild_id, shuffle_bags::pool))
+            .do_update()
+            .set((
+                shuffle_bags::remaining.eq(&remaining),
+                shuffle_bags::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        Ok(pick)
+    })
+}
+
+fn pick_for_guild(
+    conn: &mut PgConnection,
+    guild: i64,
+    kind: PoolKind,
+) -> QueryResult<Option<String>> {
+    let (items, selection) = load_responses(conn, guild, kind)?;
+    match selection {
+        Selection::Random => Ok(weighted_choice(&items, &mut rand::thread_rng()).cloned()),
+        Selection::Shuffle => draw_from_bag(conn, guild, kind, &items),
+    }
+}
+
+/// Pick a response for a command invocation. Outside a server, or if the
+/// database can't be reached, this falls back to the built-in list.
+pub(crate) fn pick_response(
+    ctx: &Context<'_, crate::Data, crate::Error>,
+    kind: PoolKind,
+) -> String {
+    let fallback = || random_choice(kind.defaults()).unwrap().to_string();
+    let Some(guild) = ctx.guild_id() else {
+        return fallback();
+    };
+    let picked = ctx
+        .data()
+        .db_pool
+        .get()
+        .map_err(crate::Error::from)
+        .and_then(|mut conn| {
+            pick_for_guild(&mut conn, guild.get() as i64, kind).map_err(crate::Error::from)
+        });
+    match picked {
+        Ok(Some(response)) => response,
+        Ok(None) => fallback(),
+        Err(e) => {
+            warn!("Falling back to built-in {} responses: {}", kind.key(), e);
+            fallback()
+        }
+    }
+}
+
+/// Show an entry with its weight, if it isn't the default of 1.
+fn describe_entry(item: &str, weight: u32) -> String {
+    if weight == 1 {
+        item.to_string()
+    } else {
+        format!("{} (x{})", item, weight)
+    }
+}
+
+/// Customise the responses used by /food, /drink, /ball and /botsnack.
+/// Usage: /pool add food Dumplings
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("add", "remove", "list", "reset", "mode", "selection"),
+    subcommand_required
+)]
+pub async fn pool(_ctx: Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    Ok(())
+}
+
+/// Add a response to this server's pool. Append `:N` to give it weight N.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn add(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    pool: PoolKind,
+    #[rest] item: String,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let (item, weight) = parse_weighted(item.trim());
+    if item.is_empty() || item.chars().count() > MAX_ITEM_LEN {
+        ctx.say(format!(
+            "Entries must be between 1 and {} characters.",
+            MAX_ITEM_LEN
+        ))
+        .await?;
+        return Ok(());
+    }
+    let mut conn = ctx.data().db_pool.get()?;
+    diesel::insert_into(response_pools::table)
+        .values(&NewResponsePoolItem {
+            guild_id: guild,
+            pool: pool.key(),
+            item,
+            user_id: ctx.author().id.get() as i64,
+            created_at: Utc::now().naive_utc(),
+            weight: weight as i32,
+        })
+        .on_conflict((
+            response_pools::guild_id,
+            response_pools::pool,
+            response_pools::item,
+        ))
+        .do_update()
+        .set(response_pools::weight.eq(weight as i32))
+        .execute(&mut conn)?;
+    ctx.say(format!(
+        "Added '{}' to the {} pool.",
+        describe_entry(item, weight),
+        pool.key()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Remove a response from this server's pool.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn remove(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    pool: PoolKind,
+    #[rest] item: String,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let item = item.trim();
+    let mut conn = ctx.data().db_pool.get()?;
+    let removed = diesel::delete(
+        response_pools::table
+            .filter(response_pools::guild_id.eq(guild))
+            .filter(response_pools::pool.eq(pool.key()))
+            .filter(response_pools::item.eq(item)),
+    )
+    .execute(&mut conn)?;
+    if removed > 0 {
+        ctx.say(format!("Removed '{}' from the {} pool.", item, pool.key()))
+            .await?;
+    } else {
+        ctx.say(format!(
+            "'{}' isn't a custom entry in the {} pool.",
+            item,
+            pool.key()
+        ))
+        .await?;
+    }
+    Ok(())
+}
+
+/// Show the responses this server currently uses.
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn list(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    pool: PoolKind,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    let custom = custom_items(&mut conn, guild, pool)?;
+    let (replace, selection) = pool_settings(&mut conn, guild, pool)?;
+    let mode = if replace { "replace" } else { "extend" };
+    let mut msg = format!(
+        "{} pool (mode: {}, selection: {})\nCustom entries: ",
+        pool.key(),
+        mode,
+        selection.key()
+    );
+    let show_defaults = !replace || custom.is_empty();
+    let defaults_label = "\nBuilt-in entries: ";
+    // Split what's left of the reply between the two lists.
+    let mut room = MAX_REPLY_LEN - msg.chars().count();
+    if show_defaults {
+        room = (room - defaults_label.len()) / 2;
+    }
+    if custom.is_empty() {
+        msg.push_str("none");
+    } else {
+        let entries: Vec<String> = custom
+            .iter()
+            .map(|(item, weight)| describe_entry(item, *weight))
+            .collect();
+        msg.push_str(&join_within(&entries, room));
+    }
+    if show_defaults {
+        let defaults: Vec<String> = pool
+            .defaults()
+            .iter()
+            .map(|item| item.to_string())
+            .collect();
+        msg.push_str(defaults_label);
+        msg.push_str(&join_within(&defaults, room));
+    }
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Remove all custom entries and settings for a pool.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn reset(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    pool: PoolKind,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    conn.transaction(|conn| {
+        diesel::delete(
+            response_pools::table
+                .filter(response_pools::guild_id.eq(guild))
+                .filter(response_pools::pool.eq(pool.key())),
+        )
+        .execute(conn)?;
+        diesel::delete(
+            response_pool_settings::table
+                .filter(response_pool_settings::guild_id.eq(guild))
+                .filter(response_pool_settings::pool.eq(pool.key())),
+        )
+        .execute(conn)?;
+        diesel::delete(
+            shuffle_bags::table
+                .filter(shuffle_bags::guild_id.eq(guild))
+                .filter(shuffle_bags::pool.eq(pool.key())),
+        )
+        .execute(conn)
+    })?;
+    ctx.say(format!(
+        "The {} pool is back to the built-in responses.",
+        pool.key()
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Choose whether custom entries extend or replace the built-in responses.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn mode(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    pool: PoolKind,
+    mode: PoolMode,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let replace = mode == PoolMode::Replace;
+    let mut conn = ctx.data().db_pool.get()?;
+    diesel::insert_into(response_pool_settings::table)
+        .values((
+            response_pool_settings::guild_id.eq(guild),
+            response_pool_settings::pool.eq(pool.key()),
+            response_pool_settings::replace_defaults.eq(replace),
+        ))
+        .on_conflict((
+            response_pool_settings::guild_id,
+            response_pool_settings::pool,
+        ))
+        .do_update()
+        .set(response_pool_settings::replace_defaults.eq(replace))
+        .execute(&mut conn)?;
+    let msg = if replace {
+        format!(
+            "Custom entries now replace the built-in {} responses.",
+            pool.key()
+        )
+    } else {
+        format!(
+            "Custom entries are now added to the built-in {} responses.",
+            pool.key()
+        )
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Choose whether a pool picks independently or works through a shuffled bag.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn selection(
+    ctx: Context<'_, crate::Data, crate::Error>,
+    pool: PoolKind,
+    selection: Selection,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let mut conn = ctx.data().db_pool.get()?;
+    diesel::insert_into(response_pool_settings::table)
+        .values((
+            response_pool_settings::guild_id.eq(guild),
+            response_pool_settings::pool.eq(pool.key()),
+            response_pool_settings::selection.eq(selection.key()),
+        ))
+        .on_conflict((
+            response_pool_settings::guild_id,
+            response_pool_settings::pool,
+        ))
+        .do_update()
+        .set(response_pool_settings::selection.eq(selection.key()))
+        .execute(&mut conn)?;
+    let msg = match selection {
+        Selection::Random => format!("The {} pool now picks at random.", pool.key()),
+        Selection::Shuffle => format!(
+            "The {} pool now serves every entry before repeating.",
+            pool.key()
+        ),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(pairs: &[(&str, u32)]) -> Vec<(String, u32)> {
+        pairs.iter().map(|(s, w)| (s.to_string(), *w)).collect()
+    }
+
+    #[test]
+    fn test_join_within() {
+        let items: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(join_within(&items, 100), "a, b, c");
+
+        let long: Vec<String> = (0..500).map(|i| format!("entry number {}", i)).collect();
+        let joined = join_within(&long, 1000);
+        assert!(joined.chars().count() <= 1000);
+        assert!(joined.starts_with("entry number 0, entry number 1, "));
+        assert!(joined.ends_with(" more"));
+        let shown = joined.matches("entry number").count();
+        assert!(joined.ends_with(&format!("…and {} more", 500 - shown)));
+    }
+
+    #[test]
+    fn test_merge_extends_defaults() {
+        let merged = merge_responses(&["a", "b"], entries(&[("c", 1)]), false);
+        assert_eq!(merged, entries(&[("a", 1), ("b", 1), ("c", 1)]));
+    }
+
+    #[test]
+    fn test_merge_overrides_default_weight() {
+        let merged = merge_responses(&["a", "b"], entries(&[("b", 4)]), false);
+        assert_eq!(merged, entries(&[("a", 1), ("b", 4)]));
+    }
+
+    #[test]
+    fn test_merge_replaces_defaults() {
+        let merged = merge_responses(&["a", "b"], entries(&[("c", 2)]), true);
+        assert_eq!(merged, entries(&[("c", 2)]));
+    }
+
+    #[test]
+    fn test_empty_replacement_falls_back_to_defaults() {
+        let merged = merge_responses(&["a", "b"], Vec::new(), true);
+        assert_eq!(merged, entries(&[("a", 1), ("b", 1)]));
+    }
+
+    #[test]
+    fn test_selection_keys_round_trip() {
+        for selection in [Selection::Random, Selection::Shuffle] {
+            assert_eq!(Selection::from_key(selection.key()), selection);
+        }
+        assert_eq!(Selection::from_key("unknown"), Selection::Random);
+    }
+
+    #[test]
+    fn test_describe_entry() {
+        assert_eq!(describe_entry("Pizza", 1), "Pizza");
+        assert_eq!(describe_entry("Pizza", 3), "Pizza (x3)");
+    }
+
+    #[test]
+    fn test_pool_defaults() {
+        assert!(PoolKind::Food.defaults().contains(&"Pizza"));
+        assert!(!PoolKind::Drink.defaults().is_empty());
+        assert!(!PoolKind::Ball.defaults().is_empty());
+        assert!(!PoolKind::Botsnack.defaults().is_empty());
+    }
+}
@@ -1,24 +1,188 @@
-use rand::prelude::IteratorRandom;
+use crate::utils::dice::DiceExpr;
+use crate::utils::random::{split_weighted_choices, weighted_choice, weighted_sample, SplitError};
+use poise::serenity_prelude::CreateAllowedMentions;
+use poise::CreateReply;
+use rand::seq::SliceRandom;
+use rand::Rng;
 
-/// Choose a random item from a list of choices.
-/// Usage: /random apple orange banana
-#[poise::command(slash_command, prefix_command)]
+/// Most choices accepted in one command.
+pub(crate) const MAX_CHOICES: usize = 50;
+/// Longest reply Discord will accept.
+const MAX_REPLY_LEN: usize = 2000;
+
+/// Split input into weighted choices. See [`split_weighted_choices`] for the
+/// quoting rules; each choice may end in `:N` to make it N times as likely.
+pub(crate) fn parse_choices(input: &str) -> Result<Vec<(String, u32)>, SplitError> {
+    split_weighted_choices(input)
+}
+
+/// Format a numbered list, one entry per line.
+fn numbered(items: &[&String]) -> String {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| format!("{}. {}", i + 1, item))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reply with user-supplied text without pinging anyone.
+async fn reply(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    content: String,
+) -> Result<(), crate::Error> {
+    let content = if content.chars().count() > MAX_REPLY_LEN {
+        "That's too long to post, try fewer or shorter choices.".to_string()
+    } else {
+        content
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Parse the choices, replying with the reason if they are unusable.
+async fn load_choices(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    input: &str,
+) -> Result<Option<Vec<(String, u32)>>, crate::Error> {
+    let choices = match parse_choices(input) {
+        Ok(choices) => choices,
+        Err(e) => {
+            ctx.say(format!("Couldn't read those choices: {}", e))
+                .await?;
+            return Ok(None);
+        }
+    };
+    if choices.is_empty() {
+        ctx.say("Why u no args?!").await?;
+        return Ok(None);
+    }
+    if choices.len() > MAX_CHOICES {
+        ctx.say(format!("Give me at most {} choices.", MAX_CHOICES))
+            .await?;
+        return Ok(None);
+    }
+    Ok(Some(choices))
+}
+
+/// Pick from a list of choices, shuffle them, or roll dice.
+/// Usage: /random choose pizza:3 tacos
+///
+/// Quote choices containing spaces, or separate them with commas. Append
+/// `:N` to a choice to make it N times as likely. As a prefix command the
+/// choices can follow `random` directly, e.g. `@bot random pizza tacos`;
+/// Discord doesn't let a slash command with subcommands take options, so
+/// there it's always `/random choose`.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    subcommands("choose", "pick", "shuffle", "number", "dice")
+)]
 pub async fn random(
     ctx: poise::Context<'_, crate::Data, crate::Error>,
+    // Only reachable as a prefix command, see above.
+    #[rest] choices: Option<String>,
+) -> Result<(), crate::Error> {
+    choose_one(ctx, choices.as_deref().unwrap_or_default()).await
+}
+
+async fn choose_one(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    input: &str,
+) -> Result<(), crate::Error> {
+    let Some(choices) = load_choices(ctx, input).await? else {
+        return Ok(());
+    };
+    let choice = weighted_choice(&choices, &mut rand::thread_rng())
+        .cloned()
+        .unwrap_or_default();
+    reply(ctx, choice).await
+}
+
+/// Choose one item from a list of choices.
+/// Usage: /random choose "Thai food" pizza:3 tacos
+#[poise::command(slash_command, prefix_command)]
+pub async fn choose(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Choices, quoted or comma-separated; add :N to weight one"]
+    #[rest]
+    choices: String,
+) -> Result<(), crate::Error> {
+    choose_one(ctx, &choices).await
+}
+
+/// Draw several different items from a list of choices.
+/// Usage: /random pick 2 "Thai food" pizza tacos
+#[poise::command(slash_command, prefix_command)]
+pub async fn pick(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[min = 1]
+    #[max = 50]
+    count: usize,
     #[rest] choices: String,
 ) -> Result<(), crate::Error> {
-    let choices_vec: Vec<&str> = choices.split_whitespace().collect();
-    let thing = choices_vec.iter().choose(&mut rand::rng());
-    match thing {
-        Some(choice) => ctx.say(*choice).await?,
-        None => ctx.say("Why u no args?!").await?,
+    let Some(choices) = load_choices(ctx, &choices).await? else {
+        return Ok(());
     };
+    let picked = weighted_sample(&choices, count, &mut rand::thread_rng());
+    reply(ctx, numbered(&picked)).await
+}
+
+/// Put a list of choices in random order.
+/// Usage: /random shuffle alice bob carol
+#[poise::command(slash_command, prefix_command)]
+pub async fn shuffle(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[rest] choices: String,
+) -> Result<(), crate::Error> {
+    let Some(choices) = load_choices(ctx, &choices).await? else {
+        return Ok(());
+    };
+    let mut order: Vec<&String> = choices.iter().map(|(item, _)| item).collect();
+    order.shuffle(&mut rand::thread_rng());
+    reply(ctx, numbered(&order)).await
+}
+
+/// Pick a whole number between two bounds, inclusive.
+/// Usage: /random number 1 100
+#[poise::command(slash_command, prefix_command)]
+pub async fn number(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    min: i64,
+    max: i64,
+) -> Result<(), crate::Error> {
+    let (low, high) = if min <= max { (min, max) } else { (max, min) };
+    let value = rand::thread_rng().gen_range(low..=high);
+    ctx.say(value.to_string()).await?;
     Ok(())
 }
 
+/// Roll dice, e.g. 3d6+2, d20, 4d6kh3 or 2d10-1d4.
+/// Usage: /random dice 3d6+2
+#[poise::command(slash_command, prefix_command)]
+pub async fn dice(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[rest] expression: String,
+) -> Result<(), crate::Error> {
+    let expr = match DiceExpr::parse(&expression) {
+        Ok(expr) => expr,
+        Err(e) => {
+            ctx.say(format!("Couldn't roll that: {}", e)).await?;
+            return Ok(());
+        }
+    };
+    let roll = expr.roll(&mut rand::thread_rng());
+    reply(ctx, format!("{}: {}", expr, roll.breakdown())).await
+}
+
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
     use crate::utils::random_choice;
 
     #[test]
@@ -36,4 +200,39 @@ mod tests {
         assert!(result.is_some());
         assert!(choices_vec.contains(result.unwrap()));
     }
+
+    #[test]
+    fn test_weighted_choices() {
+        let weighted = parse_choices("foo bar:3 baz").unwrap();
+        let names: Vec<(&str, u32)> = weighted.iter().map(|(s, w)| (s.as_str(), *w)).collect();
+        assert_eq!(names, vec![("foo", 1), ("bar", 3), ("baz", 1)]);
+        let result = weighted_choice(&weighted, &mut rand::thread_rng()).unwrap();
+        assert!(["foo", "bar", "baz"].contains(&result.as_str()));
+    }
+
+    #[test]
+    fn test_quoted_and_comma_choices() {
+        let quoted = parse_choices(r#""Thai food":2 pizza"#).unwrap();
+        assert_eq!(
+            quoted,
+            vec![("Thai food".to_string(), 2), ("pizza".to_string(), 1)]
+        );
+        let commas = parse_choices("Thai food, pizza").unwrap();
+        assert_eq!(
+            commas,
+            vec![("Thai food".to_string(), 1), ("pizza".to_string(), 1)]
+        );
+        assert_eq!(
+            parse_choices(r#""Thai:3" pizza"#).unwrap(),
+            vec![("Thai:3".to_string(), 1), ("pizza".to_string(), 1)]
+        );
+        assert!(parse_choices("\"oops").is_err());
+    }
+
+    #[test]
+    fn test_numbered() {
+        let a = "a".to_string();
+        let b = "b".to_string();
+        assert_eq!(numbered(&[&a, &b]), "1. a\n2. b");
+    }
 }
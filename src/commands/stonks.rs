@@ -6,31 +6,68 @@
 
 // use crate::AlphaVantageApiToken;
 
-use plotters::prelude::*;
+use self::assets::{canonical_symbol, AssetClass};
+use self::chart::{
+    align, render_comparison_chart, render_price_chart, ChartOptions, ChartStyle, SmaOverlay,
+};
+use self::market::{Bar, Interval, MarketDataProvider, MarketError, TimeRange};
+use self::symbols::autocomplete_tickers;
 use poise::serenity_prelude::CreateAttachment;
-use serde::Deserialize;
-use std::env;
 
-#[derive(Deserialize, Debug)]
-struct TimeSeriesDaily {
-    #[serde(rename = "Time Series (Daily)")]
-    time_series: std::collections::BTreeMap<String, DailyData>,
-}
-
-#[derive(Deserialize, Debug)]
-struct DailyData {
-    #[serde(rename = "4. close")]
-    close: String,
-}
+pub mod assets;
+pub mod calendar;
+pub mod chart;
+pub mod currency;
+pub mod market;
+pub mod paper;
+pub mod quote;
+pub mod summary;
+pub mod symbols;
+pub mod watch;
 
 /// Show a Finviz chart for a ticker symbol.
 /// Usage: /stonks AAPL
 #[poise::command(slash_command, prefix_command)]
 pub async fn stonks(
     ctx: poise::Context<'_, crate::Data, crate::Error>,
-    #[rest] tickers: String,
+    #[description = "Ticker symbols"]
+    #[autocomplete = "autocomplete_tickers"]
+    #[rest]
+    tickers: String,
 ) -> Result<(), crate::Error> {
-    for stonk in tickers.split_whitespace() {
+    let symbols: Vec<String> = tickers
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|ticker| !ticker.is_empty())
+        .map(str::to_uppercase)
+        .collect();
+    let unlisted = |symbol: &String| {
+        AssetClass::detect(symbol) == AssetClass::Equity && !ctx.data().symbols.contains(symbol)
+    };
+    if symbols.iter().any(unlisted) {
+        ctx.defer().await?;
+    }
+    for stonk in symbols {
+        if AssetClass::detect(&stonk) != AssetClass::Equity {
+            ctx.say(format!("Finviz only charts stocks, try /graph {}", stonk))
+                .await?;
+            continue;
+        }
+        // The directory can be short of symbols, so ask the provider before
+        // linking one it doesn't have.
+        if unlisted(&stonk) {
+            match ctx.data().market_data.quote(&stonk).await {
+                Ok(_) => {}
+                Err(MarketError::UnknownSymbol(_)) => {
+                    ctx.say(ctx.data().symbols.unknown_message(&stonk)).await?;
+                    continue;
+                }
+                Err(e) => {
+                    ctx.say(format!("Couldn't check '{}': {}", stonk, e))
+                        .await?;
+                    continue;
+                }
+            }
+        }
         ctx.say(format!(
             "https://www.finviz.com/chart.ashx?t={}&ty=c&ta=1&p=d&s=l",
             stonk
@@ -40,126 +77,213 @@ pub async fn stonks(
     Ok(())
 }
 
-/// Compare a ticker to the S&P 500.
-/// Usage: /stonkcomp AAPL
+/// Index every comparison is drawn against.
+pub(crate) const BENCHMARK: &str = "SPY";
+/// Most symbols on one comparison chart, including the benchmark.
+pub(crate) const MAX_COMPARED: usize = 6;
+
+/// Split a range such as `5y` out of the ticker list.
+fn split_range(tickers: &str) -> (String, Option<TimeRange>) {
+    let mut range = None;
+    let mut rest = Vec::new();
+    for token in tickers.split_whitespace() {
+        match poise::ChoiceParameter::from_name(&token.to_lowercase()) {
+            Some(found) if range.is_none() => range = Some(found),
+            _ => rest.push(token),
+        }
+    }
+    (rest.join(" "), range)
+}
+
+/// The symbols to compare: the given tickers, uppercased and deduplicated,
+/// followed by [`BENCHMARK`] unless it was already listed.
+fn comparison_symbols(tickers: &str) -> Result<Vec<String>, String> {
+    let mut symbols: Vec<String> = Vec::new();
+    for ticker in tickers.split(|c: char| c.is_whitespace() || c == ',') {
+        let ticker = canonical_symbol(ticker);
+        if !ticker.is_empty() && !symbols.contains(&ticker) {
+            symbols.push(ticker);
+        }
+    }
+    if symbols.is_empty() {
+        return Err("Give me at least one ticker to compare".to_string());
+    }
+    if !symbols.iter().any(|symbol| symbol == BENCHMARK) {
+        symbols.push(BENCHMARK.to_string());
+    }
+    if symbols.len() > MAX_COMPARED {
+        return Err(format!(
+            "I can only compare up to {} symbols at once",
+            MAX_COMPARED
+        ));
+    }
+    Ok(symbols)
+}
+
+/// Compare tickers to each other and the S&P 500 by percent change.
+/// Usage: /stonkcomp AAPL MSFT [range]
+///
+/// The range is one of 5d, 1m, 3m, 6m, ytd, 1y (the default), 5y or max.
 #[poise::command(slash_command, prefix_command)]
 pub async fn stonkcomp(
     ctx: poise::Context<'_, crate::Data, crate::Error>,
-    #[rest] tickers: String,
+    #[description = "Tickers to compare and optionally a range, e.g. AAPL MSFT 5y"]
+    #[autocomplete = "autocomplete_tickers"]
+    #[rest]
+    tickers: String,
 ) -> Result<(), crate::Error> {
-    for stonk in tickers.split_whitespace() {
-        ctx.say(format!("https://stonks.egd.pw/spcomp?symbol={}", stonk))
+    let (tickers, range) = split_range(&tickers);
+    let symbols = match comparison_symbols(&tickers) {
+        Ok(symbols) => symbols,
+        Err(message) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+    };
+    let range = range.unwrap_or(TimeRange::OneYear);
+    let interval = range.default_interval();
+    ctx.defer().await?;
+
+    let mut series = Vec::with_capacity(symbols.len());
+    for symbol in &symbols {
+        match ctx.data().market_data.series(symbol, interval, range).await {
+            Ok(mut bars) => {
+                range.trim(&mut bars);
+                series.push(bars);
+            }
+            Err(MarketError::UnknownSymbol(_)) => {
+                ctx.say(ctx.data().symbols.unknown_message(symbol)).await?;
+                return Ok(());
+            }
+            Err(e) => {
+                ctx.say(format!("{}: {}", symbol, e)).await?;
+                return Ok(());
+            }
+        }
+    }
+    align(&mut series);
+    if series.iter().any(|bars| bars.is_empty()) {
+        ctx.say("Those symbols have no prices in common for that range")
             .await?;
+        return Ok(());
     }
+
+    let title = format!("{} ({})", symbols.join(" vs "), range.label());
+    let named: Vec<(String, Vec<Bar>)> = symbols.iter().cloned().zip(series).collect();
+    let png = render_comparison_chart(&title, &named)?;
+    ctx.send(
+        poise::CreateReply::default().attachment(CreateAttachment::bytes(
+            png,
+            format!("{}_comp.png", symbols.join("_")),
+        )),
+    )
+    .await?;
     Ok(())
 }
 
-/// Show a graph of a stock's daily closing prices.
-/// Usage: /graph AAPL
+/// Show a chart of a stock's, coin's or currency pair's prices.
+/// Usage: /graph AAPL [options]
+///
+/// Pairs work too, e.g. /graph BTC-USD or /graph EUR-USD.
 #[poise::command(slash_command, prefix_command)]
 pub async fn graph(
     ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Ticker symbol"]
+    #[autocomplete = "autocomplete_tickers"]
     ticker: String,
+    #[description = "How far back to go (default 6m)"] range: Option<TimeRange>,
+    #[description = "Time per bar (default depends on the range)"] interval: Option<Interval>,
+    #[description = "Line or candlestick chart"] style: Option<ChartStyle>,
+    #[description = "Show volume bars underneath"] volume: Option<bool>,
+    #[description = "Moving averages to overlay"] sma: Option<SmaOverlay>,
 ) -> Result<(), crate::Error> {
-    let api_key = env::var("ALPHAVANTAGE_API_KEY")
-        .map_err(|_| "ALPHAVANTAGE_API_KEY not set in environment")?;
-    
-    let url = format!(
-        "https://www.alphavantage.co/query?function=TIME_SERIES_DAILY&symbol={}&apikey={}",
-        ticker, api_key
-    );
-    
-    let resp = reqwest::get(&url)
-        .await?
-        .json::<TimeSeriesDaily>()
-        .await
-        .map_err(|e| format!("Failed to fetch or parse stock data: {}", e))?;
-
-    if resp.time_series.is_empty() {
-        return Err("No data available for the specified ticker".into());
-    }
-
-    let mut dates: Vec<_> = resp.time_series.iter().collect();
-    dates.sort_by_key(|(date, _)| *date);
-    
-    let closes: Vec<f64> = dates
-        .iter()
-        .map(|(_, data)| data.close.parse::<f64>().unwrap_or(0.0))
-        .collect();
-
-    if closes.is_empty() {
-        return Err("No valid closing prices found".into());
-    }
-
-    let date_labels: Vec<&str> = dates.iter().map(|(d, _)| d.as_str()).collect();
-
-    // Plot to a buffer
-    let mut buf = String::new();
-    {
-        let root = SVGBackend::with_string(&mut buf, (800, 480)).into_drawing_area();
-        root.fill(&WHITE)?;
-        
-        let min = closes.iter().cloned().fold(f64::INFINITY, f64::min);
-        let max = closes.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
-        
-        let mut chart = ChartBuilder::on(&root)
-            .caption(format!("{} Closing Prices", ticker), ("monospace", 30))
-            .margin(20)
-            .x_label_area_size(40)
-            .y_label_area_size(60)
-            .build_cartesian_2d(0..closes.len(), min..max)?;
-
-        chart
-            .configure_mesh()
-            .x_labels(10)
-            .x_label_formatter(&|idx| date_labels[*idx].to_string())
-            .y_desc("Close")
-            .x_desc("Date")
-            .draw()?;
-
-        chart.draw_series(LineSeries::new(
-            closes.iter().enumerate().map(|(i, v)| (i, *v)),
-            &BLUE,
-        ))?;
-
-        root.present()?;
+    let ticker = canonical_symbol(&ticker);
+    let range = range.unwrap_or(TimeRange::SixMonths);
+    let interval = interval.unwrap_or_else(|| range.default_interval());
+    if !range.supports(interval) {
+        ctx.say("Intraday prices only go back a month, pick a shorter range")
+            .await?;
+        return Ok(());
     }
+    let options = ChartOptions {
+        style: style.unwrap_or_default(),
+        volume: volume.unwrap_or(false),
+        sma: sma.unwrap_or_default(),
+    };
+    // Fetch enough bars before the range to start each average at its edge.
+    let lookback = options.sma.windows().iter().max().copied().unwrap_or(0);
+    let series = ctx
+        .data()
+        .market_data
+        .series_with_lookback(&ticker, interval, range, lookback)
+        .await;
+    let bars = match series {
+        Ok(bars) if !bars.is_empty() => bars,
+        Ok(_) => {
+            ctx.say("No data available for the specified ticker")
+                .await?;
+            return Ok(());
+        }
+        Err(MarketError::UnknownSymbol(_)) => {
+            ctx.say(ctx.data().symbols.unknown_message(&ticker)).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            ctx.say(e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let start = range.start(&bars);
+    let title = format!("{} ({}, {})", ticker, range.label(), interval.key());
+    let png = render_price_chart(&title, &bars, start, &options)?;
 
-    // Send as attachment
     ctx.send(
         poise::CreateReply::default().attachment(CreateAttachment::bytes(
-            buf.into_bytes(),
-            format!("{}_graph.svg", ticker),
+            png,
+            format!("{}_graph.png", ticker),
         )),
     )
     .await?;
-    
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
 
     #[test]
-    fn test_daily_data_parse() {
-        let data = json!({"4. close": "123.45"});
-        let daily: DailyData = serde_json::from_value(data).unwrap();
-        assert_eq!(daily.close, "123.45");
+    fn test_comparison_symbols() {
+        assert_eq!(
+            comparison_symbols("aapl msft, AAPL").unwrap(),
+            vec!["AAPL", "MSFT", "SPY"]
+        );
+        assert_eq!(comparison_symbols("SPY qqq").unwrap(), vec!["SPY", "QQQ"]);
+        assert_eq!(
+            comparison_symbols("btc/usd").unwrap(),
+            vec!["BTC-USD", "SPY"]
+        );
+        assert!(comparison_symbols("  ").is_err());
+        assert!(comparison_symbols("A B C D E F").is_err());
+        assert_eq!(comparison_symbols("A B C D E").unwrap().len(), MAX_COMPARED);
     }
 
     #[test]
-    fn test_time_series_daily_parse() {
-        let data = json!({
-            "Time Series (Daily)": {
-                "2023-01-01": {"4. close": "100.0"},
-                "2023-01-02": {"4. close": "110.0"}
-            }
-        });
-        let ts: TimeSeriesDaily = serde_json::from_value(data).unwrap();
-        assert_eq!(ts.time_series.len(), 2);
-        assert_eq!(ts.time_series["2023-01-01"].close, "100.0");
+    fn test_split_range() {
+        assert_eq!(
+            split_range("AAPL 5y MSFT"),
+            ("AAPL MSFT".to_string(), Some(TimeRange::FiveYears))
+        );
+        assert_eq!(
+            split_range("YTD aapl"),
+            ("aapl".to_string(), Some(TimeRange::YearToDate))
+        );
+        assert_eq!(split_range("AAPL MSFT"), ("AAPL MSFT".to_string(), None));
+        // Only the first range counts; later ones are left as tickers.
+        assert_eq!(
+            split_range("1m AAPL 1y"),
+            ("AAPL 1y".to_string(), Some(TimeRange::OneMonth))
+        );
     }
 
     #[tokio::test]
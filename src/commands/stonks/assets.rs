@@ -0,0 +1,121 @@
+//! Telling equities, crypto and currency pairs apart from their symbols.
+//!
+//! Pairs are written `BASE-QUOTE` (`BTC-USD`, `EUR-USD`); `EUR/USD` and
+//! `EURUSD=X` are accepted too. Anything that isn't a pair of known
+//! currency codes is treated as an equity ticker.
+
+/// Fiat currency codes recognised in pairs.
+pub const FIAT_CURRENCIES: &[&str] = &[
+    "AUD", "BRL", "CAD", "CHF", "CNY", "CZK", "DKK", "EUR", "GBP", "HKD", "HUF", "IDR", "ILS",
+    "INR", "ISK", "JPY", "KRW", "MXN", "MYR", "NOK", "NZD", "PHP", "PLN", "RON", "SEK", "SGD",
+    "THB", "TRY", "TWD", "USD", "ZAR",
+];
+
+/// Cryptocurrency codes recognised in pairs.
+pub const CRYPTO_CURRENCIES: &[&str] = &[
+    "ADA", "ATOM", "AVAX", "BCH", "BNB", "BTC", "DAI", "DOGE", "DOT", "ETC", "ETH", "LINK", "LTC",
+    "MATIC", "NEAR", "SHIB", "SOL", "TON", "TRX", "UNI", "USDC", "USDT", "XLM", "XMR", "XRP",
+];
+
+pub fn is_fiat(code: &str) -> bool {
+    FIAT_CURRENCIES.contains(&code)
+}
+
+pub fn is_crypto(code: &str) -> bool {
+    CRYPTO_CURRENCIES.contains(&code)
+}
+
+/// What kind of market a symbol trades in, which decides where its prices
+/// come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetClass {
+    Equity,
+    /// A coin priced in a fiat currency or another coin.
+    Crypto {
+        coin: String,
+        market: String,
+    },
+    /// One fiat currency priced in another.
+    Fx {
+        from: String,
+        to: String,
+    },
+}
+
+impl AssetClass {
+    /// Work out the asset class of `symbol`.
+    pub fn detect(symbol: &str) -> Self {
+        let symbol = symbol.trim().to_uppercase();
+        let pair = match symbol.strip_suffix("=X") {
+            Some(pair) if pair.len() == 6 && pair.is_ascii() => Some(pair.split_at(3)),
+            Some(pair) => pair.split_once(['-', '/']),
+            None => symbol.split_once(['-', '/']),
+        };
+        match pair {
+            Some((base, quote)) if is_crypto(base) && (is_fiat(quote) || is_crypto(quote)) => {
+                AssetClass::Crypto {
+                    coin: base.to_string(),
+                    market: quote.to_string(),
+                }
+            }
+            Some((base, quote)) if is_fiat(base) && is_fiat(quote) => AssetClass::Fx {
+                from: base.to_string(),
+                to: quote.to_string(),
+            },
+            _ => AssetClass::Equity,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AssetClass::Equity => "equity",
+            AssetClass::Crypto { .. } => "crypto",
+            AssetClass::Fx { .. } => "fx",
+        }
+    }
+}
+
+/// The symbol prices are stored and looked up under: `BASE-QUOTE` for pairs,
+/// the uppercased ticker otherwise.
+pub fn canonical_symbol(symbol: &str) -> String {
+    match AssetClass::detect(symbol) {
+        AssetClass::Equity => symbol.trim().to_uppercase(),
+        AssetClass::Crypto { coin, market } => format!("{}-{}", coin, market),
+        AssetClass::Fx { from, to } => format!("{}-{}", from, to),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let crypto = AssetClass::Crypto {
+            coin: "BTC".to_string(),
+            market: "USD".to_string(),
+        };
+        assert_eq!(AssetClass::detect("btc-usd"), crypto);
+        assert_eq!(AssetClass::detect("BTC/USD"), crypto);
+        assert_eq!(AssetClass::detect("ETH-BTC").label(), "crypto");
+
+        let fx = AssetClass::Fx {
+            from: "EUR".to_string(),
+            to: "USD".to_string(),
+        };
+        assert_eq!(AssetClass::detect("EUR/USD"), fx);
+        assert_eq!(AssetClass::detect("EURUSD=X"), fx);
+        assert_eq!(AssetClass::detect("eur-usd"), fx);
+
+        for equity in ["AAPL", "BRK-B", "BRK.B", "BTC", "USD-BTC", "EURUSD"] {
+            assert_eq!(AssetClass::detect(equity), AssetClass::Equity, "{}", equity);
+        }
+    }
+
+    #[test]
+    fn test_canonical_symbol() {
+        assert_eq!(canonical_symbol("btc/usd"), "BTC-USD");
+        assert_eq!(canonical_symbol("GBPJPY=X"), "GBP-JPY");
+        assert_eq!(canonical_symbol(" aapl "), "AAPL");
+    }
+}
@@ -0,0 +1,175 @@
+//! The New York Stock Exchange's full-day holidays, worked out from the
+//! rules rather than a hardcoded list so they never go stale.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::America::New_York;
+
+/// Easter Sunday in the Gregorian calendar (anonymous Gregorian algorithm).
+pub fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).expect("Easter is a valid date")
+}
+
+/// The `n`th `weekday` of a month, counting from 1.
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).expect("month has the weekday")
+}
+
+/// The last `weekday` of a month.
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    let last = nth_weekday(year, month, weekday, 4);
+    let next = last + Duration::weeks(1);
+    if next.month() == month {
+        next
+    } else {
+        last
+    }
+}
+
+/// Move a holiday that falls on a weekend to the nearest weekday.
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+/// Every day the exchange is closed for a holiday in `year`, in date order.
+pub fn holidays(year: i32) -> Vec<(NaiveDate, &'static str)> {
+    let date = |month, day| NaiveDate::from_ymd_opt(year, month, day).expect("valid holiday");
+    let mut days = Vec::new();
+    // A New Year's Day on a Saturday isn't moved back into the old year.
+    let new_year = date(1, 1);
+    if new_year.weekday() != Weekday::Sat {
+        days.push((observed(new_year), "New Year's Day"));
+    }
+    days.push((
+        nth_weekday(year, 1, Weekday::Mon, 3),
+        "Martin Luther King Jr. Day",
+    ));
+    days.push((
+        nth_weekday(year, 2, Weekday::Mon, 3),
+        "Washington's Birthday",
+    ));
+    days.push((easter(year) - Duration::days(2), "Good Friday"));
+    days.push((last_weekday(year, 5, Weekday::Mon), "Memorial Day"));
+    if year >= 2022 {
+        days.push((observed(date(6, 19)), "Juneteenth"));
+    }
+    days.push((observed(date(7, 4)), "Independence Day"));
+    days.push((nth_weekday(year, 9, Weekday::Mon, 1), "Labor Day"));
+    days.push((nth_weekday(year, 11, Weekday::Thu, 4), "Thanksgiving Day"));
+    days.push((observed(date(12, 25)), "Christmas Day"));
+    days
+}
+
+/// The holiday the exchange is closed for on `date`, if any.
+pub fn holiday(date: NaiveDate) -> Option<&'static str> {
+    holidays(date.year())
+        .into_iter()
+        .find(|(day, _)| *day == date)
+        .map(|(_, name)| name)
+}
+
+/// Whether the exchange is open on `date`.
+pub fn is_trading_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && holiday(date).is_none()
+}
+
+/// Whether the exchange is in its regular session, 9:30 to 16:00 New York
+/// time on a trading day.
+pub fn is_market_open(now: DateTime<Utc>) -> bool {
+    let local = now.with_timezone(&New_York);
+    let open = NaiveTime::from_hms_opt(9, 30, 0).expect("valid time");
+    let close = NaiveTime::from_hms_opt(16, 0, 0).expect("valid time");
+    is_trading_day(local.date_naive()) && (open..close).contains(&local.time())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_easter() {
+        assert_eq!(easter(2024), date("2024-03-31"));
+        assert_eq!(easter(2025), date("2025-04-20"));
+        assert_eq!(easter(2026), date("2026-04-05"));
+    }
+
+    #[test]
+    fn test_holidays_2024() {
+        let days: Vec<NaiveDate> = holidays(2024).into_iter().map(|(day, _)| day).collect();
+        let expected: Vec<NaiveDate> = [
+            "2024-01-01",
+            "2024-01-15",
+            "2024-02-19",
+            "2024-03-29",
+            "2024-05-27",
+            "2024-06-19",
+            "2024-07-04",
+            "2024-09-02",
+            "2024-11-28",
+            "2024-12-25",
+        ]
+        .into_iter()
+        .map(date)
+        .collect();
+        assert_eq!(days, expected);
+    }
+
+    #[test]
+    fn test_observed_holidays() {
+        // Christmas 2022 was a Sunday, Independence Day 2026 is a Saturday.
+        assert_eq!(holiday(date("2022-12-26")), Some("Christmas Day"));
+        assert_eq!(holiday(date("2026-07-03")), Some("Independence Day"));
+        // New Year's Day 2022 was a Saturday and wasn't observed.
+        assert!(is_trading_day(date("2021-12-31")));
+        assert_eq!(holiday(date("2021-06-18")), None);
+    }
+
+    #[test]
+    fn test_is_trading_day() {
+        assert!(is_trading_day(date("2024-03-28")));
+        assert!(!is_trading_day(date("2024-03-29")));
+        assert!(!is_trading_day(date("2024-03-30")));
+    }
+
+    #[test]
+    fn test_is_market_open() {
+        let at = |s: &str| {
+            NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+                .unwrap()
+                .and_utc()
+        };
+        // 9:30 and 16:00 New York time are 13:30 and 20:00 UTC in summer.
+        assert!(is_market_open(at("2024-03-28 13:30")));
+        assert!(is_market_open(at("2024-03-28 19:59")));
+        assert!(!is_market_open(at("2024-03-28 13:29")));
+        assert!(!is_market_open(at("2024-03-28 20:00")));
+        // And 14:30 UTC in winter.
+        assert!(!is_market_open(at("2024-01-16 14:00")));
+        assert!(is_market_open(at("2024-01-16 14:30")));
+        // Closed all day on Good Friday and weekends.
+        assert!(!is_market_open(at("2024-03-29 15:00")));
+        assert!(!is_market_open(at("2024-03-30 15:00")));
+    }
+}
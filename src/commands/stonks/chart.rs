@@ -0,0 +1,763 @@
+//! PNG price charts for the stock commands.
+
+use super::market::Bar;
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Timelike};
+use image::{ImageFormat, RgbImage};
+use plotters::coord::ranged1d::{KeyPointHint, NoDefaultFormatting, Ranged, ValueFormatter};
+use plotters::coord::types::RangedCoordf64;
+use plotters::prelude::*;
+use std::collections::BTreeSet;
+use std::io::Cursor;
+use std::ops::Range;
+
+/// Size of rendered charts, in pixels.
+pub const CHART_SIZE: (u32, u32) = (1000, 600);
+/// Size of sparklines, in pixels.
+pub const SPARKLINE_SIZE: (u32, u32) = (300, 60);
+/// Share of the chart height given to the price panel when volume is shown.
+const PRICE_PANEL_SHARE: f64 = 0.75;
+/// Most labels drawn along the time axis.
+const MAX_TIME_LABELS: usize = 8;
+
+/// How prices are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, poise::ChoiceParameter)]
+pub enum ChartStyle {
+    /// A line through the closes.
+    #[default]
+    #[name = "line"]
+    Line,
+    /// Open, high, low and close candles.
+    #[name = "candlestick"]
+    Candlestick,
+}
+
+/// Which simple moving averages to overlay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, poise::ChoiceParameter)]
+pub enum SmaOverlay {
+    #[default]
+    #[name = "none"]
+    None,
+    #[name = "20-day"]
+    Sma20,
+    #[name = "50-day"]
+    Sma50,
+    #[name = "200-day"]
+    Sma200,
+    #[name = "20, 50 and 200-day"]
+    All,
+}
+
+impl SmaOverlay {
+    /// The window lengths to draw, in days.
+    pub fn windows(self) -> &'static [usize] {
+        match self {
+            SmaOverlay::None => &[],
+            SmaOverlay::Sma20 => &[20],
+            SmaOverlay::Sma50 => &[50],
+            SmaOverlay::Sma200 => &[200],
+            SmaOverlay::All => &[20, 50, 200],
+        }
+    }
+}
+
+/// Everything that changes how a chart looks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChartOptions {
+    pub style: ChartStyle,
+    pub volume: bool,
+    pub sma: SmaOverlay,
+}
+
+/// Simple moving average of `values` over `window` points. Entries before a
+/// full window is available are `None`.
+pub fn sma(values: &[f64], window: usize) -> Vec<Option<f64>> {
+    if window == 0 {
+        return vec![None; values.len()];
+    }
+    let mut out = Vec::with_capacity(values.len());
+    let mut sum = 0.0;
+    for (i, value) in values.iter().enumerate() {
+        sum += value;
+        if i >= window {
+            sum -= values[i - window];
+        }
+        out.push((i + 1 >= window).then(|| sum / window as f64));
+    }
+    out
+}
+
+/// Moving averages of the closes in `history` for each of `windows`, lined
+/// up with the bars from `start` on. Averaging over the whole history lets a
+/// line begin at the first bar shown when there is enough history before
+/// it. Windows with no full average in view are left out.
+pub fn sma_overlays(
+    history: &[Bar],
+    start: usize,
+    windows: &[usize],
+) -> Vec<(usize, Vec<Option<f64>>)> {
+    let closes: Vec<f64> = history.iter().map(|bar| bar.close).collect();
+    let start = start.min(closes.len());
+    windows
+        .iter()
+        .filter_map(|&window| {
+            let shown = sma(&closes, window).split_off(start);
+            shown.iter().any(Option::is_some).then_some((window, shown))
+        })
+        .collect()
+}
+
+fn sma_colour(window: usize) -> RGBColor {
+    match window {
+        20 => RGBColor(255, 140, 0),
+        50 => RGBColor(148, 0, 211),
+        _ => RGBColor(105, 105, 105),
+    }
+}
+
+/// Spacing of labels on the time axis, chosen from the span of the chart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TickUnit {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl TickUnit {
+    fn for_span(days: i64, intraday: bool) -> Self {
+        match days {
+            ..=1 if intraday => TickUnit::Hour,
+            ..=14 => TickUnit::Day,
+            15..=92 => TickUnit::Week,
+            93..=730 => TickUnit::Month,
+            731..=1461 => TickUnit::Quarter,
+            _ => TickUnit::Year,
+        }
+    }
+
+    /// Times in the same bucket share a label.
+    fn bucket(self, time: NaiveDateTime) -> (i32, u32, u32) {
+        match self {
+            TickUnit::Hour => (time.year(), time.ordinal(), time.hour()),
+            TickUnit::Day => (time.year(), time.ordinal(), 0),
+            TickUnit::Week => {
+                let week = time.iso_week();
+                (week.year(), week.week(), 0)
+            }
+            TickUnit::Month => (time.year(), time.month(), 0),
+            TickUnit::Quarter => (time.year(), time.month0() / 3, 0),
+            TickUnit::Year => (time.year(), 0, 0),
+        }
+    }
+
+    fn format(self) -> &'static str {
+        match self {
+            TickUnit::Hour => "%H:%M",
+            TickUnit::Day | TickUnit::Week => "%b %d",
+            TickUnit::Month | TickUnit::Quarter => "%b %Y",
+            TickUnit::Year => "%Y",
+        }
+    }
+}
+
+/// Where to label the time axis: the index of the first bar of each hour,
+/// day, week, month, quarter or year depending on the span of `times`,
+/// thinned to at most [`MAX_TIME_LABELS`].
+pub fn time_ticks(times: &[NaiveDateTime]) -> Vec<(usize, String)> {
+    let (Some(first), Some(last)) = (times.first(), times.last()) else {
+        return Vec::new();
+    };
+    let intraday = times.iter().any(|time| time.time() != NaiveTime::MIN);
+    let unit = TickUnit::for_span((*last - *first).num_days(), intraday);
+    let mut ticks: Vec<usize> = (1..times.len())
+        .filter(|&i| unit.bucket(times[i]) != unit.bucket(times[i - 1]))
+        .collect();
+    if ticks.is_empty() {
+        ticks.push(0);
+    }
+    let step = ticks.len().div_ceil(MAX_TIME_LABELS);
+    ticks
+        .into_iter()
+        .step_by(step)
+        .map(|i| (i, times[i].format(unit.format()).to_string()))
+        .collect()
+}
+
+/// X axis over bar indices, labelled at [`time_ticks`] rather than at evenly
+/// spaced indices.
+#[derive(Debug, Clone)]
+struct TimeAxis {
+    bars: usize,
+    ticks: Vec<(usize, String)>,
+}
+
+impl TimeAxis {
+    fn new(times: &[NaiveDateTime]) -> Self {
+        Self {
+            bars: times.len(),
+            ticks: time_ticks(times),
+        }
+    }
+}
+
+impl Ranged for TimeAxis {
+    type FormatOption = NoDefaultFormatting;
+    type ValueType = f64;
+
+    fn map(&self, value: &f64, limit: (i32, i32)) -> i32 {
+        RangedCoordf64::from(self.range()).map(value, limit)
+    }
+
+    fn key_points<Hint: KeyPointHint>(&self, _hint: Hint) -> Vec<f64> {
+        self.ticks.iter().map(|(i, _)| *i as f64).collect()
+    }
+
+    fn range(&self) -> Range<f64> {
+        -0.5..self.bars as f64 - 0.5
+    }
+}
+
+impl ValueFormatter<f64> for TimeAxis {
+    fn format_ext(&self, value: &f64) -> String {
+        self.ticks
+            .iter()
+            .find(|(i, _)| *i as f64 == *value)
+            .map(|(_, label)| label.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// The lowest and highest price to plot, padded so lines don't touch the edges.
+fn price_bounds(bars: &[Bar], style: ChartStyle) -> (f64, f64) {
+    let (low, high) =
+        bars.iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(lo, hi), bar| match style {
+                ChartStyle::Line => (lo.min(bar.close), hi.max(bar.close)),
+                ChartStyle::Candlestick => (lo.min(bar.low), hi.max(bar.high)),
+            },
+        );
+    let pad = ((high - low) * 0.05)
+        .max(high.abs() * 0.001)
+        .max(f64::EPSILON);
+    (low - pad, high + pad)
+}
+
+/// Encode a raw RGB buffer as PNG.
+pub fn encode_png(rgb: Vec<u8>, (width, height): (u32, u32)) -> Result<Vec<u8>, crate::Error> {
+    let image = RgbImage::from_raw(width, height, rgb).ok_or("chart buffer has the wrong size")?;
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Render `bars` (oldest first) as a PNG chart titled `title`.
+/// Draw the bars of `history` from `start` on. The bars before `start` only
+/// feed the moving averages.
+pub fn render_price_chart(
+    title: &str,
+    history: &[Bar],
+    start: usize,
+    options: &ChartOptions,
+) -> Result<Vec<u8>, crate::Error> {
+    let bars = &history[start.min(history.len())..];
+    if bars.is_empty() {
+        return Err("No data to chart".into());
+    }
+    let (width, height) = CHART_SIZE;
+    let mut rgb = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut rgb, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let (price_area, volume_area) = if options.volume {
+            let split = (f64::from(height) * PRICE_PANEL_SHARE) as u32;
+            let (top, bottom) = root.split_vertically(split);
+            (top, Some(bottom))
+        } else {
+            (root.clone(), None)
+        };
+
+        let times: Vec<NaiveDateTime> = bars.iter().map(|bar| bar.time).collect();
+        let x_axis = TimeAxis::new(&times);
+        let (min, max) = price_bounds(bars, options.style);
+
+        let mut chart = ChartBuilder::on(&price_area)
+            .caption(title, ("sans-serif", 28))
+            .margin(20)
+            .x_label_area_size(if volume_area.is_some() { 0 } else { 40 })
+            .y_label_area_size(70)
+            .build_cartesian_2d(x_axis.clone(), min..max)?;
+        chart.configure_mesh().y_desc("Price").draw()?;
+
+        match options.style {
+            ChartStyle::Line => {
+                chart.draw_series(LineSeries::new(
+                    bars.iter()
+                        .enumerate()
+                        .map(|(i, bar)| (i as f64, bar.close)),
+                    &BLUE,
+                ))?;
+            }
+            ChartStyle::Candlestick => {
+                let candle_width =
+                    ((width as usize / bars.len().max(1)) as u32 * 7 / 10).clamp(1, 15);
+                chart.draw_series(bars.iter().enumerate().map(|(i, bar)| {
+                    CandleStick::new(
+                        i as f64,
+                        bar.open,
+                        bar.high,
+                        bar.low,
+                        bar.close,
+                        GREEN.filled(),
+                        RED.filled(),
+                        candle_width,
+                    )
+                }))?;
+            }
+        }
+
+        let overlays = sma_overlays(history, start, options.sma.windows());
+        for (window, averages) in &overlays {
+            let colour = sma_colour(*window);
+            chart
+                .draw_series(LineSeries::new(
+                    averages
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, avg)| avg.map(|avg| (i as f64, avg))),
+                    colour.stroke_width(2),
+                ))?
+                .label(format!("SMA {}", window))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], colour));
+        }
+        if !overlays.is_empty() {
+            chart
+                .configure_series_labels()
+                .background_style(WHITE.mix(0.8))
+                .border_style(BLACK)
+                .position(SeriesLabelPosition::UpperLeft)
+                .draw()?;
+        }
+
+        if let Some(volume_area) = volume_area {
+            let max_volume = bars.iter().map(|bar| bar.volume).max().unwrap_or(0).max(1);
+            let mut volume_chart = ChartBuilder::on(&volume_area)
+                .margin_left(20)
+                .margin_right(20)
+                .margin_bottom(10)
+                .x_label_area_size(40)
+                .y_label_area_size(70)
+                .build_cartesian_2d(x_axis, 0u64..max_volume)?;
+            volume_chart
+                .configure_mesh()
+                .y_labels(3)
+                .y_desc("Volume")
+                .draw()?;
+            volume_chart.draw_series(bars.iter().enumerate().map(|(i, bar)| {
+                let colour = if bar.close >= bar.open { GREEN } else { RED };
+                Rectangle::new(
+                    [(i as f64 - 0.4, 0), (i as f64 + 0.4, bar.volume)],
+                    colour.mix(0.6).filled(),
+                )
+            }))?;
+        }
+
+        root.present()?;
+    }
+    encode_png(rgb, CHART_SIZE)
+}
+
+/// Percent change of each close from the first one.
+pub fn percent_change(bars: &[Bar]) -> Vec<f64> {
+    let Some(base) = bars.first().map(|bar| bar.close) else {
+        return Vec::new();
+    };
+    bars.iter()
+        .map(|bar| (bar.close / base - 1.0) * 100.0)
+        .collect()
+}
+
+/// Keep only the bars whose times appear in every series, so that all the
+/// lines of a comparison start from the same point.
+pub fn align(series: &mut [Vec<Bar>]) {
+    let Some((first, rest)) = series.split_first() else {
+        return;
+    };
+    let mut common: BTreeSet<NaiveDateTime> = first.iter().map(|bar| bar.time).collect();
+    for bars in rest {
+        let times: BTreeSet<NaiveDateTime> = bars.iter().map(|bar| bar.time).collect();
+        common.retain(|time| times.contains(time));
+    }
+    for bars in series.iter_mut() {
+        bars.retain(|bar| common.contains(&bar.time));
+    }
+}
+
+/// Render several aligned series (see [`align`]) as percent change from
+/// their first bar, one line per symbol with a legend.
+pub fn render_comparison_chart(
+    title: &str,
+    series: &[(String, Vec<Bar>)],
+) -> Result<Vec<u8>, crate::Error> {
+    let Some((_, first)) = series.first() else {
+        return Err("No data to chart".into());
+    };
+    if first.is_empty() {
+        return Err("No data to chart".into());
+    }
+    let changes: Vec<Vec<f64>> = series
+        .iter()
+        .map(|(_, bars)| percent_change(bars))
+        .collect();
+    let (low, high) = changes
+        .iter()
+        .flatten()
+        .fold((0.0f64, 0.0f64), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+    let pad = ((high - low) * 0.05).max(0.5);
+
+    let (width, height) = CHART_SIZE;
+    let mut rgb = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut rgb, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let times: Vec<NaiveDateTime> = first.iter().map(|bar| bar.time).collect();
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 28))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(70)
+            .build_cartesian_2d(TimeAxis::new(&times), (low - pad)..(high + pad))?;
+        chart
+            .configure_mesh()
+            .y_label_formatter(&|v| format!("{:+.0}%", v))
+            .y_desc("Change")
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            [(-0.5, 0.0), (times.len() as f64 - 0.5, 0.0)],
+            BLACK.mix(0.5),
+        ))?;
+
+        for (i, ((symbol, _), change)) in series.iter().zip(&changes).enumerate() {
+            let colour = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(
+                    change.iter().enumerate().map(|(x, v)| (x as f64, *v)),
+                    colour.stroke_width(2),
+                ))?
+                .label(format!(
+                    "{} {:+.1}%",
+                    symbol,
+                    change.last().copied().unwrap_or(0.0)
+                ))
+                .legend(move |(x, y)| {
+                    PathElement::new(vec![(x, y), (x + 20, y)], colour.stroke_width(2))
+                });
+        }
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .position(SeriesLabelPosition::UpperLeft)
+            .draw()?;
+
+        root.present()?;
+    }
+    encode_png(rgb, CHART_SIZE)
+}
+
+/// Render a value over time (oldest first) as a line, with a grey line at
+/// `baseline` for comparison, such as an account's starting cash.
+pub fn render_value_chart(
+    title: &str,
+    points: &[(NaiveDateTime, f64)],
+    baseline: f64,
+) -> Result<Vec<u8>, crate::Error> {
+    if points.is_empty() {
+        return Err("No data to chart".into());
+    }
+    let (low, high) = points
+        .iter()
+        .fold((baseline, baseline), |(lo, hi), (_, v)| {
+            (lo.min(*v), hi.max(*v))
+        });
+    let pad = ((high - low) * 0.05)
+        .max(high.abs() * 0.001)
+        .max(f64::EPSILON);
+
+    let (width, height) = CHART_SIZE;
+    let mut rgb = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut rgb, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let times: Vec<NaiveDateTime> = points.iter().map(|(time, _)| *time).collect();
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 28))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(90)
+            .build_cartesian_2d(TimeAxis::new(&times), (low - pad)..(high + pad))?;
+        chart
+            .configure_mesh()
+            .y_label_formatter(&|v| format!("${:.0}", v))
+            .y_desc("Value")
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            [(-0.5, baseline), (points.len() as f64 - 0.5, baseline)],
+            BLACK.mix(0.4),
+        ))?;
+        chart.draw_series(LineSeries::new(
+            points.iter().enumerate().map(|(i, (_, v))| (i as f64, *v)),
+            BLUE.stroke_width(2),
+        ))?;
+        root.present()?;
+    }
+    encode_png(rgb, CHART_SIZE)
+}
+
+/// Render `closes` (oldest first) as a small line with no axes, green if it
+/// ends at or above where it started and red otherwise.
+pub fn render_sparkline(closes: &[f64]) -> Result<Vec<u8>, crate::Error> {
+    let (Some(first), Some(last)) = (closes.first(), closes.last()) else {
+        return Err("No data to chart".into());
+    };
+    let (low, high) = closes
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(*v), hi.max(*v))
+        });
+    let pad = ((high - low) * 0.05)
+        .max(high.abs() * 0.001)
+        .max(f64::EPSILON);
+    let colour = if last >= first { GREEN } else { RED };
+
+    let (width, height) = SPARKLINE_SIZE;
+    let mut rgb = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut rgb, SPARKLINE_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root).margin(4).build_cartesian_2d(
+            0.0..(closes.len().max(2) - 1) as f64,
+            (low - pad)..(high + pad),
+        )?;
+        chart.draw_series(LineSeries::new(
+            closes.iter().enumerate().map(|(i, v)| (i as f64, *v)),
+            colour.stroke_width(2),
+        ))?;
+        root.present()?;
+    }
+    encode_png(rgb, SPARKLINE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::stonks::market::TimeRange;
+    use chrono::{Duration, NaiveDate};
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn every(start: &str, step: Duration, n: usize) -> Vec<NaiveDateTime> {
+        let start = time(start);
+        (0..n).map(|i| start + step * i as i32).collect()
+    }
+
+    fn bars(n: usize) -> Vec<Bar> {
+        let start = NaiveDate::from_ymd_opt(2023, 1, 2)
+            .unwrap()
+            .and_time(NaiveTime::MIN);
+        (0..n)
+            .map(|i| {
+                let close = 100.0 + (i as f64).sin() * 5.0;
+                Bar {
+                    time: start + Duration::days(i as i64),
+                    open: close - 1.0,
+                    high: close + 2.0,
+                    low: close - 3.0,
+                    close,
+                    volume: 1000 + i as u64 * 10,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sma() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(
+            sma(&values, 3),
+            vec![None, None, Some(2.0), Some(3.0), Some(4.0)]
+        );
+        assert_eq!(sma(&values, 6), vec![None; 5]);
+        assert_eq!(
+            sma(&values, 1),
+            values.iter().map(|v| Some(*v)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_sma_overlays_use_earlier_history() {
+        // A daily six-month chart shows about 180 of these bars, so the
+        // 200-day average needs history from before the range.
+        let history = bars(400);
+        let start = TimeRange::SixMonths.start(&history);
+        let shown = history.len() - start;
+        assert!(shown < 200);
+        let overlays = sma_overlays(&history, start, SmaOverlay::All.windows());
+        let windows: Vec<usize> = overlays.iter().map(|(window, _)| *window).collect();
+        assert_eq!(windows, vec![20, 50, 200]);
+        for (_, averages) in &overlays {
+            assert_eq!(averages.len(), shown);
+            assert!(averages.iter().all(Option::is_some));
+        }
+
+        // Without that history the 200-day line has nothing to draw.
+        let trimmed = &history[start..];
+        let overlays = sma_overlays(trimmed, 0, SmaOverlay::All.windows());
+        let windows: Vec<usize> = overlays.iter().map(|(window, _)| *window).collect();
+        assert_eq!(windows, vec![20, 50]);
+        assert!(overlays[1].1[0].is_none());
+    }
+
+    #[test]
+    fn test_sma_windows() {
+        assert!(SmaOverlay::None.windows().is_empty());
+        assert_eq!(SmaOverlay::All.windows(), &[20, 50, 200]);
+    }
+
+    #[test]
+    fn test_price_bounds_cover_wicks() {
+        let bars = bars(10);
+        let (line_lo, line_hi) = price_bounds(&bars, ChartStyle::Line);
+        let (candle_lo, candle_hi) = price_bounds(&bars, ChartStyle::Candlestick);
+        assert!(candle_lo < line_lo && candle_hi > line_hi);
+        assert!(bars.iter().all(|b| b.low > candle_lo && b.high < candle_hi));
+    }
+
+    #[test]
+    fn test_price_bounds_flat_series() {
+        let flat: Vec<Bar> = bars(3)
+            .into_iter()
+            .map(|b| Bar::from_close(b.time, 50.0))
+            .collect();
+        let (lo, hi) = price_bounds(&flat, ChartStyle::Line);
+        assert!(lo < 50.0 && hi > 50.0);
+    }
+
+    #[test]
+    fn test_time_ticks_intraday() {
+        // Hourly bars over three days label each new day.
+        let times: Vec<NaiveDateTime> = (0..3)
+            .flat_map(|day| {
+                every("2023-01-04 09:00", Duration::hours(1), 7)
+                    .into_iter()
+                    .map(move |t| t + Duration::days(day))
+            })
+            .collect();
+        let ticks = time_ticks(&times);
+        assert_eq!(
+            ticks,
+            vec![(7, "Jan 05".to_string()), (14, "Jan 06".to_string())]
+        );
+        let one_day = time_ticks(&times[..7]);
+        assert_eq!(one_day[0], (1, "10:00".to_string()));
+    }
+
+    #[test]
+    fn test_time_ticks_long_ranges() {
+        let daily = every("2023-01-01 00:00", Duration::days(1), 365);
+        let ticks = time_ticks(&daily);
+        assert!(ticks.len() <= MAX_TIME_LABELS);
+        assert_eq!(ticks[0], (31, "Feb 2023".to_string()));
+
+        let weekly = every("2000-01-03 00:00", Duration::weeks(1), 52 * 20);
+        let ticks = time_ticks(&weekly);
+        assert!(ticks.len() <= MAX_TIME_LABELS);
+        assert!(ticks.iter().all(|(_, label)| label.len() == 4));
+
+        assert!(time_ticks(&[]).is_empty());
+        assert_eq!(time_ticks(&daily[..1]), vec![(0, "Jan 01".to_string())]);
+    }
+
+    #[test]
+    fn test_render_produces_png() {
+        let options = ChartOptions {
+            style: ChartStyle::Candlestick,
+            volume: true,
+            sma: SmaOverlay::Sma20,
+        };
+        let png = render_price_chart("TEST", &bars(60), 10, &options).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let line = render_price_chart("TEST", &bars(5), 0, &ChartOptions::default()).unwrap();
+        assert_eq!(&line[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_render_rejects_empty() {
+        assert!(render_price_chart("TEST", &[], 0, &ChartOptions::default()).is_err());
+        assert!(render_price_chart("TEST", &bars(5), 5, &ChartOptions::default()).is_err());
+        assert!(render_comparison_chart("TEST", &[]).is_err());
+    }
+
+    #[test]
+    fn test_render_sparkline() {
+        let png = render_sparkline(&[1.0, 3.0, 2.0]).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert!(render_sparkline(&[5.0]).is_ok());
+        assert!(render_sparkline(&[]).is_err());
+    }
+
+    #[test]
+    fn test_render_value_chart() {
+        let points: Vec<(NaiveDateTime, f64)> = bars(10)
+            .iter()
+            .map(|bar| (bar.time, bar.close * 1000.0))
+            .collect();
+        let png = render_value_chart("Portfolio", &points, 100_000.0).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert!(render_value_chart("Portfolio", &[], 100_000.0).is_err());
+    }
+
+    #[test]
+    fn test_percent_change() {
+        let day = NaiveDate::from_ymd_opt(2023, 1, 2)
+            .unwrap()
+            .and_time(NaiveTime::MIN);
+        let bars: Vec<Bar> = [50.0, 75.0, 25.0]
+            .iter()
+            .map(|close| Bar::from_close(day, *close))
+            .collect();
+        assert_eq!(percent_change(&bars), vec![0.0, 50.0, -50.0]);
+        assert!(percent_change(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_align() {
+        let all = bars(5);
+        let mut series = vec![all.clone(), all[1..].to_vec(), all[..4].to_vec()];
+        align(&mut series);
+        for bars in &series {
+            let times: Vec<NaiveDateTime> = bars.iter().map(|bar| bar.time).collect();
+            assert_eq!(times, vec![all[1].time, all[2].time, all[3].time]);
+        }
+    }
+
+    #[test]
+    fn test_render_comparison_produces_png() {
+        let series = vec![
+            ("AAPL".to_string(), bars(30)),
+            (
+                "SPY".to_string(),
+                bars(30)
+                    .into_iter()
+                    .map(|bar| Bar::from_close(bar.time, 400.0 - bar.close))
+                    .collect(),
+            ),
+        ];
+        let png = render_comparison_chart("AAPL vs SPY", &series).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+}
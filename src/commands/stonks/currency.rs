@@ -0,0 +1,163 @@
+//! The `/crypto` and `/fx` commands. Charts of pairs go through `/graph`.
+
+use super::assets::{is_crypto, is_fiat, AssetClass, CRYPTO_CURRENCIES};
+use super::market::{ExchangeRate, MarketDataProvider};
+use super::quote::{add_quote, format_price, group_thousands, quote_snapshot};
+use poise::CreateReply;
+
+/// Market coins are priced in when none is given.
+pub(crate) const DEFAULT_MARKET: &str = "USD";
+
+/// The pair `/crypto` looks up: `BTC` becomes `BTC-USD` (or `BTC-<market>`),
+/// and a full pair like `ETH/EUR` is used as it is.
+fn crypto_pair(coin: &str, market: Option<&str>) -> Result<String, String> {
+    let coin = coin.trim().to_uppercase();
+    let symbol = if coin.contains(['-', '/']) {
+        coin
+    } else {
+        format!(
+            "{}-{}",
+            coin,
+            market.unwrap_or(DEFAULT_MARKET).trim().to_uppercase()
+        )
+    };
+    match AssetClass::detect(&symbol) {
+        AssetClass::Crypto { coin, market } => Ok(format!("{}-{}", coin, market)),
+        _ => Err(format!(
+            "I can't price '{}'. Coins I know: {}",
+            symbol,
+            CRYPTO_CURRENCIES.join(", ")
+        )),
+    }
+}
+
+/// Format an amount of money with thousands separators and two decimals,
+/// or more for amounts below one.
+fn format_amount(amount: f64) -> String {
+    let formatted = format_price(amount);
+    let (whole, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+    let (sign, digits) = match whole.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", whole),
+    };
+    format!("{}{}.{}", sign, group_thousands(digits), fraction)
+}
+
+/// The `/fx` reply, e.g. `100.00 EUR = 108.52 USD`, with both rates.
+fn conversion_message(rate: &ExchangeRate, amount: f64) -> String {
+    format!(
+        "{} {} = **{} {}**\n1 {} = {} {} · 1 {} = {} {}\nAs of {} UTC",
+        format_amount(amount),
+        rate.from,
+        format_amount(rate.convert(amount)),
+        rate.to,
+        rate.from,
+        format_price(rate.rate),
+        rate.to,
+        rate.to,
+        format_price(1.0 / rate.rate),
+        rate.from,
+        rate.time.format("%Y-%m-%d %H:%M")
+    )
+}
+
+/// Show the price of a cryptocurrency.
+/// Usage: /crypto BTC [market]
+#[poise::command(slash_command, prefix_command)]
+pub async fn crypto(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Coin, e.g. BTC, or a pair like ETH-EUR"] coin: String,
+    #[description = "Currency to price it in (default USD)"] market: Option<String>,
+) -> Result<(), crate::Error> {
+    let symbol = match crypto_pair(&coin, market.as_deref()) {
+        Ok(symbol) => symbol,
+        Err(message) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+    };
+    ctx.defer().await?;
+    let data = ctx.data();
+    match quote_snapshot(&data.market_data, &data.quote_cache, &symbol).await {
+        Ok(snapshot) => {
+            ctx.send(add_quote(CreateReply::default(), &snapshot))
+                .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}: {}", symbol, e)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert an amount between currencies at the latest exchange rate.
+/// Usage: /fx EUR USD [amount]
+#[poise::command(slash_command, prefix_command)]
+pub async fn fx(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Currency to convert from, e.g. EUR"] from: String,
+    #[description = "Currency to convert to, e.g. USD"] to: String,
+    #[description = "Amount to convert (default 1)"]
+    #[min = 0]
+    amount: Option<f64>,
+) -> Result<(), crate::Error> {
+    let (from, to) = (from.trim().to_uppercase(), to.trim().to_uppercase());
+    if let Some(unknown) = [&from, &to]
+        .into_iter()
+        .find(|code| !is_fiat(code) && !is_crypto(code))
+    {
+        ctx.say(format!("'{}' isn't a currency I know", unknown))
+            .await?;
+        return Ok(());
+    }
+    let amount = amount.unwrap_or(1.0);
+    if !amount.is_finite() || amount < 0.0 {
+        ctx.say("The amount has to be a positive number").await?;
+        return Ok(());
+    }
+    match ctx.data().market_data.exchange_rate(&from, &to).await {
+        Ok(rate) => ctx.say(conversion_message(&rate, amount)).await?,
+        Err(e) => ctx.say(e.to_string()).await?,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_crypto_pair() {
+        assert_eq!(crypto_pair("btc", None).unwrap(), "BTC-USD");
+        assert_eq!(crypto_pair("eth", Some("eur")).unwrap(), "ETH-EUR");
+        assert_eq!(crypto_pair("SOL/USD", Some("EUR")).unwrap(), "SOL-USD");
+        assert!(crypto_pair("AAPL", None).is_err());
+        assert!(crypto_pair("BTC", Some("XYZ")).is_err());
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(1234567.891), "1,234,567.89");
+        assert_eq!(format_amount(100.0), "100.00");
+        assert_eq!(format_amount(-1500.5), "-1,500.50");
+        assert_eq!(format_amount(0.9215), "0.9215");
+    }
+
+    #[test]
+    fn test_conversion_message() {
+        let rate = ExchangeRate {
+            from: "EUR".to_string(),
+            to: "USD".to_string(),
+            rate: 1.25,
+            time: NaiveDate::from_ymd_opt(2024, 4, 5)
+                .unwrap()
+                .and_hms_opt(17, 2, 1)
+                .unwrap(),
+        };
+        assert_eq!(
+            conversion_message(&rate, 1000.0),
+            "1,000.00 EUR = **1,250.00 USD**\n1 EUR = 1.25 USD · 1 USD = 0.8000 EUR\nAs of 2024-04-05 17:02 UTC"
+        );
+    }
+}
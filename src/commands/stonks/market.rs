@@ -0,0 +1,1519 @@
+//! Price history sources for the stock commands.
+//!
+//! [`MarketDataProvider`] is implemented by [`AlphaVantage`], which talks to
+//! the Alpha Vantage API (or any stand-in served at its base URL), and by
+//! [`FixtureProvider`], which serves series loaded from CSV or JSON files.
+//! [`MarketData`] picks one of them from the environment at startup.
+//!
+//! Crypto and currency pairs such as `BTC-USD` and `EUR-USD` go through the
+//! same interface; see [`AssetClass`].
+
+use super::assets::{canonical_symbol, AssetClass};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+/// Used when `ALPHAVANTAGE_BASE_URL` is not set.
+pub const DEFAULT_BASE_URL: &str = "https://www.alphavantage.co";
+/// How long to wait for Alpha Vantage before giving up.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Minutes per bar for intraday series.
+pub const INTRADAY_INTERVAL: &str = "60min";
+/// Bars in Alpha Vantage's compact output.
+const COMPACT_BARS: usize = 100;
+
+/// One interval of trading. Daily and longer bars are stamped at midnight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    pub time: NaiveDateTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: u64,
+}
+
+impl Bar {
+    /// A bar where only the close is known, as in close-only fixtures.
+    pub fn from_close(time: NaiveDateTime, close: f64) -> Self {
+        Self {
+            time,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 0,
+        }
+    }
+}
+
+/// The latest trading day for a symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub symbol: String,
+    pub day: NaiveDate,
+    pub price: f64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub previous_close: f64,
+    pub volume: u64,
+}
+
+impl Quote {
+    /// Build a quote from daily bars (oldest first): the last bar is the
+    /// current day and the one before gives the previous close.
+    pub fn from_bars(symbol: &str, bars: &[Bar]) -> Option<Self> {
+        let last = bars.last()?;
+        let previous_close = match bars.len() {
+            1 => last.open,
+            n => bars[n - 2].close,
+        };
+        Some(Self {
+            symbol: symbol.to_uppercase(),
+            day: last.time.date(),
+            price: last.close,
+            open: last.open,
+            high: last.high,
+            low: last.low,
+            previous_close,
+            volume: last.volume,
+        })
+    }
+
+    pub fn change(&self) -> f64 {
+        self.price - self.previous_close
+    }
+
+    pub fn change_percent(&self) -> f64 {
+        self.change() / self.previous_close * 100.0
+    }
+}
+
+/// The price of one unit of a currency in another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeRate {
+    pub from: String,
+    pub to: String,
+    pub rate: f64,
+    pub time: NaiveDateTime,
+}
+
+impl ExchangeRate {
+    pub fn convert(&self, amount: f64) -> f64 {
+        amount * self.rate
+    }
+}
+
+/// Longest ticker symbol accepted.
+pub const MAX_SYMBOL_LEN: usize = 12;
+
+/// The [`canonical_symbol`] for a ticker symbol or pair, or `None` if it
+/// can't be one. Symbols are letters and digits plus `.`, `-`, `^` and `=`
+/// (e.g. `BRK.B`, `^GSPC`); pairs may also be written `BTC/USD`.
+pub fn normalize_symbol(symbol: &str) -> Option<String> {
+    let symbol = canonical_symbol(symbol);
+    let valid = !symbol.is_empty()
+        && symbol.len() <= MAX_SYMBOL_LEN
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '^' | '='));
+    valid.then_some(symbol)
+}
+
+/// Error returned by a [`MarketDataProvider`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketError {
+    /// The provider refused the request because too many were made.
+    RateLimited,
+    /// The provider doesn't know the symbol.
+    UnknownSymbol(String),
+    /// The response couldn't be understood, or contained invalid prices.
+    BadPayload(String),
+    /// The provider couldn't be reached.
+    Unavailable(String),
+    /// `ALPHAVANTAGE_API_KEY` is not set.
+    MissingApiKey,
+}
+
+impl fmt::Display for MarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketError::RateLimited => {
+                write!(
+                    f,
+                    "The market data provider is rate limiting us, try again in a minute"
+                )
+            }
+            MarketError::UnknownSymbol(symbol) => write!(f, "Unknown symbol '{}'", symbol),
+            MarketError::BadPayload(reason) => {
+                write!(f, "The market data provider sent bad data ({})", reason)
+            }
+            MarketError::Unavailable(reason) => {
+                write!(f, "The market data provider is unavailable ({})", reason)
+            }
+            MarketError::MissingApiKey => write!(f, "ALPHAVANTAGE_API_KEY not set in environment"),
+        }
+    }
+}
+
+impl std::error::Error for MarketError {}
+
+/// How much time each bar covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, poise::ChoiceParameter)]
+pub enum Interval {
+    #[name = "intraday"]
+    Intraday,
+    #[name = "daily"]
+    Daily,
+    #[name = "weekly"]
+    Weekly,
+    #[name = "monthly"]
+    Monthly,
+}
+
+impl Interval {
+    /// The Alpha Vantage function serving this interval.
+    pub fn function(self) -> &'static str {
+        match self {
+            Interval::Intraday => "TIME_SERIES_INTRADAY",
+            Interval::Daily => "TIME_SERIES_DAILY",
+            Interval::Weekly => "TIME_SERIES_WEEKLY",
+            Interval::Monthly => "TIME_SERIES_MONTHLY",
+        }
+    }
+
+    /// The Alpha Vantage function serving this interval for `class`.
+    pub fn function_for(self, class: &AssetClass) -> &'static str {
+        match (class, self) {
+            (AssetClass::Equity, _) => self.function(),
+            (AssetClass::Crypto { .. }, Interval::Intraday) => "CRYPTO_INTRADAY",
+            (AssetClass::Crypto { .. }, Interval::Daily) => "DIGITAL_CURRENCY_DAILY",
+            (AssetClass::Crypto { .. }, Interval::Weekly) => "DIGITAL_CURRENCY_WEEKLY",
+            (AssetClass::Crypto { .. }, Interval::Monthly) => "DIGITAL_CURRENCY_MONTHLY",
+            (AssetClass::Fx { .. }, Interval::Intraday) => "FX_INTRADAY",
+            (AssetClass::Fx { .. }, Interval::Daily) => "FX_DAILY",
+            (AssetClass::Fx { .. }, Interval::Weekly) => "FX_WEEKLY",
+            (AssetClass::Fx { .. }, Interval::Monthly) => "FX_MONTHLY",
+        }
+    }
+
+    /// Name used in fixture file names, e.g. `AAPL.weekly.csv`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Interval::Intraday => "intraday",
+            Interval::Daily => "daily",
+            Interval::Weekly => "weekly",
+            Interval::Monthly => "monthly",
+        }
+    }
+
+    /// Roughly how many bars of this interval cover `days` trading days.
+    fn bars_over(self, days: usize) -> usize {
+        match self {
+            // 60-minute bars, extended hours included.
+            Interval::Intraday => days * 16,
+            Interval::Daily => days,
+            Interval::Weekly => days.div_ceil(5),
+            Interval::Monthly => days.div_ceil(21),
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        [
+            Interval::Intraday,
+            Interval::Daily,
+            Interval::Weekly,
+            Interval::Monthly,
+        ]
+        .into_iter()
+        .find(|interval| interval.key() == key)
+    }
+}
+
+/// How far back a chart goes from the latest bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum TimeRange {
+    #[name = "5d"]
+    FiveDays,
+    #[name = "1m"]
+    OneMonth,
+    #[name = "3m"]
+    ThreeMonths,
+    #[name = "6m"]
+    SixMonths,
+    #[name = "ytd"]
+    YearToDate,
+    #[name = "1y"]
+    OneYear,
+    #[name = "5y"]
+    FiveYears,
+    #[name = "max"]
+    Max,
+}
+
+impl TimeRange {
+    pub fn label(self) -> &'static str {
+        match self {
+            TimeRange::FiveDays => "5 days",
+            TimeRange::OneMonth => "1 month",
+            TimeRange::ThreeMonths => "3 months",
+            TimeRange::SixMonths => "6 months",
+            TimeRange::YearToDate => "year to date",
+            TimeRange::OneYear => "1 year",
+            TimeRange::FiveYears => "5 years",
+            TimeRange::Max => "all time",
+        }
+    }
+
+    /// The interval used when none is asked for, keeping charts to a
+    /// readable number of bars.
+    pub fn default_interval(self) -> Interval {
+        match self {
+            TimeRange::FiveDays => Interval::Intraday,
+            TimeRange::FiveYears => Interval::Weekly,
+            TimeRange::Max => Interval::Monthly,
+            _ => Interval::Daily,
+        }
+    }
+
+    /// Whether Alpha Vantage's compact output (the latest 100 bars) covers
+    /// this range at `interval` plus `lookback` bars before it. Weekly and
+    /// monthly series always come in full.
+    pub fn fits_compact(self, interval: Interval, lookback: usize) -> bool {
+        match interval {
+            Interval::Weekly | Interval::Monthly => true,
+            _ => self
+                .trading_days()
+                .is_some_and(|days| interval.bars_over(days) + lookback <= COMPACT_BARS),
+        }
+    }
+
+    /// The most trading days in this range, or `None` for all of history.
+    fn trading_days(self) -> Option<usize> {
+        match self {
+            TimeRange::FiveDays => Some(5),
+            TimeRange::YearToDate => Some(253),
+            range => range.months().map(|months| months as usize * 253 / 12 + 2),
+        }
+    }
+
+    /// Whether a provider can serve this range at `interval`. Intraday
+    /// history only goes back about a month.
+    pub fn supports(self, interval: Interval) -> bool {
+        interval != Interval::Intraday || matches!(self, TimeRange::FiveDays | TimeRange::OneMonth)
+    }
+
+    fn months(self) -> Option<u32> {
+        match self {
+            TimeRange::OneMonth => Some(1),
+            TimeRange::ThreeMonths => Some(3),
+            TimeRange::SixMonths => Some(6),
+            TimeRange::OneYear => Some(12),
+            TimeRange::FiveYears => Some(60),
+            _ => None,
+        }
+    }
+
+    /// Drop bars (oldest first) from before the start of the range.
+    pub fn trim(self, bars: &mut Vec<Bar>) {
+        bars.drain(..self.start(bars));
+    }
+
+    /// Index of the first bar in the range. Five days means the last five
+    /// trading days present in the series.
+    pub fn start(self, bars: &[Bar]) -> usize {
+        let Some(latest) = bars.last().map(|bar| bar.time.date()) else {
+            return 0;
+        };
+        let start = match self {
+            TimeRange::Max => return 0,
+            TimeRange::FiveDays => {
+                let mut days: Vec<NaiveDate> = bars.iter().map(|bar| bar.time.date()).collect();
+                days.dedup();
+                days[days.len().saturating_sub(5)]
+            }
+            TimeRange::YearToDate => NaiveDate::from_ymd_opt(latest.year(), 1, 1).unwrap_or(latest),
+            range => {
+                let months = range.months().unwrap_or(0);
+                latest
+                    .checked_sub_months(Months::new(months))
+                    .unwrap_or(NaiveDate::MIN)
+            }
+        };
+        bars.partition_point(|bar| bar.time.date() < start)
+    }
+}
+
+/// A source of price history.
+pub trait MarketDataProvider {
+    /// Bars for `symbol` at `interval`, oldest first, covering at least
+    /// `range` where the provider has that much history. Callers trim the
+    /// result with [`TimeRange::trim`].
+    fn series(
+        &self,
+        symbol: &str,
+        interval: Interval,
+        range: TimeRange,
+    ) -> impl Future<Output = Result<Vec<Bar>, MarketError>> + Send {
+        self.series_with_lookback(symbol, interval, range, 0)
+    }
+
+    /// Like [`series`](Self::series), with at least `lookback` more bars
+    /// before the range where the provider has them, for moving averages
+    /// that start at the first bar in range.
+    fn series_with_lookback(
+        &self,
+        symbol: &str,
+        interval: Interval,
+        range: TimeRange,
+        lookback: usize,
+    ) -> impl Future<Output = Result<Vec<Bar>, MarketError>> + Send;
+
+    /// The latest price and day statistics for `symbol`.
+    fn quote(&self, symbol: &str) -> impl Future<Output = Result<Quote, MarketError>> + Send;
+
+    /// The latest rate from currency (or coin) `from` to `to`.
+    fn exchange_rate(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> impl Future<Output = Result<ExchangeRate, MarketError>> + Send;
+}
+
+/// The body of a `GLOBAL_QUOTE` response. Unknown symbols get an empty object.
+#[derive(Deserialize, Debug)]
+struct GlobalQuote {
+    #[serde(rename = "Global Quote")]
+    quote: HashMap<String, String>,
+}
+
+/// One bar of a `TIME_SERIES_*` response. Only the close is required; missing
+/// open, high and low fall back to it and a missing volume counts as zero.
+#[derive(Deserialize, Debug)]
+pub(crate) struct DailyData {
+    #[serde(rename = "1. open", default)]
+    pub open: Option<String>,
+    #[serde(rename = "2. high", default)]
+    pub high: Option<String>,
+    #[serde(rename = "3. low", default)]
+    pub low: Option<String>,
+    #[serde(rename = "4. close")]
+    pub close: String,
+    #[serde(rename = "5. volume", default)]
+    pub volume: Option<String>,
+}
+
+/// The shapes Alpha Vantage uses to report a problem instead of data.
+#[derive(Deserialize, Debug)]
+struct ApiNotice {
+    #[serde(rename = "Error Message")]
+    error: Option<String>,
+    #[serde(rename = "Note")]
+    note: Option<String>,
+    #[serde(rename = "Information")]
+    information: Option<String>,
+}
+
+/// Parse a price, rejecting anything that isn't a positive finite number.
+pub(crate) fn parse_price(value: &str, field: &str, date: &str) -> Result<f64, MarketError> {
+    match value.trim().parse::<f64>() {
+        Ok(price) if price.is_finite() && price > 0.0 => Ok(price),
+        _ => Err(MarketError::BadPayload(format!(
+            "unparsable {} '{}' on {}",
+            field, value, date
+        ))),
+    }
+}
+
+/// Parse a volume. Crypto volumes are fractional and get rounded.
+fn parse_volume(value: &str, date: &str) -> Result<u64, MarketError> {
+    let value = value.trim();
+    value
+        .parse::<u64>()
+        .ok()
+        .or_else(|| match value.parse::<f64>() {
+            Ok(volume) if volume.is_finite() && volume >= 0.0 => Some(volume.round() as u64),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            MarketError::BadPayload(format!("unparsable volume '{}' on {}", value, date))
+        })
+}
+
+/// Build a bar from raw fields, checking that the prices are consistent.
+fn build_bar(
+    date: &str,
+    open: Option<&str>,
+    high: Option<&str>,
+    low: Option<&str>,
+    close: &str,
+    volume: Option<&str>,
+) -> Result<Bar, MarketError> {
+    let close = parse_price(close, "close", date)?;
+    let price = |value: Option<&str>, field| match value {
+        Some(value) => parse_price(value, field, date),
+        None => Ok(close),
+    };
+    let bar = Bar {
+        time: parse_time(date)?,
+        open: price(open, "open")?,
+        high: price(high, "high")?,
+        low: price(low, "low")?,
+        close,
+        volume: volume
+            .map(|v| parse_volume(v, date))
+            .transpose()?
+            .unwrap_or(0),
+    };
+    let top = bar.open.max(bar.close);
+    let bottom = bar.open.min(bar.close);
+    if bar.high < top || bar.low > bottom {
+        return Err(MarketError::BadPayload(format!(
+            "inconsistent prices on {}",
+            date
+        )));
+    }
+    Ok(bar)
+}
+
+/// Parse a `YYYY-MM-DD` date or a `YYYY-MM-DD HH:MM:SS` intraday timestamp.
+fn parse_time(value: &str) -> Result<NaiveDateTime, MarketError> {
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN))
+        })
+        .map_err(|_| MarketError::BadPayload(format!("unparsable date '{}'", value)))
+}
+
+/// Report an Alpha Vantage error or rate limit notice in `body`, if any.
+fn check_notice(symbol: &str, body: &str) -> Result<(), MarketError> {
+    if let Ok(notice) = serde_json::from_str::<ApiNotice>(body) {
+        if notice.error.is_some() {
+            return Err(MarketError::UnknownSymbol(symbol.to_string()));
+        }
+        if notice.note.is_some() || notice.information.is_some() {
+            return Err(MarketError::RateLimited);
+        }
+    }
+    Ok(())
+}
+
+/// Parse an Alpha Vantage `GLOBAL_QUOTE` JSON response.
+pub fn parse_quote_json(symbol: &str, body: &str) -> Result<Quote, MarketError> {
+    check_notice(symbol, body)?;
+    let response: GlobalQuote =
+        serde_json::from_str(body).map_err(|e| MarketError::BadPayload(e.to_string()))?;
+    if response.quote.is_empty() {
+        return Err(MarketError::UnknownSymbol(symbol.to_string()));
+    }
+    let field = |name: &str| {
+        response
+            .quote
+            .iter()
+            .find(|(key, _)| key.ends_with(name))
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| MarketError::BadPayload(format!("quote has no {}", name)))
+    };
+    let day = field("latest trading day")?;
+    let bar = build_bar(
+        day,
+        Some(field("open")?),
+        Some(field("high")?),
+        Some(field("low")?),
+        field("price")?,
+        Some(field("volume")?),
+    )?;
+    Ok(Quote {
+        symbol: field("symbol")?.to_string(),
+        day: bar.time.date(),
+        price: bar.close,
+        open: bar.open,
+        high: bar.high,
+        low: bar.low,
+        previous_close: parse_price(field("previous close")?, "previous close", day)?,
+        volume: bar.volume,
+    })
+}
+
+/// The body of a `CURRENCY_EXCHANGE_RATE` response.
+#[derive(Deserialize, Debug)]
+struct RealtimeRate {
+    #[serde(rename = "Realtime Currency Exchange Rate")]
+    rate: HashMap<String, String>,
+}
+
+/// Parse an Alpha Vantage `CURRENCY_EXCHANGE_RATE` JSON response.
+pub fn parse_exchange_rate_json(
+    from: &str,
+    to: &str,
+    body: &str,
+) -> Result<ExchangeRate, MarketError> {
+    let pair = format!("{}-{}", from, to);
+    check_notice(&pair, body)?;
+    let response: RealtimeRate =
+        serde_json::from_str(body).map_err(|e| MarketError::BadPayload(e.to_string()))?;
+    let field = |name: &str| {
+        response
+            .rate
+            .iter()
+            .find(|(key, _)| key.ends_with(name))
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| MarketError::BadPayload(format!("exchange rate has no {}", name)))
+    };
+    let time = field("Last Refreshed")?;
+    Ok(ExchangeRate {
+        from: field("From_Currency Code")?.to_string(),
+        to: field("To_Currency Code")?.to_string(),
+        rate: parse_price(field("Exchange Rate")?, "exchange rate", time)?,
+        time: parse_time(time)?,
+    })
+}
+
+/// Parse an Alpha Vantage `TIME_SERIES_*` JSON response. The series is read
+/// from whichever key names a time series, e.g. `Time Series (Daily)`,
+/// `Time Series (60min)`, `Weekly Time Series` or the crypto and FX
+/// `Time Series (Digital Currency Daily)` and `Time Series FX (Daily)`.
+pub fn parse_series_json(symbol: &str, body: &str) -> Result<Vec<Bar>, MarketError> {
+    check_notice(symbol, body)?;
+    let mut response: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(body).map_err(|e| MarketError::BadPayload(e.to_string()))?;
+    let key = response
+        .keys()
+        .find(|key| key.contains("Time Series"))
+        .cloned()
+        .ok_or_else(|| MarketError::BadPayload("no time series in response".to_string()))?;
+    let series: BTreeMap<String, DailyData> = response
+        .remove(&key)
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| MarketError::BadPayload(e.to_string()))?
+        .unwrap_or_default();
+    let bars = series
+        .iter()
+        .map(|(date, data)| {
+            build_bar(
+                date,
+                data.open.as_deref(),
+                data.high.as_deref(),
+                data.low.as_deref(),
+                &data.close,
+                data.volume.as_deref(),
+            )
+        })
+        .collect::<Result<Vec<_>, MarketError>>()?;
+    if bars.is_empty() {
+        return Err(MarketError::UnknownSymbol(symbol.to_string()));
+    }
+    Ok(bars)
+}
+
+/// Parse CSV with a header row containing `timestamp` (or `date`) and
+/// `close` columns, as produced by Alpha Vantage's `datatype=csv`. `open`,
+/// `high`, `low` and `volume` columns are used when present.
+pub fn parse_series_csv(body: &str) -> Result<Vec<Bar>, MarketError> {
+    let mut lines = body.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = lines
+        .next()
+        .ok_or_else(|| MarketError::BadPayload("empty CSV".to_string()))?
+        .split(',')
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|column| names.contains(&column.as_str()))
+            .ok_or_else(|| MarketError::BadPayload(format!("CSV has no {} column", names[0])))
+    };
+    let date_col = column(&["timestamp", "date"])?;
+    let close_col = column(&["close"])?;
+    let optional = |name: &str| header.iter().position(|column| column == name);
+    let (open_col, high_col, low_col, volume_col) = (
+        optional("open"),
+        optional("high"),
+        optional("low"),
+        optional("volume"),
+    );
+    let mut bars = lines
+        .map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            let field = |col: usize| {
+                fields
+                    .get(col)
+                    .copied()
+                    .ok_or_else(|| MarketError::BadPayload(format!("short CSV row '{}'", line)))
+            };
+            let optional_field = |col: Option<usize>| col.map(field).transpose();
+            build_bar(
+                field(date_col)?,
+                optional_field(open_col)?,
+                optional_field(high_col)?,
+                optional_field(low_col)?,
+                field(close_col)?,
+                optional_field(volume_col)?,
+            )
+        })
+        .collect::<Result<Vec<_>, MarketError>>()?;
+    bars.sort_by_key(|bar| bar.time);
+    Ok(bars)
+}
+
+/// [`MarketDataProvider`] backed by the Alpha Vantage API. Crypto and FX
+/// requests can be sent to their own base URLs.
+#[derive(Debug, Clone)]
+pub struct AlphaVantage {
+    http: reqwest::Client,
+    base_url: String,
+    crypto_base_url: String,
+    fx_base_url: String,
+    api_key: Option<String>,
+}
+
+impl AlphaVantage {
+    pub fn new(base_url: impl Into<String>, api_key: Option<String>) -> Result<Self, MarketError> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| MarketError::Unavailable(e.to_string()))?;
+        let base_url = base_url.into();
+        Ok(Self {
+            http,
+            crypto_base_url: base_url.clone(),
+            fx_base_url: base_url.clone(),
+            base_url,
+            api_key,
+        })
+    }
+
+    /// Send crypto and FX requests to their own base URLs.
+    pub fn with_asset_urls(mut self, crypto: impl Into<String>, fx: impl Into<String>) -> Self {
+        self.crypto_base_url = crypto.into();
+        self.fx_base_url = fx.into();
+        self
+    }
+
+    /// Read `ALPHAVANTAGE_API_KEY` and `ALPHAVANTAGE_BASE_URL`, plus
+    /// `CRYPTO_DATA_BASE_URL` and `FX_DATA_BASE_URL`, which default to the
+    /// latter. A missing key is only reported when data is requested.
+    pub fn from_env() -> Result<Self, MarketError> {
+        let base_url =
+            std::env::var("ALPHAVANTAGE_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        let crypto = std::env::var("CRYPTO_DATA_BASE_URL").unwrap_or_else(|_| base_url.clone());
+        let fx = std::env::var("FX_DATA_BASE_URL").unwrap_or_else(|_| base_url.clone());
+        Ok(
+            Self::new(base_url, std::env::var("ALPHAVANTAGE_API_KEY").ok())?
+                .with_asset_urls(crypto, fx),
+        )
+    }
+
+    fn base_url_for(&self, class: &AssetClass) -> &str {
+        match class {
+            AssetClass::Equity => &self.base_url,
+            AssetClass::Crypto { .. } => &self.crypto_base_url,
+            AssetClass::Fx { .. } => &self.fx_base_url,
+        }
+    }
+
+    async fn query(&self, base_url: &str, params: &[(&str, &str)]) -> Result<String, MarketError> {
+        let api_key = self.api_key.as_deref().ok_or(MarketError::MissingApiKey)?;
+        let url = format!("{}/query", base_url.trim_end_matches('/'));
+        let request = self
+            .http
+            .get(url)
+            .query(params)
+            .query(&[("apikey", api_key)]);
+        let response = crate::telemetry::send(request)
+            .await
+            .map_err(|e| MarketError::Unavailable(e.to_string()))?;
+        match response.status().as_u16() {
+            429 => return Err(MarketError::RateLimited),
+            status if !(200..300).contains(&status) => {
+                return Err(MarketError::Unavailable(format!("HTTP {}", status)))
+            }
+            _ => {}
+        }
+        response
+            .text()
+            .await
+            .map_err(|e| MarketError::Unavailable(e.to_string()))
+    }
+
+    /// Fetch the `LISTING_STATUS` CSV of every active symbol.
+    pub async fn listing(&self) -> Result<String, MarketError> {
+        let body = self
+            .query(&self.base_url, &[("function", "LISTING_STATUS")])
+            .await?;
+        check_notice("LISTING_STATUS", &body)?;
+        Ok(body)
+    }
+}
+
+impl MarketDataProvider for AlphaVantage {
+    async fn series_with_lookback(
+        &self,
+        symbol: &str,
+        interval: Interval,
+        range: TimeRange,
+        lookback: usize,
+    ) -> Result<Vec<Bar>, MarketError> {
+        let output_size = if range.fits_compact(interval, lookback) {
+            "compact"
+        } else {
+            "full"
+        };
+        let class = AssetClass::detect(symbol);
+        let mut params = vec![("function", interval.function_for(&class))];
+        match &class {
+            AssetClass::Equity => params.push(("symbol", symbol)),
+            AssetClass::Crypto { coin, market } => {
+                params.extend([("symbol", coin.as_str()), ("market", market.as_str())])
+            }
+            AssetClass::Fx { from, to } => {
+                params.extend([("from_symbol", from.as_str()), ("to_symbol", to.as_str())])
+            }
+        }
+        params.push(("outputsize", output_size));
+        if interval == Interval::Intraday {
+            params.push(("interval", INTRADAY_INTERVAL));
+        }
+        let body = self.query(self.base_url_for(&class), &params).await?;
+        parse_series_json(symbol, &body)
+    }
+
+    /// Equities use `GLOBAL_QUOTE`; crypto and FX quotes come from the daily
+    /// series, which has no separate quote endpoint with day statistics.
+    async fn quote(&self, symbol: &str) -> Result<Quote, MarketError> {
+        if AssetClass::detect(symbol) != AssetClass::Equity {
+            let bars = self
+                .series(symbol, Interval::Daily, TimeRange::OneMonth)
+                .await?;
+            return Quote::from_bars(&canonical_symbol(symbol), &bars)
+                .ok_or_else(|| MarketError::UnknownSymbol(symbol.to_string()));
+        }
+        let body = self
+            .query(
+                &self.base_url,
+                &[("function", "GLOBAL_QUOTE"), ("symbol", symbol)],
+            )
+            .await?;
+        parse_quote_json(symbol, &body)
+    }
+
+    async fn exchange_rate(&self, from: &str, to: &str) -> Result<ExchangeRate, MarketError> {
+        let class = AssetClass::detect(&format!("{}-{}", from, to));
+        let body = self
+            .query(
+                self.base_url_for(&class),
+                &[
+                    ("function", "CURRENCY_EXCHANGE_RATE"),
+                    ("from_currency", from),
+                    ("to_currency", to),
+                ],
+            )
+            .await?;
+        parse_exchange_rate_json(from, to, &body)
+    }
+}
+
+/// Combine daily bars into one bar per ISO week or calendar month, stamped
+/// with the first trading day in it.
+pub fn resample(bars: &[Bar], interval: Interval) -> Vec<Bar> {
+    let bucket = |bar: &Bar| {
+        let date = bar.time.date();
+        match interval {
+            Interval::Weekly => {
+                let week = date.iso_week();
+                (week.year(), week.week())
+            }
+            Interval::Monthly => (date.year(), date.month()),
+            Interval::Intraday | Interval::Daily => (date.year(), date.ordinal()),
+        }
+    };
+    let mut out: Vec<Bar> = Vec::new();
+    let mut current = None;
+    for bar in bars {
+        let key = bucket(bar);
+        match out.last_mut() {
+            Some(last) if current == Some(key) => {
+                last.high = last.high.max(bar.high);
+                last.low = last.low.min(bar.low);
+                last.close = bar.close;
+                last.volume += bar.volume;
+            }
+            _ => {
+                out.push(*bar);
+                current = Some(key);
+            }
+        }
+    }
+    out
+}
+
+/// [`MarketDataProvider`] serving fixed series, for tests and offline use.
+/// Weekly and monthly series are resampled from the daily one when they
+/// weren't loaded separately.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureProvider {
+    series: HashMap<(String, Interval), Vec<Bar>>,
+}
+
+impl FixtureProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, symbol: &str, interval: Interval, bars: Vec<Bar>) {
+        self.series
+            .insert((canonical_symbol(symbol), interval), bars);
+    }
+
+    /// Load every `SYMBOL.csv` and `SYMBOL.json` file in `dir` as a daily
+    /// series, and `SYMBOL.<interval>.csv` (or `.json`) as the named
+    /// interval. JSON files use the Alpha Vantage `TIME_SERIES_*` format.
+    /// Crypto and FX pairs are named like `BTC-USD.csv`.
+    pub fn load_dir(dir: &Path) -> Result<Self, crate::Error> {
+        let mut provider = Self::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let (Some(symbol), Some(ext)) = (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|s| s.to_str()),
+            ) else {
+                continue;
+            };
+            let body = std::fs::read_to_string(&path)?;
+            let (symbol, interval) = match symbol.split_once('.') {
+                Some((symbol, key)) => match Interval::from_key(key) {
+                    Some(interval) => (symbol, interval),
+                    None => continue,
+                },
+                None => (symbol, Interval::Daily),
+            };
+            let bars = match ext {
+                "csv" => parse_series_csv(&body)?,
+                "json" => parse_series_json(symbol, &body)?,
+                _ => continue,
+            };
+            provider.insert(symbol, interval, bars);
+        }
+        Ok(provider)
+    }
+}
+
+impl MarketDataProvider for FixtureProvider {
+    async fn series_with_lookback(
+        &self,
+        symbol: &str,
+        interval: Interval,
+        _range: TimeRange,
+        _lookback: usize,
+    ) -> Result<Vec<Bar>, MarketError> {
+        let symbol_key = canonical_symbol(symbol);
+        if let Some(bars) = self.series.get(&(symbol_key.clone(), interval)) {
+            return Ok(bars.clone());
+        }
+        match (interval, self.series.get(&(symbol_key, Interval::Daily))) {
+            (Interval::Weekly | Interval::Monthly, Some(daily)) => Ok(resample(daily, interval)),
+            _ => Err(MarketError::UnknownSymbol(symbol.to_string())),
+        }
+    }
+
+    async fn quote(&self, symbol: &str) -> Result<Quote, MarketError> {
+        let symbol_key = canonical_symbol(symbol);
+        self.series
+            .get(&(symbol_key.clone(), Interval::Daily))
+            .and_then(|bars| Quote::from_bars(&symbol_key, bars))
+            .ok_or_else(|| MarketError::UnknownSymbol(symbol.to_string()))
+    }
+
+    /// The last daily close of the `FROM-TO` pair, or the inverse of `TO-FROM`.
+    async fn exchange_rate(&self, from: &str, to: &str) -> Result<ExchangeRate, MarketError> {
+        let (from, to) = (from.to_uppercase(), to.to_uppercase());
+        let last = |pair: String| {
+            self.series
+                .get(&(pair, Interval::Daily))
+                .and_then(|bars| bars.last())
+        };
+        let (rate, time) = if from == to {
+            (1.0, chrono::Utc::now().naive_utc())
+        } else if let Some(bar) = last(format!("{}-{}", from, to)) {
+            (bar.close, bar.time)
+        } else if let Some(bar) = last(format!("{}-{}", to, from)) {
+            (1.0 / bar.close, bar.time)
+        } else {
+            return Err(MarketError::UnknownSymbol(format!("{}-{}", from, to)));
+        };
+        Ok(ExchangeRate {
+            from,
+            to,
+            rate,
+            time,
+        })
+    }
+}
+
+/// The provider the bot was configured with.
+#[derive(Debug, Clone)]
+pub enum MarketData {
+    AlphaVantage(AlphaVantage),
+    Fixture(FixtureProvider),
+}
+
+impl MarketData {
+    /// Serve fixtures from `MARKET_DATA_FIXTURES` if it is set, otherwise use
+    /// Alpha Vantage.
+    pub fn from_env() -> Result<Self, crate::Error> {
+        match std::env::var("MARKET_DATA_FIXTURES") {
+            Ok(dir) => Ok(MarketData::Fixture(FixtureProvider::load_dir(Path::new(
+                &dir,
+            ))?)),
+            Err(_) => Ok(MarketData::AlphaVantage(AlphaVantage::from_env()?)),
+        }
+    }
+
+    /// The listing of known symbols. Fixtures serve the bundled one.
+    pub async fn listing(&self) -> Result<String, MarketError> {
+        match self {
+            MarketData::AlphaVantage(provider) => provider.listing().await,
+            MarketData::Fixture(_) => Ok(super::symbols::BUNDLED_LISTING.to_string()),
+        }
+    }
+}
+
+impl MarketDataProvider for MarketData {
+    async fn series_with_lookback(
+        &self,
+        symbol: &str,
+        interval: Interval,
+        range: TimeRange,
+        lookback: usize,
+    ) -> Result<Vec<Bar>, MarketError> {
+        match self {
+            MarketData::AlphaVantage(provider) => {
+                provider
+                    .series_with_lookback(symbol, interval, range, lookback)
+                    .await
+            }
+            MarketData::Fixture(provider) => {
+                provider
+                    .series_with_lookback(symbol, interval, range, lookback)
+                    .await
+            }
+        }
+    }
+
+    async fn quote(&self, symbol: &str) -> Result<Quote, MarketError> {
+        match self {
+            MarketData::AlphaVantage(provider) => provider.quote(symbol).await,
+            MarketData::Fixture(provider) => provider.quote(symbol).await,
+        }
+    }
+
+    async fn exchange_rate(&self, from: &str, to: &str) -> Result<ExchangeRate, MarketError> {
+        match self {
+            MarketData::AlphaVantage(provider) => provider.exchange_rate(from, to).await,
+            MarketData::Fixture(provider) => provider.exchange_rate(from, to).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn date(s: &str) -> NaiveDateTime {
+        parse_time(s).unwrap()
+    }
+
+    fn daily_bars(start: &str, days: u64) -> Vec<Bar> {
+        let start = date(start);
+        (0..days)
+            .map(|i| Bar::from_close(start + chrono::Duration::days(i as i64), 100.0 + i as f64))
+            .collect()
+    }
+
+    #[test]
+    fn test_daily_data_parse() {
+        let data = json!({"4. close": "123.45"});
+        let daily: DailyData = serde_json::from_value(data).unwrap();
+        assert_eq!(daily.close, "123.45");
+    }
+
+    #[test]
+    fn test_parse_series_json_other_intervals() {
+        let weekly = json!({
+            "Meta Data": {"1. Information": "Weekly Prices"},
+            "Weekly Time Series": {
+                "2023-01-06": {"4. close": "100.0"},
+                "2023-01-13": {"4. close": "110.0"}
+            }
+        })
+        .to_string();
+        let bars = parse_series_json("AAPL", &weekly).unwrap();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].time, date("2023-01-06"));
+
+        let intraday = json!({
+            "Time Series (60min)": {
+                "2023-01-06 10:00:00": {"4. close": "101.0"},
+                "2023-01-06 09:00:00": {"4. close": "100.0"}
+            }
+        })
+        .to_string();
+        let bars = parse_series_json("AAPL", &intraday).unwrap();
+        assert_eq!(bars[1].time, date("2023-01-06 10:00:00"));
+        assert_eq!(bars[1].close, 101.0);
+    }
+
+    #[test]
+    fn test_parse_series_json_sorted() {
+        let body = json!({
+            "Time Series (Daily)": {
+                "2023-01-03": {"4. close": "120.5"},
+                "2023-01-02": {"4. close": "110.0"}
+            }
+        })
+        .to_string();
+        let bars = parse_series_json("AAPL", &body).unwrap();
+        assert_eq!(
+            bars,
+            vec![
+                Bar::from_close(date("2023-01-02"), 110.0),
+                Bar::from_close(date("2023-01-03"), 120.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_series_json_ohlcv() {
+        let body = json!({
+            "Time Series (Daily)": {
+                "2023-01-02": {
+                    "1. open": "100.0",
+                    "2. high": "112.5",
+                    "3. low": "99.0",
+                    "4. close": "110.0",
+                    "5. volume": "123456"
+                }
+            }
+        })
+        .to_string();
+        let bars = parse_series_json("AAPL", &body).unwrap();
+        assert_eq!(
+            bars,
+            vec![Bar {
+                time: date("2023-01-02"),
+                open: 100.0,
+                high: 112.5,
+                low: 99.0,
+                close: 110.0,
+                volume: 123456,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_series_json_rejects_bad_ohlcv() {
+        let bad_volume = json!({
+            "Time Series (Daily)": {"2023-01-02": {"4. close": "110.0", "5. volume": "lots"}}
+        })
+        .to_string();
+        assert_eq!(
+            parse_series_json("AAPL", &bad_volume),
+            Err(MarketError::BadPayload(
+                "unparsable volume 'lots' on 2023-01-02".to_string()
+            ))
+        );
+        let inverted = json!({
+            "Time Series (Daily)": {"2023-01-02": {
+                "1. open": "100.0", "2. high": "90.0", "3. low": "80.0", "4. close": "95.0"
+            }}
+        })
+        .to_string();
+        assert_eq!(
+            parse_series_json("AAPL", &inverted),
+            Err(MarketError::BadPayload(
+                "inconsistent prices on 2023-01-02".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_series_json_rejects_bad_close() {
+        let body = json!({
+            "Time Series (Daily)": {"2023-01-02": {"4. close": "n/a"}}
+        })
+        .to_string();
+        assert_eq!(
+            parse_series_json("AAPL", &body),
+            Err(MarketError::BadPayload(
+                "unparsable close 'n/a' on 2023-01-02".to_string()
+            ))
+        );
+        let body = json!({
+            "Time Series (Daily)": {"2023-01-02": {"4. close": "0"}}
+        })
+        .to_string();
+        assert!(matches!(
+            parse_series_json("AAPL", &body),
+            Err(MarketError::BadPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_series_json_notices() {
+        let unknown = json!({"Error Message": "Invalid API call."}).to_string();
+        assert_eq!(
+            parse_series_json("NOPE", &unknown),
+            Err(MarketError::UnknownSymbol("NOPE".to_string()))
+        );
+        let limited = json!({"Note": "Thank you for using Alpha Vantage!"}).to_string();
+        assert_eq!(
+            parse_series_json("AAPL", &limited),
+            Err(MarketError::RateLimited)
+        );
+        let info = json!({"Information": "rate limit is 25 requests per day"}).to_string();
+        assert_eq!(
+            parse_series_json("AAPL", &info),
+            Err(MarketError::RateLimited)
+        );
+        assert!(matches!(
+            parse_series_json("AAPL", "not json"),
+            Err(MarketError::BadPayload(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_series_csv() {
+        let body = "timestamp,open,high,low,close,volume\n\
+                    2023-01-03,118,121,117.5,120.5,100\n\
+                    2023-01-02,105,111,104,110,100\n";
+        let bars = parse_series_csv(body).unwrap();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].time, date("2023-01-02"));
+        assert_eq!(bars[1].close, 120.5);
+        assert_eq!(bars[1].high, 121.0);
+        assert_eq!(bars[1].low, 117.5);
+        assert_eq!(bars[1].volume, 100);
+    }
+
+    #[test]
+    fn test_parse_series_csv_errors() {
+        assert!(matches!(
+            parse_series_csv(""),
+            Err(MarketError::BadPayload(_))
+        ));
+        assert!(matches!(
+            parse_series_csv("date,open\n2023-01-02,1\n"),
+            Err(MarketError::BadPayload(_))
+        ));
+        assert_eq!(
+            parse_series_csv("date,close\n2023-01-02,abc\n"),
+            Err(MarketError::BadPayload(
+                "unparsable close 'abc' on 2023-01-02".to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fixture_provider() {
+        let mut provider = FixtureProvider::new();
+        provider.insert(
+            "aapl",
+            Interval::Daily,
+            parse_series_csv("date,close\n2023-01-02,110\n").unwrap(),
+        );
+        let daily = provider.series("AAPL", Interval::Daily, TimeRange::Max);
+        assert_eq!(daily.await.unwrap().len(), 1);
+        assert_eq!(
+            provider
+                .series("MSFT", Interval::Daily, TimeRange::Max)
+                .await,
+            Err(MarketError::UnknownSymbol("MSFT".to_string()))
+        );
+        assert_eq!(
+            provider
+                .series("AAPL", Interval::Intraday, TimeRange::FiveDays)
+                .await,
+            Err(MarketError::UnknownSymbol("AAPL".to_string()))
+        );
+        let weekly = provider.series("AAPL", Interval::Weekly, TimeRange::Max);
+        assert_eq!(weekly.await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_crypto_and_fx_series_json() {
+        let crypto = json!({
+            "Meta Data": {"2. Digital Currency Code": "BTC"},
+            "Time Series (Digital Currency Daily)": {
+                "2024-01-02": {
+                    "1. open": "42000.10",
+                    "2. high": "43000.00",
+                    "3. low": "41500.00",
+                    "4. close": "42800.55",
+                    "5. volume": "1234.5678"
+                }
+            }
+        });
+        let bars = parse_series_json("BTC-USD", &crypto.to_string()).unwrap();
+        assert_eq!(bars[0].close, 42800.55);
+        assert_eq!(bars[0].volume, 1235);
+
+        let fx = json!({
+            "Time Series FX (Daily)": {
+                "2024-01-02": {"1. open": "1.10", "2. high": "1.11", "3. low": "1.09", "4. close": "1.095"}
+            }
+        });
+        let bars = parse_series_json("EUR-USD", &fx.to_string()).unwrap();
+        assert_eq!((bars[0].close, bars[0].volume), (1.095, 0));
+    }
+
+    #[test]
+    fn test_parse_exchange_rate_json() {
+        let body = json!({
+            "Realtime Currency Exchange Rate": {
+                "1. From_Currency Code": "EUR",
+                "2. From_Currency Name": "Euro",
+                "3. To_Currency Code": "USD",
+                "4. To_Currency Name": "United States Dollar",
+                "5. Exchange Rate": "1.08520000",
+                "6. Last Refreshed": "2024-04-05 17:02:01",
+                "7. Time Zone": "UTC"
+            }
+        });
+        let rate = parse_exchange_rate_json("EUR", "USD", &body.to_string()).unwrap();
+        assert_eq!((rate.from.as_str(), rate.to.as_str()), ("EUR", "USD"));
+        assert_eq!(rate.time, date("2024-04-05 17:02:01"));
+        assert!((rate.convert(100.0) - 108.52).abs() < 1e-9);
+
+        let error = json!({"Error Message": "Invalid API call."}).to_string();
+        assert_eq!(
+            parse_exchange_rate_json("EUR", "XXX", &error),
+            Err(MarketError::UnknownSymbol("EUR-XXX".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_function_for_asset_class() {
+        let crypto = AssetClass::detect("BTC-USD");
+        let fx = AssetClass::detect("EUR-USD");
+        assert_eq!(
+            Interval::Daily.function_for(&AssetClass::Equity),
+            "TIME_SERIES_DAILY"
+        );
+        assert_eq!(
+            Interval::Weekly.function_for(&crypto),
+            "DIGITAL_CURRENCY_WEEKLY"
+        );
+        assert_eq!(Interval::Intraday.function_for(&fx), "FX_INTRADAY");
+    }
+
+    #[tokio::test]
+    async fn test_fixture_pairs() {
+        let mut provider = FixtureProvider::new();
+        provider.insert("EUR-USD", Interval::Daily, daily_bars("2024-01-01", 3));
+        provider.insert("btc-usd", Interval::Daily, daily_bars("2024-01-01", 3));
+        let bars = provider.series("EUR/USD", Interval::Daily, TimeRange::Max);
+        assert_eq!(bars.await.unwrap().len(), 3);
+        assert_eq!(provider.quote("BTC/USD").await.unwrap().symbol, "BTC-USD");
+
+        let rate = provider.exchange_rate("eur", "usd").await.unwrap();
+        assert_eq!(rate.rate, 102.0);
+        let inverse = provider.exchange_rate("USD", "EUR").await.unwrap();
+        assert_eq!(inverse.rate, 1.0 / 102.0);
+        assert_eq!(
+            provider.exchange_rate("USD", "USD").await.unwrap().rate,
+            1.0
+        );
+        assert_eq!(
+            provider.exchange_rate("GBP", "USD").await,
+            Err(MarketError::UnknownSymbol("GBP-USD".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_quote_json() {
+        let body = json!({
+            "Global Quote": {
+                "01. symbol": "IBM",
+                "02. open": "140.00",
+                "03. high": "142.50",
+                "04. low": "139.25",
+                "05. price": "141.00",
+                "06. volume": "3456789",
+                "07. latest trading day": "2023-01-06",
+                "08. previous close": "150.00",
+                "09. change": "-9.0000",
+                "10. change percent": "-6.0000%"
+            }
+        })
+        .to_string();
+        let quote = parse_quote_json("ibm", &body).unwrap();
+        assert_eq!(quote.symbol, "IBM");
+        assert_eq!(quote.day, date("2023-01-06").date());
+        assert_eq!(quote.high, 142.5);
+        assert_eq!(quote.volume, 3456789);
+        assert_eq!(quote.change(), -9.0);
+        assert_eq!(quote.change_percent(), -6.0);
+
+        let empty = json!({"Global Quote": {}}).to_string();
+        assert_eq!(
+            parse_quote_json("NOPE", &empty),
+            Err(MarketError::UnknownSymbol("NOPE".to_string()))
+        );
+        let limited = json!({"Note": "Thank you for using Alpha Vantage!"}).to_string();
+        assert_eq!(
+            parse_quote_json("IBM", &limited),
+            Err(MarketError::RateLimited)
+        );
+    }
+
+    #[test]
+    fn test_quote_from_bars() {
+        let bars = daily_bars("2023-01-02", 3);
+        let quote = Quote::from_bars("aapl", &bars).unwrap();
+        assert_eq!(quote.symbol, "AAPL");
+        assert_eq!(quote.price, 102.0);
+        assert_eq!(quote.previous_close, 101.0);
+        assert_eq!(quote.day, date("2023-01-04").date());
+        assert!(Quote::from_bars("AAPL", &[]).is_none());
+    }
+
+    #[test]
+    fn test_normalize_symbol() {
+        assert_eq!(normalize_symbol(" brk.b "), Some("BRK.B".to_string()));
+        assert_eq!(normalize_symbol("^GSPC"), Some("^GSPC".to_string()));
+        assert_eq!(normalize_symbol("btc/usd"), Some("BTC-USD".to_string()));
+        assert_eq!(normalize_symbol("EURUSD=X"), Some("EUR-USD".to_string()));
+        assert_eq!(normalize_symbol(""), None);
+        assert_eq!(normalize_symbol("AAPL MSFT"), None);
+        assert_eq!(normalize_symbol("<@123>"), None);
+        assert_eq!(normalize_symbol("ABCDEFGHIJKLM"), None);
+    }
+
+    #[test]
+    fn test_resample() {
+        // Monday 2 January to Sunday 5 February 2023.
+        let bars = daily_bars("2023-01-02", 35);
+        let weekly = resample(&bars, Interval::Weekly);
+        assert_eq!(weekly.len(), 5);
+        assert_eq!(weekly[0].time, date("2023-01-02"));
+        assert_eq!(weekly[0].open, 100.0);
+        assert_eq!(weekly[0].close, 106.0);
+        assert_eq!(weekly[0].high, 106.0);
+        assert_eq!(weekly[0].low, 100.0);
+        let monthly = resample(&bars, Interval::Monthly);
+        assert_eq!(monthly.len(), 2);
+        assert_eq!(monthly[1].time, date("2023-02-01"));
+    }
+
+    #[test]
+    fn test_time_range_trim() {
+        let bars = daily_bars("2022-01-01", 730);
+        let trimmed = |range: TimeRange| {
+            let mut bars = bars.clone();
+            range.trim(&mut bars);
+            bars
+        };
+        assert_eq!(trimmed(TimeRange::Max).len(), 730);
+        assert_eq!(trimmed(TimeRange::FiveDays).len(), 5);
+        assert_eq!(trimmed(TimeRange::OneMonth)[0].time, date("2023-11-30"));
+        assert_eq!(trimmed(TimeRange::YearToDate)[0].time, date("2023-01-01"));
+        assert_eq!(trimmed(TimeRange::OneYear)[0].time, date("2022-12-31"));
+
+        let mut intraday = vec![
+            Bar::from_close(date("2023-01-05 15:00:00"), 1.0),
+            Bar::from_close(date("2023-01-06 09:00:00"), 1.0),
+            Bar::from_close(date("2023-01-06 10:00:00"), 1.0),
+        ];
+        TimeRange::FiveDays.trim(&mut intraday);
+        assert_eq!(intraday.len(), 3);
+        TimeRange::OneMonth.trim(&mut Vec::new());
+    }
+
+    #[test]
+    fn test_time_range_intervals() {
+        assert_eq!(TimeRange::FiveDays.default_interval(), Interval::Intraday);
+        assert_eq!(TimeRange::Max.default_interval(), Interval::Monthly);
+        assert!(TimeRange::ThreeMonths.fits_compact(Interval::Daily, 0));
+        assert!(!TimeRange::OneYear.fits_compact(Interval::Daily, 0));
+        assert!(TimeRange::FiveDays.fits_compact(Interval::Intraday, 0));
+        assert!(!TimeRange::OneMonth.fits_compact(Interval::Intraday, 0));
+        // A 50-day average on a three-month chart needs more than compact.
+        assert!(TimeRange::OneMonth.fits_compact(Interval::Daily, 50));
+        assert!(!TimeRange::ThreeMonths.fits_compact(Interval::Daily, 50));
+        assert!(!TimeRange::FiveDays.fits_compact(Interval::Daily, 200));
+        assert!(TimeRange::Max.fits_compact(Interval::Weekly, 200));
+        assert!(!TimeRange::Max.fits_compact(Interval::Daily, 0));
+        assert!(!TimeRange::OneYear.supports(Interval::Intraday));
+        assert!(TimeRange::OneYear.supports(Interval::Weekly));
+        assert_eq!(Interval::from_key("weekly"), Some(Interval::Weekly));
+        assert_eq!(Interval::from_key("hourly"), None);
+    }
+
+    /// Serve a single canned HTTP response on a local port and return its base URL.
+    fn stand_in(status: &str, body: String) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let status = status.to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf);
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_alpha_vantage_against_stand_in() {
+        let body = json!({
+            "Time Series (Daily)": {"2023-01-02": {"4. close": "110.0"}}
+        })
+        .to_string();
+        let provider =
+            AlphaVantage::new(stand_in("200 OK", body), Some("demo".to_string())).unwrap();
+        let bars = provider
+            .series("AAPL", Interval::Daily, TimeRange::Max)
+            .await
+            .unwrap();
+        assert_eq!(bars[0].close, 110.0);
+
+        let provider = AlphaVantage::new(
+            stand_in("429 Too Many Requests", String::new()),
+            Some("demo".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            provider
+                .series("AAPL", Interval::Daily, TimeRange::Max)
+                .await,
+            Err(MarketError::RateLimited)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alpha_vantage_requires_api_key() {
+        let provider = AlphaVantage::new("http://127.0.0.1:9", None).unwrap();
+        assert_eq!(
+            provider
+                .series("AAPL", Interval::Daily, TimeRange::Max)
+                .await,
+            Err(MarketError::MissingApiKey)
+        );
+    }
+}
@@ -0,0 +1,870 @@
+//! Paper trading: virtual cash accounts per guild, priced from the same
+//! market data as the chart commands.
+//!
+//! [`Account`] holds the trading rules and is replayed over the ledger by
+//! [`value_history`] to draw the portfolio chart. Money is kept in cents.
+
+use super::chart::render_value_chart;
+use super::market::{normalize_symbol, Bar, Interval, MarketDataProvider, MarketError, TimeRange};
+use super::quote::group_thousands;
+use crate::commands::require_guild;
+use crate::models::{NewPaperTrade, PaperPosition, PaperTrade};
+use crate::schema::{paper_accounts, paper_positions, paper_trades};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use poise::serenity_prelude::{
+    Colour, CreateAllowedMentions, CreateAttachment, CreateEmbed, User, UserId,
+};
+use poise::CreateReply;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::warn;
+
+/// Cash every account starts with: $100,000.
+pub const STARTING_CASH_CENTS: i64 = 10_000_000;
+/// Most different symbols one account may hold.
+pub(crate) const MAX_POSITIONS: usize = 20;
+/// Accounts listed by `/leaderboard`.
+pub(crate) const LEADERBOARD_SIZE: usize = 10;
+/// Most symbols fetched to draw one portfolio chart.
+pub(crate) const MAX_CHART_SYMBOLS: usize = 10;
+
+/// Round a price to whole cents.
+pub fn to_cents(price: f64) -> i64 {
+    (price * 100.0).round() as i64
+}
+
+/// Format cents as dollars with thousands separators, e.g. `-$1,234.50`.
+pub fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    let dollars = group_thousands(&(cents / 100).to_string());
+    format!("{}${}.{:02}", sign, dollars, cents % 100)
+}
+
+/// Gain or loss from `base` to `now`, e.g. `+$100.00 (+6.67%)`.
+fn format_change(now: i64, base: i64) -> String {
+    let change = now - base;
+    let sign = if change >= 0 { "+" } else { "" };
+    let percent = if base == 0 {
+        0.0
+    } else {
+        change as f64 / base as f64 * 100.0
+    };
+    format!("{}{} ({:+.2}%)", sign, format_cents(change), percent)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn key(self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "buy" => Some(Side::Buy),
+            "sell" => Some(Side::Sell),
+            _ => None,
+        }
+    }
+}
+
+/// Why a trade was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TradeError {
+    /// The share count or price wasn't positive, or the total overflowed.
+    InvalidQuantity,
+    InsufficientCash {
+        needed: i64,
+        available: i64,
+    },
+    InsufficientShares {
+        symbol: String,
+        held: i64,
+    },
+    TooManyPositions,
+}
+
+impl fmt::Display for TradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeError::InvalidQuantity => write!(f, "That isn't a valid number of shares"),
+            TradeError::InsufficientCash { needed, available } => write!(
+                f,
+                "That costs {} but you only have {}",
+                format_cents(*needed),
+                format_cents(*available)
+            ),
+            TradeError::InsufficientShares { symbol, held } => {
+                write!(f, "You only hold {} shares of {}", held, symbol)
+            }
+            TradeError::TooManyPositions => write!(
+                f,
+                "You can't hold more than {} different symbols",
+                MAX_POSITIONS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TradeError {}
+
+/// Shares of one symbol and what was paid for them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holding {
+    pub symbol: String,
+    pub shares: i64,
+    pub cost_cents: i64,
+}
+
+impl Holding {
+    /// Average price paid per share, in cents.
+    pub fn average_cents(&self) -> i64 {
+        self.cost_cents / self.shares.max(1)
+    }
+}
+
+/// A user's cash and holdings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub cash_cents: i64,
+    pub holdings: Vec<Holding>,
+}
+
+impl Account {
+    pub fn new(cash_cents: i64) -> Self {
+        Self {
+            cash_cents,
+            holdings: Vec::new(),
+        }
+    }
+
+    pub fn holding(&self, symbol: &str) -> Option<&Holding> {
+        self.holdings
+            .iter()
+            .find(|holding| holding.symbol == symbol)
+    }
+
+    /// Buy `shares` of `symbol` at `price_cents` each.
+    pub fn buy(&mut self, symbol: &str, shares: i64, price_cents: i64) -> Result<(), TradeError> {
+        if shares <= 0 || price_cents <= 0 {
+            return Err(TradeError::InvalidQuantity);
+        }
+        let cost = shares
+            .checked_mul(price_cents)
+            .ok_or(TradeError::InvalidQuantity)?;
+        if cost > self.cash_cents {
+            return Err(TradeError::InsufficientCash {
+                needed: cost,
+                available: self.cash_cents,
+            });
+        }
+        let open = self.holdings.len();
+        match self.holdings.iter_mut().find(|h| h.symbol == symbol) {
+            Some(holding) => {
+                holding.shares += shares;
+                holding.cost_cents += cost;
+            }
+            None if open >= MAX_POSITIONS => return Err(TradeError::TooManyPositions),
+            None => self.holdings.push(Holding {
+                symbol: symbol.to_string(),
+                shares,
+                cost_cents: cost,
+            }),
+        }
+        self.cash_cents -= cost;
+        Ok(())
+    }
+
+    /// Sell `shares` of `symbol` at `price_cents` each, returning the realised
+    /// profit against the average cost.
+    pub fn sell(&mut self, symbol: &str, shares: i64, price_cents: i64) -> Result<i64, TradeError> {
+        if shares <= 0 || price_cents <= 0 {
+            return Err(TradeError::InvalidQuantity);
+        }
+        let held = self.holding(symbol).map_or(0, |holding| holding.shares);
+        if shares > held {
+            return Err(TradeError::InsufficientShares {
+                symbol: symbol.to_string(),
+                held,
+            });
+        }
+        let proceeds = shares
+            .checked_mul(price_cents)
+            .ok_or(TradeError::InvalidQuantity)?;
+        let index = self
+            .holdings
+            .iter()
+            .position(|holding| holding.symbol == symbol)
+            .ok_or(TradeError::InvalidQuantity)?;
+        let holding = &mut self.holdings[index];
+        let basis = (i128::from(holding.cost_cents) * i128::from(shares) / i128::from(held)) as i64;
+        holding.shares -= shares;
+        holding.cost_cents -= basis;
+        if holding.shares == 0 {
+            self.holdings.remove(index);
+        }
+        self.cash_cents += proceeds;
+        Ok(proceeds - basis)
+    }
+
+    /// Replay a ledger entry.
+    pub fn apply(&mut self, trade: &PaperTrade) -> Result<(), TradeError> {
+        match Side::from_key(&trade.side) {
+            Some(Side::Buy) => self.buy(&trade.symbol, trade.shares, trade.price_cents),
+            Some(Side::Sell) => self
+                .sell(&trade.symbol, trade.shares, trade.price_cents)
+                .map(|_| ()),
+            None => Err(TradeError::InvalidQuantity),
+        }
+    }
+
+    /// Cash plus holdings at `price_of` each symbol, in cents. Holdings with
+    /// no price are counted at cost.
+    pub fn value(&self, price_of: impl Fn(&str) -> Option<i64>) -> i64 {
+        self.cash_cents
+            + self
+                .holdings
+                .iter()
+                .map(|holding| match price_of(&holding.symbol) {
+                    Some(price) => price.saturating_mul(holding.shares),
+                    None => holding.cost_cents,
+                })
+                .sum::<i64>()
+    }
+}
+
+/// The close of the last bar on or before `day`.
+fn close_on(bars: &[Bar], day: NaiveDate) -> Option<f64> {
+    let index = bars.partition_point(|bar| bar.time.date() <= day);
+    index.checked_sub(1).map(|i| bars[i].close)
+}
+
+/// Account value at each trading day's close from the first trade on,
+/// replaying `trades` (oldest first) from `starting_cash` and pricing
+/// holdings from `closes`.
+pub fn value_history(
+    trades: &[PaperTrade],
+    closes: &HashMap<String, Vec<Bar>>,
+    starting_cash: i64,
+) -> Vec<(NaiveDateTime, i64)> {
+    let Some(start) = trades.first().map(|trade| trade.executed_at.date()) else {
+        return Vec::new();
+    };
+    let mut days: Vec<NaiveDate> = closes
+        .values()
+        .flatten()
+        .map(|bar| bar.time.date())
+        .filter(|day| *day >= start)
+        .collect();
+    days.sort();
+    days.dedup();
+
+    let mut account = Account::new(starting_cash);
+    let mut replayed = 0;
+    days.into_iter()
+        .map(|day| {
+            while let Some(trade) = trades.get(replayed) {
+                if trade.executed_at.date() > day {
+                    break;
+                }
+                if let Err(e) = account.apply(trade) {
+                    warn!("Skipping unreplayable paper trade #{}: {}", trade.id, e);
+                }
+                replayed += 1;
+            }
+            let value = account.value(|symbol| {
+                closes
+                    .get(symbol)
+                    .and_then(|bars| close_on(bars, day))
+                    .map(to_cents)
+            });
+            (day.and_time(NaiveTime::MIN), value)
+        })
+        .collect()
+}
+
+/// Load a user's account to trade with, opening it with
+/// [`STARTING_CASH_CENTS`] if this is their first trade. The account row stays
+/// locked until the surrounding transaction ends.
+fn open_account(conn: &mut PgConnection, guild: i64, user: i64) -> QueryResult<Account> {
+    diesel::insert_into(paper_accounts::table)
+        .values((
+            paper_accounts::guild_id.eq(guild),
+            paper_accounts::user_id.eq(user),
+            paper_accounts::cash_cents.eq(STARTING_CASH_CENTS),
+            paper_accounts::created_at.eq(Utc::now().naive_utc()),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    let cash_cents: i64 = paper_accounts::table
+        .find((guild, user))
+        .select(paper_accounts::cash_cents)
+        .for_update()
+        .first(conn)?;
+    Ok(Account {
+        cash_cents,
+        holdings: load_holdings(conn, guild, user)?,
+    })
+}
+
+/// Load a user's account for viewing, if they have ever traded.
+fn load_account(conn: &mut PgConnection, guild: i64, user: i64) -> QueryResult<Option<Account>> {
+    let Some(cash_cents) = paper_accounts::table
+        .find((guild, user))
+        .select(paper_accounts::cash_cents)
+        .first(conn)
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(Account {
+        cash_cents,
+        holdings: load_holdings(conn, guild, user)?,
+    }))
+}
+
+fn load_holdings(conn: &mut PgConnection, guild: i64, user: i64) -> QueryResult<Vec<Holding>> {
+    Ok(paper_positions::table
+        .filter(paper_positions::guild_id.eq(guild))
+        .filter(paper_positions::user_id.eq(user))
+        .order(paper_positions::symbol.asc())
+        .select(PaperPosition::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|position| Holding {
+            symbol: position.symbol,
+            shares: position.shares,
+            cost_cents: position.cost_cents,
+        })
+        .collect())
+}
+
+/// Apply a trade to the stored account and record it in the ledger. The
+/// inner error is a refused trade, which leaves the account untouched.
+fn execute_trade(
+    conn: &mut PgConnection,
+    guild: i64,
+    user: i64,
+    side: Side,
+    symbol: &str,
+    shares: i64,
+    price_cents: i64,
+) -> QueryResult<Result<(Account, i64), TradeError>> {
+    conn.transaction(|conn| {
+        let mut account = open_account(conn, guild, user)?;
+        let realised = match side {
+            Side::Buy => account.buy(symbol, shares, price_cents).map(|_| 0),
+            Side::Sell => account.sell(symbol, shares, price_cents),
+        };
+        let realised = match realised {
+            Ok(realised) => realised,
+            Err(e) => return Ok(Err(e)),
+        };
+        diesel::update(paper_accounts::table.find((guild, user)))
+            .set(paper_accounts::cash_cents.eq(account.cash_cents))
+            .execute(conn)?;
+        let position = paper_positions::table.find((guild, user, symbol));
+        match account.holding(symbol) {
+            Some(holding) => {
+                diesel::insert_into(paper_positions::table)
+                    .values((
+                        paper_positions::guild_id.eq(guild),
+                        paper_positions::user_id.eq(user),
+                        paper_positions::symbol.eq(symbol),
+                        paper_positions::shares.eq(holding.shares),
+                        paper_positions::cost_cents.eq(holding.cost_cents),
+                    ))
+                    .on_conflict((
+                        paper_positions::guild_id,
+                        paper_positions::user_id,
+                        paper_positions::symbol,
+                    ))
+                    .do_update()
+                    .set((
+                        paper_positions::shares.eq(holding.shares),
+                        paper_positions::cost_cents.eq(holding.cost_cents),
+                    ))
+                    .execute(conn)?;
+            }
+            None => {
+                diesel::delete(position).execute(conn)?;
+            }
+        }
+        diesel::insert_into(paper_trades::table)
+            .values(NewPaperTrade {
+                guild_id: guild,
+                user_id: user,
+                symbol,
+                side: side.key(),
+                shares,
+                price_cents,
+                executed_at: Utc::now().naive_utc(),
+            })
+            .execute(conn)?;
+        Ok(Ok((account, realised)))
+    })
+}
+
+/// The latest price for `symbol`, from a fresh `/quote` if there is one.
+async fn latest_price(data: &crate::Data, symbol: &str) -> Result<f64, MarketError> {
+    if let Some(snapshot) = data.quote_cache.get(symbol) {
+        return Ok(snapshot.quote.price);
+    }
+    Ok(data.market_data.quote(symbol).await?.price)
+}
+
+/// Latest prices in cents for `symbols`; symbols that can't be priced are left out.
+async fn latest_prices<'a>(
+    data: &crate::Data,
+    symbols: impl IntoIterator<Item = &'a str>,
+) -> HashMap<String, i64> {
+    let mut prices = HashMap::new();
+    for symbol in symbols {
+        match latest_price(data, symbol).await {
+            Ok(price) => {
+                prices.insert(symbol.to_string(), to_cents(price));
+            }
+            Err(e) => warn!("Couldn't price {} for paper trading: {}", symbol, e),
+        }
+    }
+    prices
+}
+
+async fn reply(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    content: String,
+) -> Result<(), crate::Error> {
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn trade(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    side: Side,
+    symbol: String,
+    shares: i64,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let Some(symbol) = normalize_symbol(&symbol) else {
+        return reply(ctx, format!("'{}' isn't a ticker symbol.", symbol)).await;
+    };
+    let price = match latest_price(ctx.data(), &symbol).await {
+        Ok(price) => to_cents(price),
+        Err(e) => return reply(ctx, e.to_string()).await,
+    };
+    let user = ctx.author().id.get() as i64;
+    let outcome = {
+        let mut conn = ctx.data().db_pool.get()?;
+        execute_trade(&mut conn, guild, user, side, &symbol, shares, price)?
+    };
+    let msg = match outcome {
+        Ok((account, _)) if side == Side::Buy => format!(
+            "Bought {} {} at {} for {}. Cash left: {}.",
+            shares,
+            symbol,
+            format_cents(price),
+            format_cents(price * shares),
+            format_cents(account.cash_cents)
+        ),
+        Ok((account, realised)) => format!(
+            "Sold {} {} at {} for {} ({} realised). Cash: {}.",
+            shares,
+            symbol,
+            format_cents(price),
+            format_cents(price * shares),
+            format_cents(realised),
+            format_cents(account.cash_cents)
+        ),
+        Err(e) => format!("{}.", e),
+    };
+    reply(ctx, msg).await
+}
+
+/// Buy shares with your paper trading cash.
+/// Usage: /buy AAPL 10
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn buy(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Ticker symbol"] symbol: String,
+    #[description = "Number of shares"]
+    #[min = 1]
+    #[max = 1000000]
+    shares: i64,
+) -> Result<(), crate::Error> {
+    trade(ctx, Side::Buy, symbol, shares).await
+}
+
+/// Sell shares from your paper trading portfolio.
+/// Usage: /sell AAPL 10
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn sell(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Ticker symbol"] symbol: String,
+    #[description = "Number of shares"]
+    #[min = 1]
+    #[max = 1000000]
+    shares: i64,
+) -> Result<(), crate::Error> {
+    trade(ctx, Side::Sell, symbol, shares).await
+}
+
+/// Draw `user`'s account value since their first trade.
+async fn portfolio_chart(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    guild: i64,
+    user: &User,
+) -> Result<Option<Vec<u8>>, crate::Error> {
+    let trades: Vec<PaperTrade> = {
+        let mut conn = ctx.data().db_pool.get()?;
+        paper_trades::table
+            .filter(paper_trades::guild_id.eq(guild))
+            .filter(paper_trades::user_id.eq(user.id.get() as i64))
+            .order((paper_trades::executed_at.asc(), paper_trades::id.asc()))
+            .select(PaperTrade::as_select())
+            .load(&mut conn)?
+    };
+    let Some(first) = trades.first() else {
+        return Ok(None);
+    };
+    let today = Utc::now().date_naive();
+    let range = if (today - first.executed_at.date()).num_days() <= 90 {
+        TimeRange::ThreeMonths
+    } else {
+        TimeRange::Max
+    };
+    let mut symbols: Vec<&str> = trades.iter().map(|trade| trade.symbol.as_str()).collect();
+    symbols.sort();
+    symbols.dedup();
+    let mut closes = HashMap::new();
+    for symbol in symbols.into_iter().take(MAX_CHART_SYMBOLS) {
+        match ctx
+            .data()
+            .market_data
+            .series(symbol, Interval::Daily, range)
+            .await
+        {
+            Ok(bars) => {
+                closes.insert(symbol.to_string(), bars);
+            }
+            Err(e) => warn!(
+                "Couldn't chart {} for {}'s portfolio: {}",
+                symbol, user.name, e
+            ),
+        }
+    }
+    let points: Vec<(NaiveDateTime, f64)> = value_history(&trades, &closes, STARTING_CASH_CENTS)
+        .into_iter()
+        .map(|(time, cents)| (time, cents as f64 / 100.0))
+        .collect();
+    if points.is_empty() {
+        return Ok(None);
+    }
+    let title = format!("{}'s portfolio", user.name);
+    Ok(Some(render_value_chart(
+        &title,
+        &points,
+        STARTING_CASH_CENTS as f64 / 100.0,
+    )?))
+}
+
+/// Show a paper trading portfolio and how it has done.
+/// Usage: /portfolio [user]
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn portfolio(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Whose portfolio to show (default: yours)"] user: Option<User>,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    ctx.defer().await?;
+    let account = {
+        let mut conn = ctx.data().db_pool.get()?;
+        load_account(&mut conn, guild, user.id.get() as i64)?
+    };
+    let Some(account) = account else {
+        let msg = if user.id == ctx.author().id {
+            "You haven't traded yet. Start with /buy.".to_string()
+        } else {
+            format!("{} hasn't traded yet.", user.name)
+        };
+        return reply(ctx, msg).await;
+    };
+    let prices = latest_prices(
+        ctx.data(),
+        account
+            .holdings
+            .iter()
+            .map(|holding| holding.symbol.as_str()),
+    )
+    .await;
+    let total = account.value(|symbol| prices.get(symbol).copied());
+
+    let mut embed = CreateEmbed::new()
+        .title(format!("{}'s portfolio", user.name))
+        .description(format!(
+            "Total value **{}**, {} since the start",
+            format_cents(total),
+            format_change(total, STARTING_CASH_CENTS)
+        ))
+        .colour(if total >= STARTING_CASH_CENTS {
+            Colour::DARK_GREEN
+        } else {
+            Colour::RED
+        });
+    for holding in &account.holdings {
+        let value = match prices.get(&holding.symbol) {
+            Some(price) => format!(
+                "avg {} · now {} · {}",
+                format_cents(holding.average_cents()),
+                format_cents(*price),
+                format_change(price * holding.shares, holding.cost_cents)
+            ),
+            None => format!(
+                "avg {} · no current price",
+                format_cents(holding.average_cents())
+            ),
+        };
+        embed = embed.field(
+            format!("{} × {}", holding.symbol, holding.shares),
+            value,
+            false,
+        );
+    }
+    embed = embed.field("Cash", format_cents(account.cash_cents), true);
+
+    let mut reply = CreateReply::default();
+    match portfolio_chart(ctx, guild, &user).await {
+        Ok(Some(png)) => {
+            reply = reply.attachment(CreateAttachment::bytes(png, "portfolio.png"));
+            embed = embed.image("attachment://portfolio.png");
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Couldn't draw {}'s portfolio chart: {}", user.name, e),
+    }
+    ctx.send(reply.embed(embed)).await?;
+    Ok(())
+}
+
+/// Rank `totals` (user, value in cents) from best to worst, as numbered lines.
+fn leaderboard_lines(mut totals: Vec<(i64, i64)>) -> Vec<String> {
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    totals
+        .into_iter()
+        .take(LEADERBOARD_SIZE)
+        .enumerate()
+        .map(|(rank, (user, total))| {
+            format!(
+                "{}. <@{}> {} {}",
+                rank + 1,
+                UserId::new(user as u64),
+                format_cents(total),
+                format_change(total, STARTING_CASH_CENTS)
+            )
+        })
+        .collect()
+}
+
+/// Show the best paper trading portfolios in this server.
+/// Usage: /leaderboard
+#[poise::command(slash_command, prefix_command, guild_only)]
+pub async fn leaderboard(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    ctx.defer().await?;
+    let (accounts, positions) = {
+        let mut conn = ctx.data().db_pool.get()?;
+        let accounts: Vec<(i64, i64)> = paper_accounts::table
+            .filter(paper_accounts::guild_id.eq(guild))
+            .select((paper_accounts::user_id, paper_accounts::cash_cents))
+            .load(&mut conn)?;
+        let positions: Vec<PaperPosition> = paper_positions::table
+            .filter(paper_positions::guild_id.eq(guild))
+            .select(PaperPosition::as_select())
+            .load(&mut conn)?;
+        (accounts, positions)
+    };
+    if accounts.is_empty() {
+        return reply(ctx, "Nobody has traded here yet. Try /buy.".to_string()).await;
+    }
+    let mut symbols: Vec<&str> = positions.iter().map(|p| p.symbol.as_str()).collect();
+    symbols.sort();
+    symbols.dedup();
+    let prices = latest_prices(ctx.data(), symbols).await;
+
+    let totals = accounts
+        .into_iter()
+        .map(|(user, cash_cents)| {
+            let account = Account {
+                cash_cents,
+                holdings: positions
+                    .iter()
+                    .filter(|position| position.user_id == user)
+                    .map(|position| Holding {
+                        symbol: position.symbol.clone(),
+                        shares: position.shares,
+                        cost_cents: position.cost_cents,
+                    })
+                    .collect(),
+            };
+            (user, account.value(|symbol| prices.get(symbol).copied()))
+        })
+        .collect();
+    let lines = leaderboard_lines(totals);
+    reply(
+        ctx,
+        format!("Paper trading leaderboard:\n{}", lines.join("\n")),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn ledger(id: i64, at: &str, side: Side, symbol: &str, shares: i64, price: i64) -> PaperTrade {
+        PaperTrade {
+            id,
+            guild_id: 1,
+            user_id: 2,
+            symbol: symbol.to_string(),
+            side: side.key().to_string(),
+            shares,
+            price_cents: price,
+            executed_at: time(at),
+        }
+    }
+
+    #[test]
+    fn test_format_cents() {
+        assert_eq!(format_cents(0), "$0.00");
+        assert_eq!(format_cents(5), "$0.05");
+        assert_eq!(format_cents(123_456_789), "$1,234,567.89");
+        assert_eq!(format_cents(-100_050), "-$1,000.50");
+        assert_eq!(format_change(110, 100), "+$0.10 (+10.00%)");
+        assert_eq!(format_change(90, 100), "-$0.10 (-10.00%)");
+        assert_eq!(to_cents(123.456), 12346);
+    }
+
+    #[test]
+    fn test_buy_and_sell() {
+        let mut account = Account::new(100_000);
+        account.buy("AAPL", 10, 1_000).unwrap();
+        account.buy("AAPL", 10, 2_000).unwrap();
+        assert_eq!(account.cash_cents, 70_000);
+        let holding = account.holding("AAPL").unwrap();
+        assert_eq!((holding.shares, holding.cost_cents), (20, 30_000));
+        assert_eq!(holding.average_cents(), 1_500);
+
+        // Selling half at 2,000 realises 5,000 over the 1,500 average.
+        assert_eq!(account.sell("AAPL", 10, 2_000), Ok(5_000));
+        assert_eq!(account.cash_cents, 90_000);
+        assert_eq!(account.holding("AAPL").unwrap().cost_cents, 15_000);
+        assert_eq!(account.sell("AAPL", 10, 1_000), Ok(-5_000));
+        assert!(account.holding("AAPL").is_none());
+        assert_eq!(account.cash_cents, 100_000);
+    }
+
+    #[test]
+    fn test_refused_trades() {
+        let mut account = Account::new(1_000);
+        assert_eq!(
+            account.buy("AAPL", 2, 600),
+            Err(TradeError::InsufficientCash {
+                needed: 1_200,
+                available: 1_000
+            })
+        );
+        assert_eq!(
+            account.buy("AAPL", 0, 600),
+            Err(TradeError::InvalidQuantity)
+        );
+        assert_eq!(
+            account.buy("AAPL", i64::MAX, 600),
+            Err(TradeError::InvalidQuantity)
+        );
+        assert_eq!(
+            account.sell("AAPL", 1, 600),
+            Err(TradeError::InsufficientShares {
+                symbol: "AAPL".to_string(),
+                held: 0
+            })
+        );
+        assert_eq!(account, Account::new(1_000));
+
+        let mut full = Account::new(i64::MAX / 2);
+        for i in 0..MAX_POSITIONS {
+            full.buy(&format!("S{}", i), 1, 1).unwrap();
+        }
+        assert_eq!(full.buy("MORE", 1, 1), Err(TradeError::TooManyPositions));
+        assert!(full.buy("S0", 1, 1).is_ok());
+    }
+
+    #[test]
+    fn test_value() {
+        let mut account = Account::new(10_000);
+        account.buy("AAPL", 2, 1_000).unwrap();
+        account.buy("MSFT", 1, 3_000).unwrap();
+        let prices: HashMap<&str, i64> = [("AAPL", 1_500)].into_iter().collect();
+        // AAPL at its current price, MSFT at cost.
+        assert_eq!(
+            account.value(|s| prices.get(s).copied()),
+            5_000 + 3_000 + 3_000
+        );
+    }
+
+    #[test]
+    fn test_value_history() {
+        let bar = |day: &str, close: f64| Bar::from_close(time(&format!("{} 00:00", day)), close);
+        let closes: HashMap<String, Vec<Bar>> = [(
+            "AAPL".to_string(),
+            vec![
+                bar("2023-01-02", 10.0),
+                bar("2023-01-03", 12.0),
+                bar("2023-01-04", 11.0),
+                bar("2023-01-05", 15.0),
+            ],
+        )]
+        .into_iter()
+        .collect();
+        let trades = vec![
+            ledger(1, "2023-01-03 15:00", Side::Buy, "AAPL", 10, 1_200),
+            ledger(2, "2023-01-04 15:00", Side::Sell, "AAPL", 5, 1_100),
+        ];
+        let history = value_history(&trades, &closes, 100_000);
+        let values: Vec<i64> = history.iter().map(|(_, v)| *v).collect();
+        assert_eq!(values, vec![100_000, 99_000, 101_000]);
+        assert_eq!(history[0].0, time("2023-01-03 00:00"));
+        assert!(value_history(&[], &closes, 100_000).is_empty());
+    }
+
+    #[test]
+    fn test_leaderboard_lines() {
+        let lines = leaderboard_lines(vec![
+            (1, STARTING_CASH_CENTS),
+            (2, STARTING_CASH_CENTS + 100_000),
+            (3, STARTING_CASH_CENTS - 100_000),
+        ]);
+        assert_eq!(lines[0], "1. <@2> $101,000.00 +$1,000.00 (+1.00%)");
+        assert!(lines[2].starts_with("3. <@3>"));
+    }
+}
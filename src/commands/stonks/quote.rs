@@ -0,0 +1,346 @@
+//! The `/quote` command and the per-symbol cache behind it.
+
+use super::assets::canonical_symbol;
+use super::chart::render_sparkline;
+use super::market::{Bar, Interval, MarketDataProvider, MarketError, Quote, TimeRange};
+use poise::serenity_prelude::{Colour, CreateAttachment, CreateEmbed, CreateEmbedFooter};
+use poise::CreateReply;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Used when `QUOTE_CACHE_TTL_SECS` is not set.
+pub const DEFAULT_QUOTE_TTL: Duration = Duration::from_secs(60);
+/// Most symbols quoted in one reply.
+pub(crate) const MAX_QUOTES: usize = 5;
+
+/// Everything a quote embed shows for one symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteSnapshot {
+    pub quote: Quote,
+    /// Weekly bars for the last year, oldest first.
+    pub year: Vec<Bar>,
+}
+
+impl QuoteSnapshot {
+    /// Lowest and highest price over the last 52 weeks, including today.
+    pub fn year_range(&self) -> (f64, f64) {
+        self.year
+            .iter()
+            .fold((self.quote.low, self.quote.high), |(low, high), bar| {
+                (low.min(bar.low), high.max(bar.high))
+            })
+    }
+}
+
+/// Recently fetched quotes, kept for a fixed time so repeated lookups of the
+/// same symbol don't spend the provider's rate limit.
+#[derive(Debug)]
+pub struct QuoteCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, QuoteSnapshot)>>,
+}
+
+impl QuoteCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Read the time to live from `QUOTE_CACHE_TTL_SECS`.
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("QUOTE_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_QUOTE_TTL);
+        Self::new(ttl)
+    }
+
+    /// The cached snapshot for `symbol`, if it hasn't expired.
+    pub fn get(&self, symbol: &str) -> Option<QuoteSnapshot> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&symbol.to_uppercase())
+            .filter(|(fetched, _)| fetched.elapsed() < self.ttl)
+            .map(|(_, snapshot)| snapshot.clone())
+    }
+
+    /// Remember `snapshot`, dropping any entries that have expired.
+    pub fn insert(&self, symbol: &str, snapshot: QuoteSnapshot) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (fetched, _)| fetched.elapsed() < self.ttl);
+        entries.insert(symbol.to_uppercase(), (Instant::now(), snapshot));
+    }
+}
+
+impl Default for QuoteCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUOTE_TTL)
+    }
+}
+
+/// Fetch the quote and last year of weekly bars for `symbol`, or use the
+/// cached ones while they are fresh.
+pub async fn quote_snapshot(
+    provider: &impl MarketDataProvider,
+    cache: &QuoteCache,
+    symbol: &str,
+) -> Result<QuoteSnapshot, MarketError> {
+    if let Some(snapshot) = cache.get(symbol) {
+        return Ok(snapshot);
+    }
+    let quote = provider.quote(symbol).await?;
+    let mut year = provider
+        .series(symbol, Interval::Weekly, TimeRange::OneYear)
+        .await?;
+    TimeRange::OneYear.trim(&mut year);
+    let snapshot = QuoteSnapshot { quote, year };
+    cache.insert(symbol, snapshot.clone());
+    Ok(snapshot)
+}
+
+/// Shorten a share count, e.g. `3.46M`.
+pub(crate) fn format_volume(volume: u64) -> String {
+    let volume = volume as f64;
+    match volume {
+        v if v >= 1e9 => format!("{:.2}B", v / 1e9),
+        v if v >= 1e6 => format!("{:.2}M", v / 1e6),
+        v if v >= 1e3 => format!("{:.1}K", v / 1e3),
+        v => format!("{}", v),
+    }
+}
+
+/// Format a price with enough decimals to show it: two from 1 upwards, more
+/// for exchange rates and small coins.
+pub(crate) fn format_price(price: f64) -> String {
+    match price.abs() {
+        p if p >= 1.0 || p == 0.0 => format!("{:.2}", price),
+        p if p >= 0.01 => format!("{:.4}", price),
+        _ => format!("{:.8}", price),
+    }
+}
+
+/// Put a comma between each group of three digits, e.g. `1234567` becomes
+/// `1,234,567`.
+pub(crate) fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Price, change and direction, e.g. `**141.00** ▼ -9.00 (-6.00%)`.
+pub(crate) fn headline(quote: &Quote) -> String {
+    let arrow = if quote.change() >= 0.0 { "▲" } else { "▼" };
+    let change = format_price(quote.change().abs());
+    let sign = if quote.change() >= 0.0 { "+" } else { "-" };
+    format!(
+        "**{}** {} {}{} ({:+.2}%)",
+        format_price(quote.price),
+        arrow,
+        sign,
+        change,
+        quote.change_percent()
+    )
+}
+
+fn sparkline_name(symbol: &str) -> String {
+    format!("{}_spark.png", symbol.to_lowercase())
+}
+
+fn quote_embed(snapshot: &QuoteSnapshot, sparkline: bool) -> CreateEmbed {
+    let quote = &snapshot.quote;
+    let (year_low, year_high) = snapshot.year_range();
+    let colour = if quote.change() >= 0.0 {
+        Colour::DARK_GREEN
+    } else {
+        Colour::RED
+    };
+    let mut embed = CreateEmbed::new()
+        .title(&quote.symbol)
+        .description(headline(quote))
+        .field(
+            "Day range",
+            format!("{} – {}", format_price(quote.low), format_price(quote.high)),
+            true,
+        )
+        .field(
+            "52-week range",
+            format!("{} – {}", format_price(year_low), format_price(year_high)),
+            true,
+        );
+    // Currency pairs have no volume.
+    if quote.volume > 0 {
+        embed = embed.field("Volume", format_volume(quote.volume), true);
+    }
+    embed = embed
+        .field("Open", format_price(quote.open), true)
+        .field("Previous close", format_price(quote.previous_close), true)
+        .footer(CreateEmbedFooter::new(format!("As of {}", quote.day)))
+        .colour(colour);
+    if sparkline {
+        embed = embed.image(format!("attachment://{}", sparkline_name(&quote.symbol)));
+    }
+    embed
+}
+
+/// Add `snapshot`'s embed, with its sparkline attached, to `reply`.
+pub(crate) fn add_quote(mut reply: CreateReply, snapshot: &QuoteSnapshot) -> CreateReply {
+    let symbol = &snapshot.quote.symbol;
+    let closes: Vec<f64> = snapshot.year.iter().map(|bar| bar.close).collect();
+    let sparkline = match render_sparkline(&closes) {
+        Ok(png) => {
+            reply = reply.attachment(CreateAttachment::bytes(png, sparkline_name(symbol)));
+            true
+        }
+        Err(e) => {
+            warn!("Failed to draw sparkline for {}: {}", symbol, e);
+            false
+        }
+    };
+    reply.embed(quote_embed(snapshot, sparkline))
+}
+
+/// Show the latest price and key stats for up to five ticker symbols.
+/// Usage: /quote AAPL MSFT
+#[poise::command(slash_command, prefix_command)]
+pub async fn quote(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[rest] tickers: String,
+) -> Result<(), crate::Error> {
+    let mut symbols: Vec<String> = Vec::new();
+    for ticker in tickers.split(|c: char| c.is_whitespace() || c == ',') {
+        let ticker = canonical_symbol(ticker);
+        if !ticker.is_empty() && !symbols.contains(&ticker) {
+            symbols.push(ticker);
+        }
+    }
+    if symbols.is_empty() || symbols.len() > MAX_QUOTES {
+        ctx.say(format!("Give me between 1 and {} tickers", MAX_QUOTES))
+            .await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let data = ctx.data();
+    let mut reply = CreateReply::default();
+    let mut failures = Vec::new();
+    for symbol in &symbols {
+        let snapshot = match quote_snapshot(&data.market_data, &data.quote_cache, symbol).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                failures.push(format!("{}: {}", symbol, e));
+                continue;
+            }
+        };
+        reply = add_quote(reply, &snapshot);
+    }
+    if !failures.is_empty() {
+        reply = reply.content(failures.join("\n"));
+    }
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::stonks::market::FixtureProvider;
+    use chrono::{NaiveDate, NaiveTime};
+
+    fn provider() -> FixtureProvider {
+        let start = NaiveDate::from_ymd_opt(2023, 1, 2)
+            .unwrap()
+            .and_time(NaiveTime::MIN);
+        let bars = (0..400)
+            .map(|i| Bar {
+                time: start + chrono::Duration::days(i),
+                open: 100.0,
+                high: 100.0 + i as f64,
+                low: 50.0 + i as f64,
+                close: 100.0,
+                volume: 10,
+            })
+            .collect();
+        let mut provider = FixtureProvider::new();
+        provider.insert("AAPL", Interval::Daily, bars);
+        provider
+    }
+
+    #[tokio::test]
+    async fn test_quote_snapshot() {
+        let cache = QuoteCache::default();
+        let snapshot = quote_snapshot(&provider(), &cache, "aapl").await.unwrap();
+        assert_eq!(snapshot.quote.symbol, "AAPL");
+        assert_eq!(snapshot.quote.high, 499.0);
+        // The last weekly bar starts on 2024-02-05, so the year's first full
+        // week starts on 2023-02-06, day 35 of the series.
+        assert_eq!(snapshot.year_range(), (85.0, 499.0));
+        assert!(cache.get("AAPL").is_some());
+
+        let missing = quote_snapshot(&provider(), &cache, "MSFT").await;
+        assert_eq!(missing, Err(MarketError::UnknownSymbol("MSFT".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_quote_cache_expires() {
+        let snapshot = quote_snapshot(&provider(), &QuoteCache::default(), "AAPL")
+            .await
+            .unwrap();
+        let fresh = QuoteCache::new(Duration::from_secs(60));
+        fresh.insert("aapl", snapshot.clone());
+        assert_eq!(fresh.get("AAPL"), Some(snapshot.clone()));
+        let stale = QuoteCache::new(Duration::ZERO);
+        stale.insert("AAPL", snapshot);
+        assert_eq!(stale.get("AAPL"), None);
+        // A stale cache entry is replaced by a fresh fetch.
+        assert!(quote_snapshot(&provider(), &stale, "AAPL").await.is_ok());
+    }
+
+    #[test]
+    fn test_format_volume() {
+        assert_eq!(format_volume(950), "950");
+        assert_eq!(format_volume(12_345), "12.3K");
+        assert_eq!(format_volume(3_456_789), "3.46M");
+        assert_eq!(format_volume(2_000_000_000), "2.00B");
+    }
+
+    #[test]
+    fn test_headline() {
+        let quote = Quote {
+            symbol: "IBM".to_string(),
+            day: NaiveDate::from_ymd_opt(2023, 1, 6).unwrap(),
+            price: 141.0,
+            open: 140.0,
+            high: 142.5,
+            low: 139.25,
+            previous_close: 150.0,
+            volume: 1,
+        };
+        assert_eq!(headline(&quote), "**141.00** ▼ -9.00 (-6.00%)");
+    }
+
+    #[test]
+    fn test_format_price() {
+        assert_eq!(format_price(141.0), "141.00");
+        assert_eq!(format_price(0.0), "0.00");
+        assert_eq!(format_price(0.9215), "0.9215");
+        assert_eq!(format_price(0.00001234), "0.00001234");
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands("0"), "0");
+        assert_eq!(group_thousands("999"), "999");
+        assert_eq!(group_thousands("1000"), "1,000");
+        assert_eq!(group_thousands("1234567"), "1,234,567");
+    }
+}
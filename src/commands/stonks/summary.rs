@@ -0,0 +1,789 @@
+//! Scheduled market open and close summaries, posted to a guild channel by
+//! [`poll_summaries`] and managed with `/marketsummary`.
+
+use super::assets::canonical_symbol;
+use super::calendar::holiday;
+use super::chart::{align, render_comparison_chart};
+use super::market::{Bar, Interval, MarketDataProvider, MarketError, Quote, TimeRange};
+use super::quote::headline;
+use crate::commands::require_guild;
+use crate::models::ScheduledPost;
+use crate::schema::scheduled_posts;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use poise::serenity_prelude::{
+    ChannelId, Colour, CreateAttachment, CreateEmbed, CreateMessage, Http, Permissions,
+};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Used when `/marketsummary set` isn't given a timezone.
+pub const DEFAULT_TIMEZONE: &str = "America/New_York";
+/// Monday to Friday, the default posting days.
+pub const WEEKDAYS: i16 = 0b001_1111;
+/// Every day of the week.
+pub const EVERY_DAY: i16 = 0b111_1111;
+/// Most symbols in one summary.
+pub(crate) const MAX_SUMMARY_SYMBOLS: usize = 8;
+/// How late a summary may still go out, e.g. after a restart. Later than
+/// this it is skipped until its next run.
+pub(crate) const GRACE_MINUTES: i64 = 15;
+/// How often [`poll_summaries`] looks for due posts.
+pub const SUMMARY_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Which summary a schedule posts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum SummaryKind {
+    #[name = "open"]
+    Open,
+    #[name = "close"]
+    Close,
+}
+
+impl SummaryKind {
+    pub fn key(self) -> &'static str {
+        match self {
+            SummaryKind::Open => "open",
+            SummaryKind::Close => "close",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "open" => Some(SummaryKind::Open),
+            "close" => Some(SummaryKind::Close),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SummaryKind::Open => "Market open",
+            SummaryKind::Close => "Market close",
+        }
+    }
+
+    /// The New York session's opening bell or closing bell.
+    pub fn default_time(self) -> NaiveTime {
+        match self {
+            SummaryKind::Open => NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            SummaryKind::Close => NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+        }
+    }
+}
+
+fn weekday_bit(day: Weekday) -> i16 {
+    1 << day.num_days_from_monday()
+}
+
+/// Whether the weekday bitmask `mask` (Monday is bit 0) includes `day`.
+pub fn runs_on(mask: i16, day: Weekday) -> bool {
+    mask & weekday_bit(day) != 0
+}
+
+/// Parse days like `mon-fri`, `mon,wed,fri`, `weekdays` or `daily` into a
+/// weekday bitmask. Ranges may wrap, e.g. `fri-mon`.
+pub fn parse_weekdays(spec: &str) -> Result<i16, String> {
+    let spec = spec.trim().to_lowercase();
+    match spec.as_str() {
+        "weekdays" => return Ok(WEEKDAYS),
+        "daily" | "every day" | "all" => return Ok(EVERY_DAY),
+        _ => {}
+    }
+    let day = |name: &str| {
+        name.trim()
+            .parse::<Weekday>()
+            .map_err(|_| format!("'{}' isn't a day of the week", name.trim()))
+    };
+    let mut mask = 0;
+    for part in spec.split(',').filter(|part| !part.trim().is_empty()) {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (mut current, to) = (day(from)?, day(to)?);
+                mask |= weekday_bit(current);
+                while current != to {
+                    current = current.succ();
+                    mask |= weekday_bit(current);
+                }
+            }
+            None => mask |= weekday_bit(day(part)?),
+        }
+    }
+    if mask == 0 {
+        return Err("Give at least one day of the week".to_string());
+    }
+    Ok(mask)
+}
+
+/// Describe a weekday bitmask, e.g. `Mon–Fri` or `Mon, Wed, Fri`.
+pub fn format_weekdays(mask: i16) -> String {
+    match mask {
+        WEEKDAYS => "Mon–Fri".to_string(),
+        EVERY_DAY => "every day".to_string(),
+        _ => {
+            let days: Vec<String> = (0..7)
+                .filter_map(|index: u8| Weekday::try_from(index).ok())
+                .filter(|day| runs_on(mask, *day))
+                .map(|day| day.to_string())
+                .collect();
+            days.join(", ")
+        }
+    }
+}
+
+/// Parse a `HH:MM` local time.
+pub fn parse_post_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| format!("'{}' isn't a time like 09:30", value.trim()))
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| format!("'{}' isn't a timezone like America/New_York", name.trim()))
+}
+
+/// When a summary goes out: `time` in `tz` on the chosen weekdays, skipping
+/// US market holidays if asked to.
+#[derive(Debug, Clone)]
+pub struct Schedule<Z: TimeZone> {
+    pub time: NaiveTime,
+    pub tz: Z,
+    pub weekdays: i16,
+    pub skip_holidays: bool,
+}
+
+impl<Z: TimeZone> Schedule<Z> {
+    /// Whether the schedule posts on local `date`.
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        runs_on(self.weekdays, date.weekday()) && !(self.skip_holidays && holiday(date).is_some())
+    }
+
+    /// The instant of the run on local `date`. A time skipped by a daylight
+    /// saving change moves an hour later; a repeated one uses the first.
+    fn at(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        let local = date.and_time(self.time);
+        self.tz
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.tz
+                    .from_local_datetime(&(local + chrono::Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|time| time.with_timezone(&Utc))
+    }
+
+    /// The most recent run at or before `now`, looking back a day.
+    pub fn latest(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&self.tz).date_naive();
+        [Some(today), today.pred_opt()]
+            .into_iter()
+            .flatten()
+            .filter(|date| self.runs_on(*date))
+            .filter_map(|date| self.at(date))
+            .find(|run| *run <= now)
+    }
+
+    /// The first run after `now`, within the next two weeks.
+    pub fn next(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&self.tz).date_naive();
+        today
+            .iter_days()
+            .take(15)
+            .filter(|date| self.runs_on(*date))
+            .filter_map(|date| self.at(date))
+            .find(|run| *run > now)
+    }
+
+    /// The run to post now, if one started less than [`GRACE_MINUTES`] ago
+    /// and hasn't been posted since.
+    pub fn due(
+        &self,
+        now: DateTime<Utc>,
+        last_posted: Option<NaiveDateTime>,
+    ) -> Option<DateTime<Utc>> {
+        let run = self.latest(now)?;
+        let fresh = now - run <= chrono::Duration::minutes(GRACE_MINUTES);
+        let posted = last_posted.is_some_and(|last| last >= run.naive_utc());
+        (fresh && !posted).then_some(run)
+    }
+}
+
+/// The schedule a stored post describes.
+pub fn schedule_for(post: &ScheduledPost) -> Result<Schedule<Tz>, String> {
+    Ok(Schedule {
+        time: post.post_time,
+        tz: parse_timezone(&post.timezone)?,
+        weekdays: post.weekdays,
+        skip_holidays: post.skip_holidays,
+    })
+}
+
+/// The symbols to summarise: canonical, deduplicated and at most
+/// [`MAX_SUMMARY_SYMBOLS`].
+fn summary_symbols(tickers: &str) -> Result<Vec<String>, String> {
+    let mut symbols: Vec<String> = Vec::new();
+    for ticker in tickers.split(|c: char| c.is_whitespace() || c == ',') {
+        let ticker = canonical_symbol(ticker);
+        if !ticker.is_empty() && !symbols.contains(&ticker) {
+            symbols.push(ticker);
+        }
+    }
+    if symbols.is_empty() || symbols.len() > MAX_SUMMARY_SYMBOLS {
+        return Err(format!(
+            "Give me between 1 and {} tickers",
+            MAX_SUMMARY_SYMBOLS
+        ));
+    }
+    Ok(symbols)
+}
+
+/// One line per symbol with its price and change, or why it's missing.
+fn summary_lines(quotes: &[(String, Result<Quote, MarketError>)]) -> String {
+    quotes
+        .iter()
+        .map(|(symbol, quote)| match quote {
+            Ok(quote) => format!("`{}` {}", symbol, headline(quote)),
+            Err(e) => format!("`{}` unavailable ({})", symbol, e),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The percent change of `symbols` over the last month on one chart.
+async fn summary_chart(
+    market: &(impl MarketDataProvider + Sync),
+    symbols: &[String],
+) -> Result<Option<Vec<u8>>, crate::Error> {
+    let range = TimeRange::OneMonth;
+    let mut named: Vec<(String, Vec<Bar>)> = Vec::new();
+    for symbol in symbols {
+        match market.series(symbol, Interval::Daily, range).await {
+            Ok(mut bars) => {
+                range.trim(&mut bars);
+                named.push((symbol.clone(), bars));
+            }
+            Err(e) => warn!("Leaving {} out of the summary chart: {}", symbol, e),
+        }
+    }
+    let mut series: Vec<Vec<Bar>> = named.iter().map(|(_, bars)| bars.clone()).collect();
+    align(&mut series);
+    if series.is_empty() || series.iter().any(|bars| bars.is_empty()) {
+        return Ok(None);
+    }
+    let names: Vec<String> = named.into_iter().map(|(symbol, _)| symbol).collect();
+    let title = format!("{} ({})", names.join(" vs "), range.label());
+    let named: Vec<(String, Vec<Bar>)> = names.into_iter().zip(series).collect();
+    Ok(Some(render_comparison_chart(&title, &named)?))
+}
+
+/// Build the summary message for `symbols` on local `day`.
+pub async fn build_summary(
+    market: &(impl MarketDataProvider + Sync),
+    kind: SummaryKind,
+    symbols: &[String],
+    day: NaiveDate,
+) -> CreateMessage {
+    let mut quotes = Vec::with_capacity(symbols.len());
+    for symbol in symbols {
+        quotes.push((symbol.clone(), market.quote(symbol).await));
+    }
+    let up = quotes
+        .iter()
+        .filter_map(|(_, quote)| quote.as_ref().ok())
+        .filter(|quote| quote.change() >= 0.0)
+        .count();
+    let colour = if up * 2 >= quotes.len() {
+        Colour::DARK_GREEN
+    } else {
+        Colour::RED
+    };
+    let mut embed = CreateEmbed::new()
+        .title(format!("{} · {}", kind.label(), day.format("%a %-d %b %Y")))
+        .description(summary_lines(&quotes))
+        .colour(colour);
+    let mut message = CreateMessage::new();
+    match summary_chart(market, symbols).await {
+        Ok(Some(png)) => {
+            message = message.add_file(CreateAttachment::bytes(png, "summary.png"));
+            embed = embed.image("attachment://summary.png");
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Couldn't draw the market summary chart: {}", e),
+    }
+    message.embed(embed)
+}
+
+/// Look for due summaries every `every`, forever.
+pub async fn poll_summaries(
+    http: impl AsRef<Http> + Send + Sync,
+    db_pool: Pool<ConnectionManager<PgConnection>>,
+    market: impl MarketDataProvider + Send + Sync,
+    every: Duration,
+) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        match post_due_summaries(http.as_ref(), &db_pool, &market).await {
+            Ok(0) => {}
+            Ok(posted) => info!("Posted {} market summary(ies)", posted),
+            Err(e) => warn!("Market summary check failed: {}", e),
+        }
+    }
+}
+
+/// Post every summary that is due, returning how many went out. Each post
+/// is claimed by moving its `last_posted_at` on before sending, so a run is
+/// never posted twice.
+pub async fn post_due_summaries(
+    http: &Http,
+    db_pool: &Pool<ConnectionManager<PgConnection>>,
+    market: &(impl MarketDataProvider + Sync),
+) -> Result<usize, crate::Error> {
+    let posts: Vec<ScheduledPost> = {
+        let mut conn = db_pool.get()?;
+        scheduled_posts::table
+            .select(ScheduledPost::as_select())
+            .load(&mut conn)?
+    };
+    let now = Utc::now();
+    let mut posted = 0;
+    for post in posts {
+        let (schedule, kind) = match (schedule_for(&post), SummaryKind::from_key(&post.kind)) {
+            (Ok(schedule), Some(kind)) => (schedule, kind),
+            (Err(e), _) => {
+                warn!("Skipping market summary #{}: {}", post.id, e);
+                continue;
+            }
+            (_, None) => {
+                warn!(
+                    "Skipping market summary #{} of kind '{}'",
+                    post.id, post.kind
+                );
+                continue;
+            }
+        };
+        let Some(run) = schedule.due(now, post.last_posted_at) else {
+            continue;
+        };
+        let claimed = {
+            let mut conn = db_pool.get()?;
+            diesel::update(
+                scheduled_posts::table
+                    .filter(scheduled_posts::id.eq(post.id))
+                    .filter(
+                        scheduled_posts::last_posted_at.is_not_distinct_from(post.last_posted_at),
+                    ),
+            )
+            .set(scheduled_posts::last_posted_at.eq(run.naive_utc()))
+            .execute(&mut conn)?
+        };
+        if claimed == 0 {
+            continue;
+        }
+        let symbols: Vec<String> = post.symbols.split_whitespace().map(String::from).collect();
+        let day = run.with_timezone(&schedule.tz).date_naive();
+        let message = build_summary(market, kind, &symbols, day).await;
+        if let Err(e) = ChannelId::new(post.channel_id as u64)
+            .send_message(http, message)
+            .await
+        {
+            warn!("Couldn't post market summary #{}: {}", post.id, e);
+            continue;
+        }
+        posted += 1;
+    }
+    Ok(posted)
+}
+
+/// Describe a stored post for `/marketsummary show`.
+fn describe_post(post: &ScheduledPost, now: DateTime<Utc>) -> String {
+    let kind = SummaryKind::from_key(&post.kind).map_or(post.kind.as_str(), SummaryKind::label);
+    let holidays = if post.skip_holidays {
+        ", skipping market holidays"
+    } else {
+        ""
+    };
+    let next = match schedule_for(post).map(|schedule| schedule.next(now)) {
+        Ok(Some(next)) => format!(" Next: <t:{}:F>.", next.timestamp()),
+        Ok(None) => String::new(),
+        Err(e) => format!(" ({})", e),
+    };
+    format!(
+        "**{}**: {} in <#{}> at {} {}, {}{}.{}",
+        kind,
+        post.symbols,
+        post.channel_id,
+        post.post_time.format("%H:%M"),
+        post.timezone,
+        format_weekdays(post.weekdays),
+        holidays,
+        next
+    )
+}
+
+fn load_posts(conn: &mut PgConnection, guild: i64) -> QueryResult<Vec<ScheduledPost>> {
+    scheduled_posts::table
+        .filter(scheduled_posts::guild_id.eq(guild))
+        .order(scheduled_posts::kind.desc())
+        .select(ScheduledPost::as_select())
+        .load(conn)
+}
+
+/// Why summaries can't be posted in `channel` from this server, if they
+/// can't: it must belong to the server and the bot must be able to post an
+/// embed with a chart there.
+async fn unusable_channel(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    channel: ChannelId,
+) -> Result<Option<&'static str>, crate::Error> {
+    let Ok(channel) = channel.to_channel(ctx).await else {
+        return Ok(Some("I can't see that channel."));
+    };
+    let Some(channel) = channel
+        .guild()
+        .filter(|channel| Some(channel.guild_id) == ctx.guild_id())
+    else {
+        return Ok(Some("Pick a channel in this server."));
+    };
+    let bot_id = ctx.cache().current_user().id;
+    let bot = channel.guild_id.member(ctx, bot_id).await?;
+    let permissions = match ctx.guild() {
+        Some(guild) => guild.user_permissions_in(&channel, &bot),
+        None => return Err("This server isn't cached yet, try again shortly.".into()),
+    };
+    let needed = Permissions::VIEW_CHANNEL
+        | Permissions::SEND_MESSAGES
+        | Permissions::EMBED_LINKS
+        | Permissions::ATTACH_FILES;
+    if !permissions.contains(needed) {
+        return Ok(Some(
+            "I need permission to view, send messages, embed links and attach files in that channel.",
+        ));
+    }
+    Ok(None)
+}
+
+/// Manage this server's scheduled market summaries.
+/// Usage: /marketsummary set|clear|show
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("set", "clear", "show")
+)]
+pub async fn marketsummary(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<(), crate::Error> {
+    show_summaries(ctx).await
+}
+
+/// Schedule a market open or close summary.
+/// Usage: /marketsummary set open #markets SPY QQQ [options]
+///
+/// Options are the local time, timezone, days to post and whether to skip
+/// market holidays.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Post at the market open or close"] kind: SummaryKind,
+    #[description = "Channel to post in"] channel: ChannelId,
+    #[description = "Tickers to summarise, e.g. SPY QQQ AAPL"] tickers: String,
+    #[description = "Local time as HH:MM (default 09:30 open, 16:00 close)"] time: Option<String>,
+    #[description = "Timezone (default America/New_York)"] timezone: Option<String>,
+    #[description = "Days to post, e.g. mon-fri or mon,wed,fri"] days: Option<String>,
+    #[description = "Skip US market holidays (default yes)"] skip_holidays: Option<bool>,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    if let Some(problem) = unusable_channel(ctx, channel).await? {
+        ctx.say(problem).await?;
+        return Ok(());
+    }
+    let parsed = summary_symbols(&tickers).and_then(|symbols| {
+        let time = match &time {
+            Some(time) => parse_post_time(time)?,
+            None => kind.default_time(),
+        };
+        let timezone = timezone.as_deref().unwrap_or(DEFAULT_TIMEZONE);
+        let tz = parse_timezone(timezone)?;
+        let weekdays = match &days {
+            Some(days) => parse_weekdays(days)?,
+            None => WEEKDAYS,
+        };
+        Ok((symbols, time, tz, weekdays))
+    });
+    let (symbols, time, tz, weekdays) = match parsed {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+    };
+    let skip_holidays = skip_holidays.unwrap_or(true);
+    let warning = ctx.data().symbols.warning(&symbols);
+    let symbols = symbols.join(" ");
+    let now = Utc::now().naive_utc();
+    let post = {
+        let mut conn = ctx.data().db_pool.get()?;
+        diesel::insert_into(scheduled_posts::table)
+            .values((
+                scheduled_posts::guild_id.eq(guild),
+                scheduled_posts::channel_id.eq(channel.get() as i64),
+                scheduled_posts::kind.eq(kind.key()),
+                scheduled_posts::post_time.eq(time),
+                scheduled_posts::timezone.eq(tz.name()),
+                scheduled_posts::weekdays.eq(weekdays),
+                scheduled_posts::skip_holidays.eq(skip_holidays),
+                scheduled_posts::symbols.eq(&symbols),
+                scheduled_posts::created_by.eq(ctx.author().id.get() as i64),
+                scheduled_posts::updated_at.eq(now),
+            ))
+            .on_conflict((scheduled_posts::guild_id, scheduled_posts::kind))
+            .do_update()
+            .set((
+                scheduled_posts::channel_id.eq(channel.get() as i64),
+                scheduled_posts::post_time.eq(time),
+                scheduled_posts::timezone.eq(tz.name()),
+                scheduled_posts::weekdays.eq(weekdays),
+                scheduled_posts::skip_holidays.eq(skip_holidays),
+                scheduled_posts::symbols.eq(&symbols),
+                scheduled_posts::created_by.eq(ctx.author().id.get() as i64),
+                scheduled_posts::updated_at.eq(now),
+            ))
+            .returning(ScheduledPost::as_returning())
+            .get_result(&mut conn)?
+    };
+    let mut msg = format!("Scheduled. {}", describe_post(&post, Utc::now()));
+    if let Some(warning) = warning {
+        msg.push('\n');
+        msg.push_str(&warning);
+    }
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Stop posting a market summary, or both if no kind is given.
+/// Usage: /marketsummary clear [open|close]
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn clear(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Which summary to stop (default both)"] kind: Option<SummaryKind>,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let removed = {
+        let mut conn = ctx.data().db_pool.get()?;
+        let posts = scheduled_posts::table.filter(scheduled_posts::guild_id.eq(guild));
+        match kind {
+            Some(kind) => diesel::delete(posts.filter(scheduled_posts::kind.eq(kind.key())))
+                .execute(&mut conn)?,
+            None => diesel::delete(posts).execute(&mut conn)?,
+        }
+    };
+    let msg = match removed {
+        0 => "There was nothing scheduled to clear.".to_string(),
+        1 => "Cleared 1 market summary.".to_string(),
+        n => format!("Cleared {} market summaries.", n),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Show this server's scheduled market summaries.
+/// Usage: /marketsummary show
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn show(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    show_summaries(ctx).await
+}
+
+async fn show_summaries(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<(), crate::Error> {
+    let guild = require_guild(&ctx)?;
+    let posts = {
+        let mut conn = ctx.data().db_pool.get()?;
+        load_posts(&mut conn, guild)?
+    };
+    if posts.is_empty() {
+        ctx.say("No market summaries are scheduled. Add one with /marketsummary set.")
+            .await?;
+        return Ok(());
+    }
+    let now = Utc::now();
+    let lines: Vec<String> = posts.iter().map(|post| describe_post(post, now)).collect();
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc()
+    }
+
+    fn schedule(time: &str) -> Schedule<FixedOffset> {
+        Schedule {
+            time: parse_post_time(time).unwrap(),
+            tz: FixedOffset::west_opt(5 * 3600).unwrap(),
+            weekdays: WEEKDAYS,
+            skip_holidays: true,
+        }
+    }
+
+    #[test]
+    fn test_parse_weekdays() {
+        assert_eq!(parse_weekdays("mon-fri"), Ok(WEEKDAYS));
+        assert_eq!(parse_weekdays("Weekdays"), Ok(WEEKDAYS));
+        assert_eq!(parse_weekdays("daily"), Ok(EVERY_DAY));
+        assert_eq!(parse_weekdays("mon, wed,friday"), Ok(0b001_0101));
+        assert_eq!(parse_weekdays("fri-mon"), Ok(0b111_0001));
+        assert!(parse_weekdays("funday").is_err());
+        assert!(parse_weekdays(",").is_err());
+
+        assert_eq!(format_weekdays(WEEKDAYS), "Mon–Fri");
+        assert_eq!(format_weekdays(0b001_0101), "Mon, Wed, Fri");
+        assert_eq!(format_weekdays(EVERY_DAY), "every day");
+    }
+
+    #[test]
+    fn test_parse_post_time_and_timezone() {
+        assert_eq!(
+            parse_post_time("9:30"),
+            Ok(NaiveTime::from_hms_opt(9, 30, 0).unwrap())
+        );
+        assert!(parse_post_time("25:00").is_err());
+        assert_eq!(
+            parse_timezone("Europe/London").unwrap().name(),
+            "Europe/London"
+        );
+        assert!(parse_timezone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_schedule_latest_and_next() {
+        // 09:30 at UTC-5 is 14:30 UTC. 2024-03-28 is a Thursday and the 29th
+        // is Good Friday.
+        let schedule = schedule("09:30");
+        assert_eq!(
+            schedule.latest(utc("2024-03-28 15:00")),
+            Some(utc("2024-03-28 14:30"))
+        );
+        assert_eq!(
+            schedule.latest(utc("2024-03-28 14:00")),
+            Some(utc("2024-03-27 14:30"))
+        );
+        assert_eq!(
+            schedule.latest(utc("2024-03-29 15:00")),
+            Some(utc("2024-03-28 14:30"))
+        );
+        assert_eq!(schedule.latest(utc("2024-03-31 15:00")), None);
+        assert_eq!(
+            schedule.next(utc("2024-03-28 15:00")),
+            Some(utc("2024-04-01 14:30"))
+        );
+    }
+
+    #[test]
+    fn test_schedule_due() {
+        let schedule = schedule("09:30");
+        let run = utc("2024-03-28 14:30");
+        assert_eq!(schedule.due(utc("2024-03-28 14:31"), None), Some(run));
+        assert_eq!(
+            schedule.due(
+                utc("2024-03-28 14:31"),
+                Some(utc("2024-03-27 14:30").naive_utc())
+            ),
+            Some(run)
+        );
+        // Already posted, or too late after a restart.
+        assert_eq!(
+            schedule.due(utc("2024-03-28 14:32"), Some(run.naive_utc())),
+            None
+        );
+        assert_eq!(schedule.due(utc("2024-03-28 15:00"), None), None);
+        // Holidays are posted when asked to.
+        let mut every_day = schedule.clone();
+        every_day.skip_holidays = false;
+        assert_eq!(
+            every_day.due(utc("2024-03-29 14:35"), None),
+            Some(utc("2024-03-29 14:30"))
+        );
+    }
+
+    #[test]
+    fn test_schedule_follows_daylight_saving() {
+        let schedule = Schedule {
+            time: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            tz: chrono_tz::America::New_York,
+            weekdays: WEEKDAYS,
+            skip_holidays: true,
+        };
+        assert_eq!(
+            schedule.next(utc("2024-01-08 00:00")),
+            Some(utc("2024-01-08 14:30"))
+        );
+        assert_eq!(
+            schedule.next(utc("2024-07-08 00:00")),
+            Some(utc("2024-07-08 13:30"))
+        );
+    }
+
+    #[test]
+    fn test_summary_symbols() {
+        assert_eq!(
+            summary_symbols("spy, qqq SPY btc/usd").unwrap(),
+            vec!["SPY", "QQQ", "BTC-USD"]
+        );
+        assert!(summary_symbols("").is_err());
+        assert!(summary_symbols("A B C D E F G H I").is_err());
+    }
+
+    #[test]
+    fn test_summary_lines() {
+        let quote = Quote {
+            symbol: "SPY".to_string(),
+            day: NaiveDate::from_ymd_opt(2024, 3, 28).unwrap(),
+            price: 110.0,
+            open: 100.0,
+            high: 111.0,
+            low: 99.0,
+            previous_close: 100.0,
+            volume: 1,
+        };
+        let lines = summary_lines(&[
+            ("SPY".to_string(), Ok(quote)),
+            (
+                "XYZ".to_string(),
+                Err(MarketError::UnknownSymbol("XYZ".to_string())),
+            ),
+        ]);
+        assert_eq!(
+            lines,
+            "`SPY` **110.00** ▲ +10.00 (+10.00%)\n`XYZ` unavailable (Unknown symbol 'XYZ')"
+        );
+    }
+}
@@ -0,0 +1,522 @@
+//! The directory of known ticker symbols, used to suggest fixes for typos and
+//! to autocomplete ticker options.
+//!
+//! The bot starts from the listing last saved by `/refreshsymbols`, or the
+//! short one bundled in `data/listing_status.csv` until an owner fetches the
+//! provider's full listing. Either way it is only advice: the provider
+//! decides which symbols exist.
+
+use super::assets::AssetClass;
+use super::market::{normalize_symbol, MarketError};
+use poise::serenity_prelude::AutocompleteChoice;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+
+/// The listing compiled into the bot, in Alpha Vantage `LISTING_STATUS` form.
+pub const BUNDLED_LISTING: &str = include_str!("../../../data/listing_status.csv");
+/// Where `/refreshsymbols` saves the listing when `SYMBOL_LISTING_PATH` is
+/// not set.
+pub const DEFAULT_LISTING_PATH: &str = "symbol_listing.csv";
+/// Most symbols suggested for a typo.
+pub(crate) const MAX_SUGGESTIONS: usize = 3;
+/// Discord shows at most 25 autocomplete choices.
+pub(crate) const MAX_CHOICES: usize = 25;
+/// Discord's limit on an autocomplete choice label.
+const MAX_CHOICE_LEN: usize = 100;
+
+/// One listed security.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub symbol: String,
+    pub name: String,
+    pub exchange: String,
+    pub asset_type: String,
+}
+
+impl Listing {
+    /// How the listing is shown in autocomplete, e.g. `AAPL · Apple Inc (NASDAQ)`.
+    pub fn label(&self) -> String {
+        let label = if self.exchange.is_empty() {
+            format!("{} · {}", self.symbol, self.name)
+        } else {
+            format!("{} · {} ({})", self.symbol, self.name, self.exchange)
+        };
+        label.chars().take(MAX_CHOICE_LEN).collect()
+    }
+}
+
+/// Split one CSV line into trimmed fields. A field in double quotes may
+/// hold commas, and `""` inside it stands for one quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Parse a listing CSV with at least `symbol` and `name` columns. Rows whose
+/// `status` column says anything but `Active` are skipped, as are symbols
+/// [`normalize_symbol`] rejects.
+pub fn parse_listing(csv: &str) -> Result<Vec<Listing>, MarketError> {
+    let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = split_csv_line(
+        lines
+            .next()
+            .ok_or_else(|| MarketError::BadPayload("empty listing".to_string()))?,
+    )
+    .iter()
+    .map(|column| column.to_lowercase())
+    .collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let (Some(symbol_col), Some(name_col)) = (column("symbol"), column("name")) else {
+        return Err(MarketError::BadPayload(
+            "listing has no symbol or name column".to_string(),
+        ));
+    };
+    let exchange_col = column("exchange");
+    let type_col = column("assettype");
+    let status_col = column("status");
+
+    let mut listings: Vec<Listing> = lines
+        .filter_map(|line| {
+            let fields = split_csv_line(line);
+            let field = |col: Option<usize>| Some(fields.get(col?)?.as_str());
+            if field(status_col).is_some_and(|status| !status.eq_ignore_ascii_case("active")) {
+                return None;
+            }
+            Some(Listing {
+                symbol: normalize_symbol(field(Some(symbol_col))?)?,
+                name: field(Some(name_col))?.to_string(),
+                exchange: field(exchange_col).unwrap_or_default().to_string(),
+                asset_type: field(type_col).unwrap_or_default().to_string(),
+            })
+        })
+        .collect();
+    if listings.is_empty() {
+        return Err(MarketError::BadPayload(
+            "listing has no symbols".to_string(),
+        ));
+    }
+    listings.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    listings.dedup_by(|a, b| a.symbol == b.symbol);
+    Ok(listings)
+}
+
+/// Where the fetched listing is saved, from `SYMBOL_LISTING_PATH`.
+pub fn listing_path() -> PathBuf {
+    std::env::var("SYMBOL_LISTING_PATH")
+        .unwrap_or_else(|_| DEFAULT_LISTING_PATH.to_string())
+        .into()
+}
+
+/// Save a fetched listing to `path` so it outlasts a restart. It is written
+/// beside `path` first, so a failed write leaves the old listing in place.
+pub fn save_listing(path: &Path, csv: &str) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, csv)?;
+    std::fs::rename(&partial, path)
+}
+
+/// Edit distance between two symbols, counting a swap of neighbouring
+/// letters as one edit.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Known symbols, sorted by symbol.
+#[derive(Debug)]
+pub struct SymbolDirectory {
+    listings: RwLock<Vec<Listing>>,
+}
+
+impl SymbolDirectory {
+    pub fn new(listings: Vec<Listing>) -> Self {
+        Self {
+            listings: RwLock::new(listings),
+        }
+    }
+
+    /// Load [`BUNDLED_LISTING`].
+    pub fn bundled() -> Self {
+        Self::new(parse_listing(BUNDLED_LISTING).expect("bundled listing is valid"))
+    }
+
+    /// Load the listing saved at `path`, or [`BUNDLED_LISTING`] if there is
+    /// none or it can't be read.
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(csv) => match parse_listing(&csv) {
+                Ok(listings) => return Self::new(listings),
+                Err(e) => warn!("Ignoring saved symbol listing {}: {}", path.display(), e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Couldn't read symbol listing {}: {}", path.display(), e),
+        }
+        Self::bundled()
+    }
+
+    /// Load the listing saved at [`listing_path`].
+    pub fn from_env() -> Self {
+        Self::load(&listing_path())
+    }
+
+    /// Swap in a freshly parsed listing.
+    pub fn replace(&self, listings: Vec<Listing>) {
+        *self.listings.write().unwrap() = listings;
+    }
+
+    pub fn len(&self) -> usize {
+        self.listings.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<Listing> {
+        let symbol = normalize_symbol(symbol)?;
+        let listings = self.listings.read().unwrap();
+        listings
+            .binary_search_by(|listing| listing.symbol.as_str().cmp(&symbol))
+            .ok()
+            .map(|index| listings[index].clone())
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.get(symbol).is_some()
+    }
+
+    /// Listings matching what has been typed so far: exact symbol first, then
+    /// symbols starting with it, then names with a word starting with it.
+    pub fn search(&self, partial: &str, limit: usize) -> Vec<Listing> {
+        let partial = partial.trim().to_uppercase();
+        let listings = self.listings.read().unwrap();
+        if partial.is_empty() {
+            return listings.iter().take(limit).cloned().collect();
+        }
+        let mut matches: Vec<(u8, &Listing)> = listings
+            .iter()
+            .filter_map(|listing| {
+                let rank = if listing.symbol == partial {
+                    0
+                } else if listing.symbol.starts_with(&partial) {
+                    1
+                } else if listing
+                    .name
+                    .to_uppercase()
+                    .split_whitespace()
+                    .any(|word| word.starts_with(&partial))
+                {
+                    2
+                } else {
+                    return None;
+                };
+                Some((rank, listing))
+            })
+            .collect();
+        matches.sort_by(|(rank_a, a), (rank_b, b)| {
+            rank_a
+                .cmp(rank_b)
+                .then(a.symbol.len().cmp(&b.symbol.len()))
+                .then(a.symbol.cmp(&b.symbol))
+        });
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, listing)| listing.clone())
+            .collect()
+    }
+
+    /// Known symbols close to a mistyped one, closest first.
+    pub fn suggest(&self, symbol: &str) -> Vec<String> {
+        let symbol = symbol.trim().to_uppercase();
+        let allowed = if symbol.len() > 4 { 2 } else { 1 };
+        let listings = self.listings.read().unwrap();
+        let mut close: Vec<(usize, &str)> = listings
+            .iter()
+            .map(|listing| (distance(&symbol, &listing.symbol), listing.symbol.as_str()))
+            .filter(|(distance, _)| *distance <= allowed)
+            .collect();
+        close.sort();
+        close
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, symbol)| symbol.to_string())
+            .collect()
+    }
+
+    /// A note on each equity symbol that isn't in the directory, if any.
+    /// Crypto and currency pairs aren't listed and are left out.
+    pub fn warning(&self, symbols: &[String]) -> Option<String> {
+        let lines: Vec<String> = symbols
+            .iter()
+            .filter(|symbol| {
+                AssetClass::detect(symbol) == AssetClass::Equity && !self.contains(symbol)
+            })
+            .map(|symbol| {
+                format!(
+                    "'{}' isn't in my symbol list.{}",
+                    symbol,
+                    self.did_you_mean(symbol)
+                )
+            })
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    /// Reply for a symbol the provider doesn't know.
+    pub fn unknown_message(&self, symbol: &str) -> String {
+        format!(
+            "I don't know the ticker '{}'.{}",
+            symbol,
+            self.did_you_mean(symbol)
+        )
+    }
+
+    fn did_you_mean(&self, symbol: &str) -> String {
+        let suggestions = self.suggest(symbol);
+        if suggestions.is_empty() {
+            String::new()
+        } else {
+            format!(" Did you mean {}?", suggestions.join(", "))
+        }
+    }
+}
+
+impl Default for SymbolDirectory {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+/// Autocomplete labels and values for the last of the space separated
+/// tickers typed so far, keeping the ones before it.
+fn ticker_choices(directory: &SymbolDirectory, partial: &str) -> Vec<(String, String)> {
+    let (typed, last) = match partial.rfind(|c: char| c.is_whitespace() || c == ',') {
+        Some(index) => partial.split_at(index + 1),
+        None => ("", partial),
+    };
+    directory
+        .search(last, MAX_CHOICES)
+        .into_iter()
+        .map(|listing| {
+            let value = format!("{}{}", typed, listing.symbol);
+            let label = if typed.is_empty() {
+                listing.label()
+            } else {
+                format!("{}{}", typed, listing.label())
+                    .chars()
+                    .take(MAX_CHOICE_LEN)
+                    .collect()
+            };
+            (label, value)
+        })
+        .collect()
+}
+
+/// Autocomplete for ticker options, counted as autocomplete interactions.
+pub async fn autocomplete_tickers(
+    ctx: poise::ApplicationContext<'_, crate::Data, crate::Error>,
+    partial: &str,
+) -> Vec<AutocompleteChoice> {
+    if let Err(e) = ctx
+        .data()
+        .interaction_tracker
+        .read()
+        .await
+        .track_autocomplete(ctx.interaction)
+        .await
+    {
+        warn!("Failed to record ticker autocomplete: {}", e);
+    }
+    ticker_choices(&ctx.data().symbols, partial)
+        .into_iter()
+        .map(|(label, value)| AutocompleteChoice::new(label, value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory() -> SymbolDirectory {
+        SymbolDirectory::new(
+            parse_listing(
+                "symbol,name,exchange,assetType,ipoDate,delistingDate,status\n\
+                 MSFT,Microsoft Corporation,NASDAQ,Stock,1986-03-13,null,Active\n\
+                 AAPL,Apple Inc,NASDAQ,Stock,1980-12-12,null,Active\n\
+                 AA,Alcoa Corp,NYSE,Stock,2016-10-18,null,Active\n\
+                 APLE,Apple Hospitality REIT Inc,NYSE,Stock,2015-05-18,null,Active\n\
+                 LEH,Lehman Brothers Holdings,NYSE,Stock,1994-05-31,2008-09-15,Delisted\n",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_parse_listing() {
+        let directory = directory();
+        assert_eq!(directory.len(), 4);
+        assert!(directory.contains("aapl"));
+        assert!(!directory.contains("LEH"));
+        assert_eq!(directory.get("MSFT").unwrap().exchange, "NASDAQ");
+
+        let minimal = parse_listing("Symbol,Name\nbrk.b,Berkshire Hathaway\n").unwrap();
+        assert_eq!(minimal[0].symbol, "BRK.B");
+        assert!(parse_listing("").is_err());
+        assert!(parse_listing("ticker,name\nAAPL,Apple\n").is_err());
+        assert!(parse_listing("{\"Note\": \"Thank you for using Alpha Vantage!\"}").is_err());
+
+        let quoted = parse_listing(
+            "symbol,name,exchange,assetType,ipoDate,delistingDate,status\n\
+             AMC,\"AMC Entertainment Holdings, Inc. - Class A\",NYSE,Stock,2013-12-18,null,Active\n\
+             \"BRK.A\",\"Berkshire \"\"A\"\" shares\",NYSE,Stock,1980-03-17,null,Active\n",
+        )
+        .unwrap();
+        assert_eq!(quoted[0].symbol, "AMC");
+        assert_eq!(quoted[0].name, "AMC Entertainment Holdings, Inc. - Class A");
+        assert_eq!(quoted[0].exchange, "NYSE");
+        assert_eq!(quoted[1].symbol, "BRK.A");
+        assert_eq!(quoted[1].name, "Berkshire \"A\" shares");
+    }
+
+    #[test]
+    fn test_split_csv_line() {
+        assert_eq!(split_csv_line("a, b ,c"), vec!["a", "b", "c"]);
+        assert_eq!(split_csv_line("\"a, b\",c"), vec!["a, b", "c"]);
+        assert_eq!(
+            split_csv_line("\"say \"\"hi\"\"\",\"\""),
+            vec!["say \"hi\"", ""]
+        );
+        assert_eq!(split_csv_line(""), vec![""]);
+    }
+
+    #[test]
+    fn test_bundled_listing() {
+        let directory = SymbolDirectory::bundled();
+        assert!(directory.len() > 50);
+        for symbol in ["SPY", "AAPL", "MSFT"] {
+            assert!(directory.contains(symbol), "{} is missing", symbol);
+        }
+    }
+
+    #[test]
+    fn test_saved_listing() {
+        let path = std::env::temp_dir().join(format!("symbol_listing_{}.csv", std::process::id()));
+        assert!(SymbolDirectory::load(&path).contains("SPY"));
+        save_listing(&path, "symbol,name\nAMC,AMC Entertainment\n").unwrap();
+        let saved = SymbolDirectory::load(&path);
+        assert_eq!(saved.len(), 1);
+        assert!(saved.contains("AMC"));
+
+        std::fs::write(&path, "not a listing").unwrap();
+        assert!(SymbolDirectory::load(&path).contains("SPY"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_search() {
+        let directory = directory();
+        let symbols = |partial: &str| -> Vec<String> {
+            directory
+                .search(partial, 10)
+                .into_iter()
+                .map(|listing| listing.symbol)
+                .collect()
+        };
+        assert_eq!(symbols("aa"), vec!["AA", "AAPL"]);
+        assert_eq!(symbols("apple"), vec!["AAPL", "APLE"]);
+        assert_eq!(symbols("micro"), vec!["MSFT"]);
+        assert!(symbols("zzz").is_empty());
+        assert_eq!(directory.search("", 2).len(), 2);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let directory = directory();
+        assert_eq!(directory.suggest("APPL"), vec!["AAPL"]);
+        assert_eq!(directory.suggest("MSTF"), vec!["MSFT"]);
+        assert!(directory.suggest("GOOG").is_empty());
+        assert_eq!(
+            directory.unknown_message("MSTF"),
+            "I don't know the ticker 'MSTF'. Did you mean MSFT?"
+        );
+        assert_eq!(
+            directory.unknown_message("GOOG"),
+            "I don't know the ticker 'GOOG'."
+        );
+    }
+
+    #[test]
+    fn test_warning() {
+        let directory = directory();
+        let symbols = |symbols: &[&str]| -> Vec<String> {
+            symbols.iter().map(|symbol| symbol.to_string()).collect()
+        };
+        assert_eq!(
+            directory.warning(&symbols(&["AAPL", "MSTF", "AMC"])),
+            Some(
+                "'MSTF' isn't in my symbol list. Did you mean MSFT?\n\
+                 'AMC' isn't in my symbol list."
+                    .to_string()
+            )
+        );
+        assert_eq!(directory.warning(&symbols(&["AAPL", "BTC-USD"])), None);
+    }
+
+    #[test]
+    fn test_ticker_choices() {
+        let directory = directory();
+        let choices = ticker_choices(&directory, "AAPL ms");
+        assert_eq!(
+            choices,
+            vec![(
+                "AAPL MSFT · Microsoft Corporation (NASDAQ)".to_string(),
+                "AAPL MSFT".to_string()
+            )]
+        );
+        assert_eq!(ticker_choices(&directory, "aapl").len(), 1);
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("AAPL", "AAPL"), 0);
+        assert_eq!(distance("APPL", "AAPL"), 1);
+        assert_eq!(distance("MSTF", "MSFT"), 1);
+        assert_eq!(distance("GOOG", "AAPL"), 4);
+    }
+}
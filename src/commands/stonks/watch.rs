@@ -0,0 +1,602 @@
+//! Per-user watchlists and price alerts.
+//!
+//! `/watch` keeps a list of symbols for each user and `/alert` stores a price
+//! threshold. [`poll_alerts`] runs in the background, checking pending
+//! alerts against the latest quotes and notifying their owners by DM or in
+//! the channel the alert was set in. Stock alerts are only checked while the
+//! market is open; crypto and currency pairs trade around the clock.
+
+use super::assets::AssetClass;
+use super::calendar::is_market_open;
+use super::market::{normalize_symbol, MarketDataProvider, MarketError, Quote};
+use crate::models::{NewPriceAlert, PriceAlert};
+use crate::schema::{price_alerts, watchlists};
+use chrono::Utc;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use poise::serenity_prelude::{ChannelId, CreateAllowedMentions, CreateMessage, Http, UserId};
+use poise::CreateReply;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Most symbols on one user's watchlist.
+pub(crate) const MAX_WATCHED: i64 = 25;
+/// Most untriggered alerts one user may have.
+pub(crate) const MAX_PENDING_ALERTS: i64 = 10;
+/// Used when `ALERT_POLL_SECS` is not set. Every check costs one request
+/// per symbol, and Alpha Vantage's free tier allows 25 a day, so this checks
+/// each stock 13 times in a trading day. Crypto and currency pairs are
+/// checked all day.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Which side of the threshold fires an alert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Direction {
+    #[name = "above"]
+    Above,
+    #[name = "below"]
+    Below,
+}
+
+impl Direction {
+    pub fn key(self) -> &'static str {
+        match self {
+            Direction::Above => "above",
+            Direction::Below => "below",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "above" => Some(Direction::Above),
+            "below" => Some(Direction::Below),
+            _ => None,
+        }
+    }
+
+    /// Whether `price` has reached `threshold` from this side.
+    pub fn crossed(self, threshold: f64, price: f64) -> bool {
+        match self {
+            Direction::Above => price >= threshold,
+            Direction::Below => price <= threshold,
+        }
+    }
+}
+
+/// Where an alert is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, poise::ChoiceParameter)]
+pub enum AlertTarget {
+    #[default]
+    #[name = "dm"]
+    Dm,
+    #[name = "channel"]
+    Channel,
+}
+
+/// The alerts in `alerts` that `quote` sets off.
+pub(crate) fn triggered<'a>(alerts: &'a [PriceAlert], quote: &Quote) -> Vec<&'a PriceAlert> {
+    alerts
+        .iter()
+        .filter(|alert| alert.symbol == quote.symbol)
+        .filter(|alert| {
+            Direction::from_key(&alert.direction)
+                .is_some_and(|direction| direction.crossed(alert.threshold, quote.price))
+        })
+        .collect()
+}
+
+/// The notification sent when `alert` fires at `price`.
+pub(crate) fn alert_message(alert: &PriceAlert, price: f64) -> String {
+    format!(
+        "🔔 {} is {} {:.2} (now {:.2})",
+        alert.symbol, alert.direction, alert.threshold, price
+    )
+}
+
+fn describe_alert(alert: &PriceAlert) -> String {
+    let target = if alert.channel_id.is_some() {
+        "channel"
+    } else {
+        "DM"
+    };
+    format!(
+        "#{} {} {} {:.2} ({})",
+        alert.id, alert.symbol, alert.direction, alert.threshold, target
+    )
+}
+
+async fn reply(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    content: String,
+) -> Result<(), crate::Error> {
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Manage your stock watchlist.
+/// Usage: /watch add AAPL
+#[poise::command(slash_command, prefix_command, subcommands("add", "remove", "list"))]
+pub async fn watch(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    list_watchlist(ctx).await
+}
+
+/// Add a symbol to your watchlist.
+/// Usage: /watch add AAPL
+#[poise::command(slash_command, prefix_command)]
+pub async fn add(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Ticker symbol"] symbol: String,
+) -> Result<(), crate::Error> {
+    let Some(symbol) = normalize_symbol(&symbol) else {
+        return reply(ctx, format!("'{}' isn't a ticker symbol.", symbol)).await;
+    };
+    let user = ctx.author().id.get() as i64;
+    let mut conn = ctx.data().db_pool.get()?;
+    let watched: i64 = watchlists::table
+        .filter(watchlists::user_id.eq(user))
+        .count()
+        .get_result(&mut conn)?;
+    if watched >= MAX_WATCHED {
+        return reply(
+            ctx,
+            format!("Your watchlist is full ({} symbols).", MAX_WATCHED),
+        )
+        .await;
+    }
+    let added = diesel::insert_into(watchlists::table)
+        .values((
+            watchlists::user_id.eq(user),
+            watchlists::symbol.eq(&symbol),
+            watchlists::added_at.eq(Utc::now().naive_utc()),
+        ))
+        .on_conflict_do_nothing()
+        .execute(&mut conn)?;
+    let msg = if added == 0 {
+        format!("{} is already on your watchlist.", symbol)
+    } else {
+        format!("Added {} to your watchlist.", symbol)
+    };
+    reply(ctx, msg).await
+}
+
+/// Remove a symbol from your watchlist.
+/// Usage: /watch remove AAPL
+#[poise::command(slash_command, prefix_command)]
+pub async fn remove(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Ticker symbol"] symbol: String,
+) -> Result<(), crate::Error> {
+    let symbol = normalize_symbol(&symbol).unwrap_or(symbol);
+    let user = ctx.author().id.get() as i64;
+    let mut conn = ctx.data().db_pool.get()?;
+    let removed = diesel::delete(
+        watchlists::table
+            .filter(watchlists::user_id.eq(user))
+            .filter(watchlists::symbol.eq(&symbol)),
+    )
+    .execute(&mut conn)?;
+    let msg = if removed == 0 {
+        format!("{} isn't on your watchlist.", symbol)
+    } else {
+        format!("Removed {} from your watchlist.", symbol)
+    };
+    reply(ctx, msg).await
+}
+
+/// Show your watchlist and pending price alerts.
+/// Usage: /watch list
+#[poise::command(slash_command, prefix_command)]
+pub async fn list(ctx: poise::Context<'_, crate::Data, crate::Error>) -> Result<(), crate::Error> {
+    list_watchlist(ctx).await
+}
+
+async fn list_watchlist(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+) -> Result<(), crate::Error> {
+    let user = ctx.author().id.get() as i64;
+    let mut conn = ctx.data().db_pool.get()?;
+    let symbols: Vec<String> = watchlists::table
+        .filter(watchlists::user_id.eq(user))
+        .order(watchlists::symbol.asc())
+        .select(watchlists::symbol)
+        .load(&mut conn)?;
+    let alerts: Vec<PriceAlert> = price_alerts::table
+        .filter(price_alerts::user_id.eq(user))
+        .filter(price_alerts::triggered_at.is_null())
+        .order(price_alerts::id.asc())
+        .select(PriceAlert::as_select())
+        .load(&mut conn)?;
+
+    let mut lines = Vec::new();
+    if symbols.is_empty() {
+        lines.push("Your watchlist is empty. Add symbols with /watch add.".to_string());
+    } else {
+        lines.push(format!("Watching: {}", symbols.join(", ")));
+    }
+    if !alerts.is_empty() {
+        lines.push("Pending alerts:".to_string());
+        lines.extend(alerts.iter().map(describe_alert));
+    }
+    reply(ctx, lines.join("\n")).await
+}
+
+/// Get notified when a stock crosses a price, or cancel an alert.
+/// Usage: /alert set AAPL above 200
+///
+/// As a prefix command the alert can follow `alert` directly, e.g.
+/// `@bot alert AAPL above 200`. Discord doesn't let a slash command with
+/// subcommands take options, so there it's always `/alert set`.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    subcommands("alert_set", "alert_remove")
+)]
+pub async fn alert(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    // Only reachable as a prefix command, see above.
+    symbol: Option<String>,
+    direction: Option<Direction>,
+    price: Option<f64>,
+    notify: Option<AlertTarget>,
+) -> Result<(), crate::Error> {
+    match (symbol, direction, price) {
+        (Some(symbol), Some(direction), Some(price)) => {
+            set_alert(ctx, symbol, direction, price, notify).await
+        }
+        _ => list_watchlist(ctx).await,
+    }
+}
+
+/// Get notified when a stock crosses a price.
+/// Usage: /alert set AAPL above 200
+#[poise::command(slash_command, prefix_command, rename = "set")]
+pub async fn alert_set(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Ticker symbol"] symbol: String,
+    #[description = "Alert when the price goes above or below the threshold"] direction: Direction,
+    #[description = "Price threshold"] price: f64,
+    #[description = "DM (default) or this channel"] notify: Option<AlertTarget>,
+) -> Result<(), crate::Error> {
+    set_alert(ctx, symbol, direction, price, notify).await
+}
+
+/// Cancel one of your pending price alerts.
+/// Usage: /alert remove 3
+#[poise::command(slash_command, prefix_command, rename = "remove")]
+pub async fn alert_remove(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    #[description = "Alert number, as shown by /watch list"] id: i64,
+) -> Result<(), crate::Error> {
+    let user = ctx.author().id.get() as i64;
+    let mut conn = ctx.data().db_pool.get()?;
+    let removed = diesel::delete(
+        price_alerts::table
+            .filter(price_alerts::id.eq(id))
+            .filter(price_alerts::user_id.eq(user))
+            .filter(price_alerts::triggered_at.is_null()),
+    )
+    .execute(&mut conn)?;
+    let msg = if removed == 0 {
+        format!("You have no pending alert #{}.", id)
+    } else {
+        format!("Cancelled alert #{}.", id)
+    };
+    reply(ctx, msg).await
+}
+
+/// Why an alert can't be set with the price at `current`, if it can't.
+pub(crate) fn already_crossed(
+    symbol: &str,
+    direction: Direction,
+    threshold: f64,
+    current: f64,
+) -> Option<String> {
+    direction.crossed(threshold, current).then(|| {
+        format!(
+            "{} is already {} {:.2} (now {:.2}).",
+            symbol,
+            direction.key(),
+            threshold,
+            current
+        )
+    })
+}
+
+async fn set_alert(
+    ctx: poise::Context<'_, crate::Data, crate::Error>,
+    symbol: String,
+    direction: Direction,
+    price: f64,
+    notify: Option<AlertTarget>,
+) -> Result<(), crate::Error> {
+    let Some(symbol) = normalize_symbol(&symbol) else {
+        return reply(ctx, format!("'{}' isn't a ticker symbol.", symbol)).await;
+    };
+    if !price.is_finite() || price <= 0.0 {
+        return reply(ctx, "The price must be a positive number.".to_string()).await;
+    }
+    let target = notify.unwrap_or_default();
+    let channel_id = match target {
+        AlertTarget::Channel if ctx.guild_id().is_none() => {
+            return reply(
+                ctx,
+                "Channel alerts can only be set in a server.".to_string(),
+            )
+            .await;
+        }
+        AlertTarget::Channel => Some(ctx.channel_id().get() as i64),
+        AlertTarget::Dm => None,
+    };
+    let user = ctx.author().id.get() as i64;
+    let mut conn = ctx.data().db_pool.get()?;
+    let pending: i64 = price_alerts::table
+        .filter(price_alerts::user_id.eq(user))
+        .filter(price_alerts::triggered_at.is_null())
+        .count()
+        .get_result(&mut conn)?;
+    if pending >= MAX_PENDING_ALERTS {
+        return reply(
+            ctx,
+            format!(
+                "You already have {} pending alerts. Cancel one with /alert remove.",
+                MAX_PENDING_ALERTS
+            ),
+        )
+        .await;
+    }
+    ctx.defer().await?;
+    let quote = match ctx.data().market_data.quote(&symbol).await {
+        Ok(quote) => quote,
+        Err(MarketError::UnknownSymbol(_)) => {
+            return reply(ctx, ctx.data().symbols.unknown_message(&symbol)).await;
+        }
+        Err(e) => return reply(ctx, format!("Couldn't check {}: {}", symbol, e)).await,
+    };
+    if let Some(message) = already_crossed(&symbol, direction, price, quote.price) {
+        return reply(ctx, message).await;
+    }
+    let id: i64 = diesel::insert_into(price_alerts::table)
+        .values(NewPriceAlert {
+            user_id: user,
+            channel_id,
+            symbol: &symbol,
+            direction: direction.key(),
+            threshold: price,
+            created_at: Utc::now().naive_utc(),
+        })
+        .returning(price_alerts::id)
+        .get_result(&mut conn)?;
+    reply(
+        ctx,
+        format!(
+            "Alert #{} set: I'll tell you when {} goes {} {:.2}.",
+            id,
+            symbol,
+            direction.key(),
+            price
+        ),
+    )
+    .await
+}
+
+/// How often [`poll_alerts`] checks prices, from `ALERT_POLL_SECS`.
+pub fn poll_interval_from_env() -> Duration {
+    std::env::var("ALERT_POLL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_POLL_INTERVAL)
+}
+
+/// Check pending alerts every `every`, forever.
+pub async fn poll_alerts(
+    http: impl AsRef<Http> + Send + Sync,
+    db_pool: Pool<ConnectionManager<PgConnection>>,
+    market: impl MarketDataProvider + Send + Sync,
+    every: Duration,
+) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        match check_alerts(http.as_ref(), &db_pool, &market).await {
+            Ok(0) => {}
+            Ok(fired) => info!("Sent {} price alert(s)", fired),
+            Err(e) => warn!("Price alert check failed: {}", e),
+        }
+    }
+}
+
+/// Whether alerts on `symbol` are checked now. Stock prices only move while
+/// the market is open.
+pub(crate) fn tradable_now(symbol: &str, market_open: bool) -> bool {
+    market_open || AssetClass::detect(symbol) != AssetClass::Equity
+}
+
+/// Fire every pending alert whose threshold the latest quote has crossed,
+/// returning how many were sent. Each alert is marked triggered before it
+/// is sent so it can never fire twice. Stock alerts wait for market hours.
+pub async fn check_alerts(
+    http: &Http,
+    db_pool: &Pool<ConnectionManager<PgConnection>>,
+    market: &(impl MarketDataProvider + Sync),
+) -> Result<usize, crate::Error> {
+    let pending: Vec<PriceAlert> = {
+        let mut conn = db_pool.get()?;
+        price_alerts::table
+            .filter(price_alerts::triggered_at.is_null())
+            .select(PriceAlert::as_select())
+            .load(&mut conn)?
+    };
+    let mut by_symbol: BTreeMap<&str, Vec<PriceAlert>> = BTreeMap::new();
+    for alert in &pending {
+        by_symbol
+            .entry(alert.symbol.as_str())
+            .or_default()
+            .push(alert.clone());
+    }
+
+    let market_open = is_market_open(Utc::now());
+    let mut fired = 0;
+    for (symbol, alerts) in &by_symbol {
+        if !tradable_now(symbol, market_open) {
+            continue;
+        }
+        let quote = match market.quote(symbol).await {
+            Ok(quote) => quote,
+            Err(e) => {
+                warn!("Couldn't check alerts for {}: {}", symbol, e);
+                continue;
+            }
+        };
+        for alert in triggered(alerts, &quote) {
+            let claimed = {
+                let mut conn = db_pool.get()?;
+                diesel::update(
+                    price_alerts::table
+                        .filter(price_alerts::id.eq(alert.id))
+                        .filter(price_alerts::triggered_at.is_null()),
+                )
+                .set((
+                    price_alerts::triggered_at.eq(Utc::now().naive_utc()),
+                    price_alerts::triggered_price.eq(quote.price),
+                ))
+                .execute(&mut conn)?
+            };
+            if claimed == 0 {
+                continue;
+            }
+            if let Err(e) = notify(http, alert, quote.price).await {
+                warn!("Couldn't deliver price alert #{}: {}", alert.id, e);
+            }
+            fired += 1;
+        }
+    }
+    Ok(fired)
+}
+
+async fn notify(http: &Http, alert: &PriceAlert, price: f64) -> Result<(), crate::Error> {
+    let user = UserId::new(alert.user_id as u64);
+    let text = alert_message(alert, price);
+    match alert.channel_id {
+        Some(channel) => {
+            ChannelId::new(channel as u64)
+                .send_message(
+                    http,
+                    CreateMessage::new()
+                        .content(format!("<@{}> {}", user, text))
+                        .allowed_mentions(CreateAllowedMentions::new().users([user])),
+                )
+                .await?;
+        }
+        None => {
+            user.direct_message(http, CreateMessage::new().content(text))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn alert(id: i64, symbol: &str, direction: &str, threshold: f64) -> PriceAlert {
+        PriceAlert {
+            id,
+            user_id: 1,
+            channel_id: None,
+            symbol: symbol.to_string(),
+            direction: direction.to_string(),
+            threshold,
+            created_at: NaiveDate::from_ymd_opt(2023, 1, 2)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            triggered_at: None,
+            triggered_price: None,
+        }
+    }
+
+    fn quote(symbol: &str, price: f64) -> Quote {
+        Quote {
+            symbol: symbol.to_string(),
+            day: NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(),
+            price,
+            open: price,
+            high: price,
+            low: price,
+            previous_close: price,
+            volume: 0,
+        }
+    }
+
+    #[test]
+    fn test_direction_crossed() {
+        assert!(Direction::Above.crossed(100.0, 100.0));
+        assert!(Direction::Above.crossed(100.0, 101.0));
+        assert!(!Direction::Above.crossed(100.0, 99.0));
+        assert!(Direction::Below.crossed(100.0, 99.0));
+        assert!(!Direction::Below.crossed(100.0, 101.0));
+        assert_eq!(Direction::from_key("below"), Some(Direction::Below));
+        assert_eq!(Direction::from_key("sideways"), None);
+    }
+
+    #[test]
+    fn test_triggered() {
+        let alerts = vec![
+            alert(1, "AAPL", "above", 150.0),
+            alert(2, "AAPL", "below", 140.0),
+            alert(3, "MSFT", "above", 100.0),
+            alert(4, "AAPL", "sideways", 0.0),
+        ];
+        let ids = |price| {
+            triggered(&alerts, &quote("AAPL", price))
+                .iter()
+                .map(|alert| alert.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(155.0), vec![1]);
+        assert_eq!(ids(139.0), vec![2]);
+        assert!(ids(145.0).is_empty());
+    }
+
+    #[test]
+    fn test_tradable_now() {
+        assert!(tradable_now("AAPL", true));
+        assert!(!tradable_now("AAPL", false));
+        assert!(tradable_now("BTC-USD", false));
+        assert!(tradable_now("EUR-USD", false));
+    }
+
+    #[test]
+    fn test_alert_message() {
+        assert_eq!(
+            alert_message(&alert(1, "AAPL", "above", 150.0), 151.234),
+            "🔔 AAPL is above 150.00 (now 151.23)"
+        );
+        let mut in_channel = alert(7, "MSFT", "below", 99.5);
+        assert_eq!(describe_alert(&in_channel), "#7 MSFT below 99.50 (DM)");
+        in_channel.channel_id = Some(5);
+        assert_eq!(describe_alert(&in_channel), "#7 MSFT below 99.50 (channel)");
+    }
+
+    #[test]
+    fn test_already_crossed() {
+        assert_eq!(
+            already_crossed("AAPL", Direction::Above, 150.0, 151.0),
+            Some("AAPL is already above 150.00 (now 151.00).".to_string())
+        );
+        assert!(already_crossed("AAPL", Direction::Above, 150.0, 149.0).is_none());
+        assert!(already_crossed("AAPL", Direction::Below, 150.0, 151.0).is_none());
+        assert!(already_crossed("AAPL", Direction::Below, 150.0, 150.0).is_some());
+    }
+}
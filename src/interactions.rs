@@ -14,9 +14,9 @@ use diesel::r2d2::{ConnectionManager, Pool};
 use poise::serenity_prelude::{
     Context, GuildId, User, UserId,
     model::application::interaction::{
-        ApplicationCommandInteraction, MessageComponentInteraction, ModalSubmitInteraction,
-        AutocompleteInteraction,
+        ApplicationCommandInteraction, ModalSubmitInteraction,
     },
+    CommandInteraction, ComponentInteraction,
 };
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -25,6 +25,25 @@ use std::time::Instant;
 use std::error::Error;
 use diesel::PgConnection;
 
+/// What gets recorded for a button press. Features built on buttons can also
+/// record clicks of their own, such as a poll's result, under a custom id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonClick<'a> {
+    pub custom_id: &'a str,
+    pub user_id: i64,
+    pub guild_id: i64,
+}
+
+impl<'a> From<&'a ComponentInteraction> for ButtonClick<'a> {
+    fn from(interaction: &'a ComponentInteraction) -> Self {
+        Self {
+            custom_id: &interaction.data.custom_id,
+            user_id: interaction.user.id.get() as i64,
+            guild_id: interaction.guild_id.map(|id| id.get() as i64).unwrap_or(0),
+        }
+    }
+}
+
 pub struct InteractionTracker {
     pool: Arc<Pool<ConnectionManager<PgConnection>>>,
     rate_limits: RwLock<HashMap<(i64, String), RateLimit>>,
@@ -146,14 +165,16 @@ impl InteractionTracker {
         Ok(())
     }
 
-    pub async fn track_button_click(
+    pub async fn track_button_click<'a>(
         &self,
-        interaction: &MessageComponentInteraction,
+        click: impl Into<ButtonClick<'a>>,
     ) -> Result<(), diesel::result::Error> {
         let start_time = Instant::now();
-        let guild_id = interaction.guild_id.map(|id| id.0 as i64).unwrap_or(0);
-        let user_id = interaction.user.id.0 as i64;
-        let interaction_id = interaction.data.custom_id.clone();
+        let ButtonClick {
+            custom_id: interaction_id,
+            user_id,
+            guild_id,
+        } = click.into();
 
         // Record metrics
         INTERACTION_REQUESTS.with_label_values(&["button"]).inc();
@@ -165,10 +186,11 @@ impl InteractionTracker {
         }
 
         // Record interaction
-        self.track_interaction("button", &interaction_id, user_id, guild_id)?;
+        self.track_interaction("button", interaction_id, user_id, guild_id)
+            .await?;
 
         // Update stats
-        self.update_interaction_stats("button", &interaction_id, guild_id, 0.0, true)
+        self.update_interaction_stats("button", interaction_id, guild_id, 0.0, true)
             .await?;
 
         // Record duration
@@ -210,13 +232,14 @@ impl InteractionTracker {
         Ok(())
     }
 
+    /// Serenity delivers autocomplete requests as command interactions.
     pub async fn track_autocomplete(
         &self,
-        interaction: &AutocompleteInteraction,
+        interaction: &CommandInteraction,
     ) -> Result<(), diesel::result::Error> {
         let start_time = Instant::now();
-        let guild_id = interaction.guild_id.map(|id| id.0 as i64).unwrap_or(0);
-        let user_id = interaction.user.id.0 as i64;
+        let guild_id = interaction.guild_id.map(|id| id.get() as i64).unwrap_or(0);
+        let user_id = interaction.user.id.get() as i64;
         let interaction_id = interaction.data.name.clone();
 
         // Record metrics
@@ -229,7 +252,8 @@ impl InteractionTracker {
         }
 
         // Record interaction
-        self.track_interaction("autocomplete", &interaction_id, user_id, guild_id)?;
+        self.track_interaction("autocomplete", &interaction_id, user_id, guild_id)
+            .await?;
 
         // Update stats
         self.update_interaction_stats("autocomplete", &interaction_id, guild_id, 0.0, true)
@@ -1,9 +1,11 @@
 pub mod commands;
 pub mod db;
 pub mod interactions;
+pub mod logging;
 pub mod metrics;
 pub mod models;
 pub mod schema;
+pub mod telemetry;
 pub mod utils;
 
 pub use db::Pool;
@@ -23,16 +25,19 @@ pub use crate::metrics::{
     USER_COUNT,
     CHANNEL_COUNT,
     MEMBER_COUNT,
-    TEXT_CHANNEL_COUNT,
-    VOICE_CHANNEL_COUNT,
-    CATEGORY_COUNT,
-    ROLE_COUNT,
-    EMOJI_COUNT,
-    BOOST_COUNT,
-    BOOST_LEVEL,
+    GUILD_MEMBER_COUNT,
+    GUILD_CHANNEL_COUNT,
+    GUILD_TEXT_CHANNEL_COUNT,
+    GUILD_VOICE_CHANNEL_COUNT,
+    GUILD_CATEGORY_CHANNEL_COUNT,
+    GUILD_ROLE_COUNT,
+    GUILD_EMOJI_COUNT,
+    GUILD_BOOST_COUNT,
+    GUILD_PREMIUM_TIER,
     INTERACTION_REQUESTS,
     INTERACTION_ERRORS,
     INTERACTION_DURATION,
+    REGISTRY,
 };
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -44,7 +49,6 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
-use std::time::Instant;
 use poise::serenity_prelude::{
     Channel, Guild, User,
     model::channel::Channel as SerenityChannel,
@@ -52,26 +56,48 @@ use poise::serenity_prelude::{
     model::user::User as SerenityUser,
 };
 
+use commands::advice::client::{AdviceCache, AdviceConfig, HttpAdviceClient};
+use commands::stonks::market::MarketData;
+use commands::stonks::quote::QuoteCache;
+use commands::stonks::symbols::SymbolDirectory;
+use logging::{LogLevel, DEFAULT_FILTER};
+use metrics::CommandTimers;
+
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 
 pub struct Data {
     pub db_pool: Arc<DbPool>,
-    pub command_timers: HashMap<String, Instant>,
+    pub command_timers: CommandTimers,
+    pub log_level: LogLevel,
     pub guilds: Arc<HashMap<GuildId, SerenityGuild>>,
     pub users: Arc<HashMap<UserId, SerenityUser>>,
     pub channels: Arc<HashMap<GuildId, Vec<SerenityChannel>>>,
     pub interaction_tracker: RwLock<InteractionTracker>,
+    pub advice_client: HttpAdviceClient,
+    pub advice_cache: AdviceCache,
+    pub market_data: MarketData,
+    pub quote_cache: QuoteCache,
+    pub symbols: SymbolDirectory,
 }
 
 impl Data {
     pub fn new(db_pool: DbPool) -> Self {
         Self {
             db_pool: Arc::new(db_pool),
-            command_timers: HashMap::new(),
+            command_timers: CommandTimers::new(),
+            log_level: LogLevel::new(DEFAULT_FILTER)
+                .expect("Default log filter is valid")
+                .1,
             guilds: Arc::new(HashMap::new()),
             users: Arc::new(HashMap::new()),
             channels: Arc::new(HashMap::new()),
             interaction_tracker: RwLock::new(InteractionTracker::new()),
+            advice_client: HttpAdviceClient::new(AdviceConfig::default())
+                .expect("Failed to build advice client"),
+            advice_cache: AdviceCache::new(),
+            market_data: MarketData::from_env().expect("Failed to configure market data"),
+            quote_cache: QuoteCache::from_env(),
+            symbols: SymbolDirectory::bundled(),
         }
     }
 }
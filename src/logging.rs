@@ -0,0 +1,352 @@
+//! Log output, as human-readable text or one JSON object per line
+//! (`LOG_FORMAT`), filtered with `RUST_LOG`-style directives.
+//!
+//! The filter can be replaced while the bot runs, with the owner-only
+//! `/loglevel` command or `PUT /admin/loglevel`. It only applies to log
+//! output: spans are exported to a collector regardless.
+//!
+//! Log lines written while a command runs carry its `correlation_id` (the
+//! Discord interaction or message id), and those written while the web
+//! server handles a request carry the request's.
+
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::{fmt as subscriber_fmt, reload, EnvFilter, Layer, Registry};
+
+/// Filter used unless `RUST_LOG` is set.
+pub const DEFAULT_FILTER: &str = "warn,testbot=info,tower_http=info";
+
+/// Header a caller can send its own correlation id in.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied correlation id that's used as is.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" | "" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format '{}', expected 'text' or 'json'",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    pub filter: String,
+}
+
+impl LogConfig {
+    /// Read `LOG_FORMAT` (default `text`) and `RUST_LOG` (default
+    /// [`DEFAULT_FILTER`]).
+    pub fn from_env() -> Result<Self, crate::Error> {
+        let format = match std::env::var("LOG_FORMAT") {
+            Ok(format) => format.parse()?,
+            Err(_) => LogFormat::default(),
+        };
+        let filter = std::env::var("RUST_LOG")
+            .ok()
+            .filter(|filter| !filter.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_FILTER.to_string());
+        Ok(Self { format, filter })
+    }
+}
+
+/// The log output layer for `format`.
+pub fn fmt_layer(format: LogFormat) -> Box<dyn Layer<Registry> + Send + Sync> {
+    match format {
+        LogFormat::Text => subscriber_fmt::layer().boxed(),
+        LogFormat::Json => subscriber_fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+/// The filter in front of the log output layer, swapped out by [`LogLevel`].
+pub type FilterLayer = reload::Layer<EnvFilter, Registry>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogFilterError {
+    Invalid(String),
+    Reload(String),
+}
+
+impl fmt::Display for LogFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFilterError::Invalid(e) => write!(f, "Invalid log filter: {}", e),
+            LogFilterError::Reload(e) => write!(f, "Couldn't change the log filter: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LogFilterError {}
+
+fn parse_filter(filter: &str) -> Result<EnvFilter, LogFilterError> {
+    if filter.is_empty() {
+        return Err(LogFilterError::Invalid("the filter is empty".to_string()));
+    }
+    EnvFilter::try_new(filter).map_err(|e| LogFilterError::Invalid(e.to_string()))
+}
+
+/// Reads and replaces the log filter of a running subscriber.
+#[derive(Clone)]
+pub struct LogLevel {
+    handle: reload::Handle<EnvFilter, Registry>,
+    current: Arc<Mutex<String>>,
+}
+
+impl LogLevel {
+    /// A filter layer starting out as `filter`, and the handle to change it.
+    /// The handle stops working once the layer is dropped.
+    pub fn new(filter: &str) -> Result<(FilterLayer, Self), LogFilterError> {
+        let filter = filter.trim();
+        let (layer, handle) = reload::Layer::new(parse_filter(filter)?);
+        let level = Self {
+            handle,
+            current: Arc::new(Mutex::new(filter.to_string())),
+        };
+        Ok((layer, level))
+    }
+
+    /// The filter in effect, as it was given.
+    pub fn current(&self) -> String {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Replace the filter. An invalid filter leaves the current one in place.
+    pub fn set(&self, filter: &str) -> Result<(), LogFilterError> {
+        let filter = filter.trim();
+        let env_filter = parse_filter(filter)?;
+        let mut current = self.current.lock().unwrap();
+        self.handle
+            .reload(env_filter)
+            .map_err(|e| LogFilterError::Reload(e.to_string()))?;
+        tracing::info!(from = %current, to = %filter, "Log filter changed");
+        *current = filter.to_string();
+        Ok(())
+    }
+}
+
+/// A fresh id to tie together the log lines of one request.
+pub fn correlation_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// The correlation id to log an HTTP request under: the caller's
+/// `x-request-id` if it sent a reasonable one, otherwise a fresh one.
+pub fn request_correlation_id(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map(str::to_string)
+        .unwrap_or_else(correlation_id)
+}
+
+#[derive(Clone)]
+struct AdminState {
+    log_level: LogLevel,
+    token: Arc<str>,
+}
+
+/// The admin token, from `ADMIN_TOKEN`. The admin endpoints are only served
+/// when one is set.
+pub fn admin_token_from_env() -> Option<String> {
+    std::env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+}
+
+/// `GET /admin/loglevel` shows the log filter and `PUT /admin/loglevel`
+/// replaces it with the request body. Both need an
+/// `Authorization: Bearer <token>` header.
+pub fn admin_routes(log_level: LogLevel, token: &str) -> Router {
+    Router::new()
+        .route("/admin/loglevel", get(get_log_level).put(put_log_level))
+        .with_state(AdminState {
+            log_level,
+            token: Arc::from(token),
+        })
+}
+
+/// Whether `headers` carry the bearer `token`, compared in constant time.
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(given) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn get_log_level(State(state): State<AdminState>, headers: HeaderMap) -> Response {
+    if !authorized(&headers, &state.token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    state.log_level.current().into_response()
+}
+
+async fn put_log_level(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    if !authorized(&headers, &state.token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    match state.log_level.set(&body) {
+        Ok(()) => state.log_level.current().into_response(),
+        Err(e @ LogFilterError::Invalid(_)) => {
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{HeaderValue, Request};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!(" Text ".parse(), Ok(LogFormat::Text));
+        assert_eq!("".parse(), Ok(LogFormat::Text));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_log_level_set() {
+        let (_layer, level) = LogLevel::new(DEFAULT_FILTER).unwrap();
+        assert_eq!(level.current(), DEFAULT_FILTER);
+        level.set(" debug,hyper=warn ").unwrap();
+        assert_eq!(level.current(), "debug,hyper=warn");
+        assert!(matches!(
+            level.set("testbot=loudest"),
+            Err(LogFilterError::Invalid(_))
+        ));
+        assert!(matches!(level.set(""), Err(LogFilterError::Invalid(_))));
+        assert_eq!(level.current(), "debug,hyper=warn");
+    }
+
+    #[test]
+    fn test_log_level_without_layer() {
+        let (layer, level) = LogLevel::new("info").unwrap();
+        drop(layer);
+        assert!(matches!(level.set("debug"), Err(LogFilterError::Reload(_))));
+        assert_eq!(level.current(), "info");
+    }
+
+    #[test]
+    fn test_request_correlation_id() {
+        let mut headers = HeaderMap::new();
+        let fresh = request_correlation_id(&headers);
+        assert_eq!(fresh.len(), 16);
+        assert_ne!(fresh, request_correlation_id(&headers));
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"));
+        assert_eq!(request_correlation_id(&headers), "abc-123");
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("<script>"));
+        assert_ne!(request_correlation_id(&headers), "<script>");
+    }
+
+    #[test]
+    fn test_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "secret"));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert!(authorized(&headers, "secret"));
+        assert!(!authorized(&headers, "secrets"));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secreT"),
+        );
+        assert!(!authorized(&headers, "secret"));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("secret"));
+        assert!(!authorized(&headers, "secret"));
+    }
+
+    async fn call(
+        app: Router,
+        method: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let mut request = Request::builder().method(method).uri("/admin/loglevel");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = app
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes() {
+        let (_layer, level) = LogLevel::new("info").unwrap();
+        let app = admin_routes(level.clone(), "secret");
+
+        let (status, _) = call(app.clone(), "GET", None, "").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(app.clone(), "PUT", Some("wrong"), "debug").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(level.current(), "info");
+
+        let (status, body) = call(app.clone(), "GET", Some("secret"), "").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "info"));
+        let (status, body) = call(app.clone(), "PUT", Some("secret"), "testbot=debug").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "testbot=debug"));
+        assert_eq!(level.current(), "testbot=debug");
+        let (status, _) = call(app, "PUT", Some("secret"), "testbot=loudest").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(level.current(), "testbot=debug");
+    }
+}
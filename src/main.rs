@@ -1,7 +1,10 @@
 mod commands;
+mod interactions;
+mod logging;
 mod metrics;
 mod models;
 mod schema;
+mod telemetry;
 mod utils;
 
 // #[macro_use]
@@ -16,7 +19,20 @@ use poise::{
     serenity_prelude::{ClientBuilder, GatewayIntents},
 };
 // use std::error::Error;
+use crate::commands::advice::client::{AdviceCache, AdviceConfig, HttpAdviceClient};
+use crate::commands::stonks::market::MarketData;
+use crate::commands::stonks::quote::QuoteCache;
+use crate::commands::stonks::summary::{poll_summaries, SUMMARY_POLL_INTERVAL};
+use crate::commands::stonks::symbols::SymbolDirectory;
+use crate::commands::stonks::watch::{poll_alerts, poll_interval_from_env};
+use crate::interactions::InteractionTracker;
+use crate::logging::{
+    admin_routes, admin_token_from_env, request_correlation_id, LogConfig, LogLevel,
+};
 use crate::models::CommandHistory;
+use crate::telemetry::{OtlpConfig, TracedFramework};
+use axum::http::header;
+use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::{response::Html, Router};
 use chrono::Utc;
@@ -27,35 +43,42 @@ use tokio::sync::Mutex as TokioMutex;
 // All use statements above
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 use axum::serve;
-use poise::serenity_prelude::{ChannelId, Guild, GuildChannel, GuildId, User, UserId};
-use prometheus::{
-    register_histogram_vec, register_int_counter_vec, register_int_gauge, HistogramTimer,
-    HistogramVec, IntCounterVec, IntGauge,
+use metrics::{
+    prometheus_metrics, record_command_outcome, remove_guild_metrics, set_process_metrics,
+    track_http, update_discord_metrics, update_guild_metrics, update_resource_metrics,
+    CommandTimers, Outcome, COMMAND_REQUESTS,
 };
+use poise::serenity_prelude::{ChannelId, Guild, GuildChannel, GuildId, User, UserId};
 use std::collections::HashMap;
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
-use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+use tower_http::trace::TraceLayer;
 use tracing::error;
-use tracing::Level;
-use utils::{
-    prometheus_metrics, set_process_metrics, update_discord_metrics, update_guild_metrics,
-    update_resource_metrics,
-};
 
 use commands::{
     advice::advice,
-    ball::ball,
+    ball::{ball, ballmode},
     botsnack::botsnack,
-    desc::set,
+    desc::{get, history, keys, revert, set, unset},
     drink::drink,
+    factoids::{factoids, FactoidCooldowns},
     food::food,
     github::github,
-    owner::quit,
+    lunchpoll::lunchpoll,
+    owner::{loglevel, quit, refreshsymbols},
     pingpong::ping,
+    pool::pool,
     random::random,
     stats::stats,
-    stonks::{graph, stonkcomp, stonks},
+    stonks::{
+        currency::{crypto, fx},
+        graph,
+        paper::{buy, leaderboard, portfolio, sell},
+        quote::quote,
+        stonkcomp, stonks,
+        summary::marketsummary,
+        watch::{alert, watch},
+    },
 };
 
 // use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
@@ -64,10 +87,17 @@ use commands::{
 type Error = Box<dyn std::error::Error + Send + Sync>;
 pub struct Data {
     pub db_pool: Pool<ConnectionManager<PgConnection>>,
-    pub command_timers: Arc<RwLock<HashMap<String, f64>>>,
+    pub command_timers: CommandTimers,
+    pub log_level: LogLevel,
     pub guilds: Arc<RwLock<HashMap<GuildId, Guild>>>,
     pub users: Arc<RwLock<HashMap<UserId, User>>>,
     pub channels: Arc<RwLock<HashMap<ChannelId, GuildChannel>>>,
+    pub interaction_tracker: RwLock<InteractionTracker>,
+    pub advice_client: HttpAdviceClient,
+    pub advice_cache: AdviceCache,
+    pub market_data: MarketData,
+    pub quote_cache: QuoteCache,
+    pub symbols: SymbolDirectory,
 }
 
 // pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
@@ -77,127 +107,6 @@ struct WebConfig {
     history_retention_days: i64,
 }
 
-// Global metrics registry
-lazy_static::lazy_static! {
-    static ref COMMAND_COUNTER: IntCounterVec = register_int_counter_vec!(
-        "bot_command_total",
-        "Total number of commands invoked",
-        &["command"]
-    ).unwrap();
-    static ref COMMAND_FAILURES: IntCounterVec = register_int_counter_vec!(
-        "bot_command_failures_total",
-        "Total number of failed command invocations",
-        &["command"]
-    ).unwrap();
-    static ref COMMAND_DURATION: HistogramVec = register_histogram_vec!(
-        "bot_command_duration_seconds",
-        "Command execution duration in seconds",
-        &["command"]
-    ).unwrap();
-    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
-        "bot_http_requests_total",
-        "Total HTTP requests",
-        &["endpoint", "method"]
-    ).unwrap();
-    static ref HTTP_DURATION: HistogramVec = register_histogram_vec!(
-        "bot_http_request_duration_seconds",
-        "HTTP request duration in seconds",
-        &["endpoint", "method"]
-    ).unwrap();
-    static ref PROCESS_START_TIME: IntGauge = register_int_gauge!(
-        "process_start_time_seconds",
-        "Process start time in seconds since epoch"
-    ).unwrap();
-    static ref DB_POOL_CONNECTIONS: IntGauge = register_int_gauge!(
-        "bot_db_pool_connections",
-        "Number of DB pool connections"
-    ).unwrap();
-    static ref MEMORY_USAGE: IntGauge = register_int_gauge!(
-        "bot_memory_usage_bytes",
-        "Memory usage of the bot process in bytes"
-    ).unwrap();
-    static ref CPU_USAGE: IntGauge = register_int_gauge!(
-        "bot_cpu_usage_percent",
-        "CPU usage of the bot process in percent (0-100)"
-    ).unwrap();
-    static ref DISCORD_GUILD_COUNT: IntGauge = register_int_gauge!(
-        "bot_discord_guild_count",
-        "Number of Discord guilds (servers) the bot is in"
-    ).unwrap();
-    static ref DISCORD_USER_COUNT: IntGauge = register_int_gauge!(
-        "bot_discord_user_count",
-        "Number of Discord users visible to the bot"
-    ).unwrap();
-    static ref DISCORD_CHANNEL_COUNT: IntGauge = register_int_gauge!(
-        "bot_discord_channel_count",
-        "Number of Discord channels visible to the bot"
-    ).unwrap();
-    static ref GUILD_MEMBER_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_member_count",
-        "Number of members in each guild"
-    ).unwrap();
-    static ref GUILD_CHANNEL_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_channel_count",
-        "Number of channels in each guild"
-    ).unwrap();
-    static ref GUILD_ROLE_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_role_count",
-        "Number of roles in each guild"
-    ).unwrap();
-    static ref GUILD_ONLINE_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_online_count",
-        "Number of online members in each guild"
-    ).unwrap();
-    static ref GUILD_CREATION_TIME: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_creation_time_seconds",
-        "Guild creation time in seconds since epoch"
-    ).unwrap();
-    static ref GUILD_HUMAN_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_human_count",
-        "Number of human members in each guild"
-    ).unwrap();
-    static ref GUILD_BOT_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_bot_count",
-        "Number of bot members in each guild"
-    ).unwrap();
-    static ref GUILD_TEXT_CHANNEL_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_text_channel_count",
-        "Number of text channels in each guild"
-    ).unwrap();
-    static ref GUILD_VOICE_CHANNEL_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_voice_channel_count",
-        "Number of voice channels in each guild"
-    ).unwrap();
-    static ref GUILD_CATEGORY_CHANNEL_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_category_channel_count",
-        "Number of category channels in each guild"
-    ).unwrap();
-    static ref GUILD_EMOJI_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_emoji_count",
-        "Number of emojis in each guild"
-    ).unwrap();
-    static ref GUILD_STICKER_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_sticker_count",
-        "Number of stickers in each guild"
-    ).unwrap();
-    static ref GUILD_BOOST_COUNT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_boost_count",
-        "Number of boosts in each guild"
-    ).unwrap();
-    static ref GUILD_PREMIUM_TIER: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_premium_tier",
-        "Premium tier of each guild"
-    ).unwrap();
-    static ref GUILD_OWNER_ID: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_owner_id",
-        "Owner ID of each guild (gauge set to 1 for the owner, 0 otherwise)"
-    ).unwrap();
-    static ref GUILD_AFK_TIMEOUT: prometheus::IntGauge = register_int_gauge!(
-        "bot_guild_afk_timeout_seconds",
-        "AFK timeout in seconds for each guild"
-    ).unwrap();
-}
-
 async fn bot_info() -> Html<String> {
     Html(format!("<h1>TestBot</h1><p>Configuration: ...</p>"))
 }
@@ -306,15 +215,34 @@ async fn stats_data_handler(
 }
 
 // Add a /metrics endpoint for Prometheus
-async fn metrics_handler() -> Html<String> {
-    Html(prometheus_metrics())
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        prometheus_metrics(),
+    )
 }
 
 // Add this before the main function
-struct Handler;
+struct Handler {
+    db_pool: Pool<ConnectionManager<PgConnection>>,
+    factoid_cooldowns: FactoidCooldowns,
+}
 
 #[poise::serenity_prelude::async_trait]
 impl poise::serenity_prelude::EventHandler for Handler {
+    async fn message(
+        &self,
+        ctx: poise::serenity_prelude::Context,
+        msg: poise::serenity_prelude::Message,
+    ) {
+        if let Err(e) =
+            commands::factoids::handle_message(&ctx, &self.db_pool, &self.factoid_cooldowns, &msg)
+                .await
+        {
+            error!("Error while handling factoid trigger: {}", e);
+        }
+    }
+
     async fn ready(
         &self,
         ctx: poise::serenity_prelude::Context,
@@ -337,9 +265,10 @@ impl poise::serenity_prelude::EventHandler for Handler {
     async fn guild_delete(
         &self,
         ctx: poise::serenity_prelude::Context,
-        _incomplete: poise::serenity_prelude::UnavailableGuild,
+        incomplete: poise::serenity_prelude::UnavailableGuild,
         _full: Option<poise::serenity_prelude::Guild>,
     ) {
+        remove_guild_metrics(incomplete.id);
         update_discord_metrics(&ctx);
         update_guild_metrics(&ctx);
     }
@@ -395,6 +324,14 @@ impl poise::serenity_prelude::EventHandler for Handler {
 }
 
 async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
+    if let Some(ctx) = error.ctx() {
+        let elapsed = ctx.data().command_timers.finish(ctx.id());
+        record_command_outcome(
+            &ctx.command().qualified_name,
+            Outcome::of_framework_error(&error),
+            elapsed,
+        );
+    }
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
         poise::FrameworkError::Command { error, ctx, .. } => {
@@ -412,6 +349,8 @@ async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     dotenv().ok();
+    let (log_level, tracer_provider) =
+        telemetry::init(&LogConfig::from_env()?, OtlpConfig::from_env().as_ref())?;
     let token = std::env::var("DISCORD_TOKEN")?;
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let manager = ConnectionManager::<PgConnection>::new(database_url);
@@ -427,6 +366,8 @@ async fn main() -> Result<(), Error> {
             tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         }
     });
+    let alert_db_pool = db_pool.clone();
+    let summary_db_pool = db_pool.clone();
     // Run migrations automatically
     {
         let mut conn = db_pool.get().unwrap();
@@ -450,58 +391,91 @@ async fn main() -> Result<(), Error> {
         commands: vec![
             advice(),
             ball(),
+            ballmode(),
             botsnack(),
             set(),
+            get(),
+            unset(),
+            keys(),
+            history(),
+            revert(),
+            factoids(),
             drink(),
             food(),
             github(),
+            lunchpoll(),
             quit(),
+            loglevel(),
+            refreshsymbols(),
             ping(),
+            pool(),
             random(),
             stonks(),
             stonkcomp(),
             graph(),
+            quote(),
+            crypto(),
+            fx(),
+            watch(),
+            alert(),
+            buy(),
+            sell(),
+            portfolio(),
+            leaderboard(),
+            marketsummary(),
             stats(),
         ],
         pre_command: |ctx| {
             Box::pin(async move {
                 let pool = &ctx.data().db_pool;
+                telemetry::record_command(&ctx.command().qualified_name);
                 let mut conn = pool.get().unwrap();
                 let command = ctx.command().name.clone();
                 let user = ctx.author().id.to_string();
                 crate::commands::log_command(&mut conn, &user, &command);
                 crate::commands::update_command_stats(&mut conn, &command, &command);
-                COMMAND_COUNTER.with_label_values(&[&command]).inc();
-                let timer = COMMAND_DURATION
-                    .with_label_values(&[&command])
-                    .start_timer();
-                let mut timers = ctx.data().command_timers.lock().await;
-                timers.insert(command.clone(), timer);
+                COMMAND_REQUESTS
+                    .with_label_values(&[&ctx.command().qualified_name])
+                    .inc();
+                ctx.data().command_timers.start(ctx.id());
             })
         },
         post_command: |ctx| {
             Box::pin(async move {
-                let command = ctx.command().name.clone();
-                let mut timers = ctx.data().command_timers.lock().await;
-                if let Some(timer) = timers.remove(&command) {
-                    timer.observe_duration();
-                }
+                let elapsed = ctx.data().command_timers.finish(ctx.id());
+                record_command_outcome(&ctx.command().qualified_name, Outcome::Ok, elapsed);
             })
         },
         on_error: |error| Box::pin(on_error(error)),
         ..Default::default()
     };
+    let handler = Handler {
+        db_pool: db_pool.clone(),
+        factoid_cooldowns: FactoidCooldowns::new(),
+    };
+    let data_log_level = log_level.clone();
     let framework = poise::Framework::builder()
         .options(options)
         .setup(move |_ctx, _ready, _framework| {
             let db_pool = db_pool.clone();
+            let log_level = data_log_level.clone();
             Box::pin(async move {
+                let interaction_tracker = InteractionTracker::new(Arc::new(db_pool.clone()));
+                let advice_client = HttpAdviceClient::new(AdviceConfig::from_env())?;
+                let market_data = MarketData::from_env()?;
                 Ok(Data {
                     db_pool,
-                    command_timers: Arc::new(TokioMutex::new(HashMap::new())),
+                    command_timers: CommandTimers::new(),
+                    log_level,
                     guilds: Arc::new(RwLock::new(HashMap::new())),
                     users: Arc::new(RwLock::new(HashMap::new())),
                     channels: Arc::new(RwLock::new(HashMap::new())),
+                    interaction_tracker: RwLock::new(interaction_tracker),
+                    advice_client,
+                    advice_cache: AdviceCache::new(),
+                    market_data,
+                    quote_cache: QuoteCache::from_env(),
+                    symbols: SymbolDirectory::from_env(),
                 })
             })
         })
@@ -510,37 +484,66 @@ async fn main() -> Result<(), Error> {
         token,
         GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT,
     )
-    .framework(framework)
-    .event_handler(Handler)
+    .framework(TracedFramework(framework))
+    .event_handler(handler)
     .await?;
-    client.start().await?;
+    // Spawn a background task to check price alerts
+    tokio::spawn(poll_alerts(
+        client.http.clone(),
+        alert_db_pool,
+        MarketData::from_env()?,
+        poll_interval_from_env(),
+    ));
+    // And one to post scheduled market summaries
+    tokio::spawn(poll_summaries(
+        client.http.clone(),
+        summary_db_pool,
+        MarketData::from_env()?,
+        SUMMARY_POLL_INTERVAL,
+    ));
     let web_port: u16 = std::env::var("WEB_PORT")
         .ok()
         .and_then(|s| s.parse().ok())
         .unwrap_or(8080);
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(bot_info))
         .route("/history", get(command_history_handler))
         .route("/stats", get(stats_handler))
         .route("/stats/data", get(stats_data_handler))
-        .route("/metrics", get(metrics_handler))
+        .route("/metrics", get(metrics_handler));
+    // The admin endpoints are only served when a token is configured
+    if let Some(token) = admin_token_from_env() {
+        app = app.merge(admin_routes(log_level, &token));
+    }
+    let app = app
+        .route_layer(axum::middleware::from_fn(track_http))
         .layer(axum::extract::Extension(pool))
         .layer(axum::extract::Extension(web_config))
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
-                .on_request(|request: &axum::http::Request<_>, _span: &tracing::Span| {
-                    let method = request.method().as_str();
-                    let path = request.uri().path();
-                    metrics::HTTP_REQUESTS
-                        .with_label_values(&[&path.to_string(), &method.to_string()])
-                        .inc();
-                }),
-        );
+        .layer(TraceLayer::new_for_http().make_span_with(
+            |request: &axum::http::Request<axum::body::Body>| {
+                tracing::info_span!(
+                    "request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    correlation_id = %request_correlation_id(request.headers()),
+                )
+            },
+        ));
     tokio::spawn(async move {
         let listener = TcpListener::bind(("0.0.0.0", web_port)).await.unwrap();
         serve(listener, app.into_make_service()).await.unwrap();
     });
+    let result = client.start().await;
+    // Flush the spans still waiting to be exported. The exporter's blocking
+    // client can't run on a runtime thread.
+    if let Some(provider) = tracer_provider {
+        match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Error while shutting down trace export: {}", e),
+            Err(e) => error!("Trace export shutdown didn't finish: {}", e),
+        }
+    }
+    result?;
     Ok(())
 }
 
@@ -1,225 +1,507 @@
+//! The bot's Prometheus metrics. Every metric lives in the one [`REGISTRY`]
+//! under the `bot` namespace and is served from `/metrics` by
+//! [`prometheus_metrics`]. Names are part of our dashboards' contract, so
+//! rename with care:
+//!
+//! | Metric | Type | Labels |
+//! |---|---|---|
+//! | `bot_command_requests_total` | counter | `command` |
+//! | `bot_command_errors_total` | counter | `command`, `outcome` |
+//! | `bot_command_duration_seconds` | histogram | `command`, `outcome` |
+//! | `bot_interaction_requests_total` | counter | `type` |
+//! | `bot_interaction_errors_total` | counter | `type` |
+//! | `bot_interaction_duration_seconds` | histogram | `type` |
+//! | `bot_http_requests_total` | counter | `path`, `method` |
+//! | `bot_http_request_duration_seconds` | histogram | `path`, `method` |
+//! | `bot_process_start_time_seconds` | gauge | |
+//! | `bot_memory_usage_bytes` | gauge | |
+//! | `bot_cpu_usage_percent` | gauge | |
+//! | `bot_db_pool_connections` | gauge | |
+//! | `bot_discord_guild_count`, `bot_discord_user_count`, `bot_discord_channel_count`, `bot_discord_member_count` | gauge | |
+//! | `bot_guild_*` (members, channels, roles, emojis, boosts, ...) | gauge | `guild_id` |
+//! | `bot_guild_info` (always 1) | gauge | `guild_id`, `name` |
+//! | `bot_untracked_guild_count` | gauge | |
+//!
+//! The `bot_http_*` series are recorded by [`track_http`] and labelled with
+//! the matched route, e.g. `/stats/data`, so unknown paths can't add series.
+//!
+//! Only the largest `METRICS_MAX_GUILDS` guilds (default
+//! [`DEFAULT_MAX_GUILD_SERIES`]) get `bot_guild_*` series, so the exposition
+//! stays small however many guilds the bot is in.
+//!
+//! Command latency is recorded per invocation with an [`Outcome`] label, in
+//! buckets set by `COMMAND_DURATION_BUCKETS` (default
+//! [`DEFAULT_COMMAND_BUCKETS`]) so SLO burn-rate alerts can pick a threshold
+//! that is a bucket boundary.
+
+use crate::commands::advice::client::AdviceError;
+use crate::commands::stonks::market::MarketError;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool};
 use lazy_static::lazy_static;
+use poise::serenity_prelude::{ChannelType, Context, Guild, GuildId, OnlineStatus};
+use poise::FrameworkError;
 use prometheus::{
-    Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter,
-    IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
 };
-use std::error::Error;
-
-/// Macro to register a gauge metric
-macro_rules! register_gauge {
-    ($name:ident, $help:expr) => {
-        pub static ref $name: IntGauge = register_int_gauge!(
-            concat!("bot_", stringify!($name)),
-            $help
-        ).unwrap();
-    };
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{ProcessesToUpdate, System};
+use tracing::warn;
+
+/// Prefix of every metric name.
+pub const NAMESPACE: &str = "bot";
+
+/// The content type of [`prometheus_metrics`]' output.
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+/// Most guilds given their own `bot_guild_*` series when
+/// `METRICS_MAX_GUILDS` isn't set.
+pub const DEFAULT_MAX_GUILD_SERIES: usize = 100;
+
+/// How many guilds get their own series, from `METRICS_MAX_GUILDS`.
+pub fn max_guild_series_from_env() -> usize {
+    std::env::var("METRICS_MAX_GUILDS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_GUILD_SERIES)
 }
 
-/// Macro to register a counter vector metric
-macro_rules! register_counter_vec {
-    ($name:ident, $help:expr, $labels:expr) => {
-        pub static ref $name: IntCounterVec = register_int_counter_vec!(
-            concat!("bot_", stringify!($name)),
-            $help,
-            $labels
-        ).unwrap();
-    };
+/// Command latency buckets in seconds when `COMMAND_DURATION_BUCKETS` isn't
+/// set, finest around the one-second mark most latency SLOs sit at.
+pub const DEFAULT_COMMAND_BUCKETS: &[f64] =
+    &[0.05, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Parse comma-separated bucket bounds in seconds, e.g. `0.1,0.5,1,5`.
+pub fn parse_buckets(spec: &str) -> Result<Vec<f64>, String> {
+    let buckets = spec
+        .split(',')
+        .map(|bound| {
+            bound
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("'{}' isn't a number of seconds", bound.trim()))
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    if buckets
+        .iter()
+        .any(|bound| !bound.is_finite() || *bound <= 0.0)
+        || buckets.windows(2).any(|pair| pair[0] >= pair[1])
+    {
+        return Err("bucket bounds must be positive and increasing".to_string());
+    }
+    Ok(buckets)
 }
 
-/// Macro to register a histogram vector metric
-macro_rules! register_histogram_vec {
-    ($name:ident, $help:expr, $labels:expr) => {
-        pub static ref $name: HistogramVec = register_histogram_vec!(
-            concat!("bot_", stringify!($name)),
-            $help,
-            $labels
-        ).unwrap();
-    };
+/// The command latency buckets, from `COMMAND_DURATION_BUCKETS`.
+pub fn command_buckets_from_env() -> Vec<f64> {
+    match std::env::var("COMMAND_DURATION_BUCKETS") {
+        Ok(spec) => parse_buckets(&spec).unwrap_or_else(|e| {
+            warn!("Ignoring COMMAND_DURATION_BUCKETS: {}", e);
+            DEFAULT_COMMAND_BUCKETS.to_vec()
+        }),
+        Err(_) => DEFAULT_COMMAND_BUCKETS.to_vec(),
+    }
 }
 
-lazy_static! {
-    pub static ref REGISTRY: Registry = Registry::new();
-
-    // Command metrics
-    pub static ref COMMAND_REQUESTS: IntCounterVec = IntCounterVec::new(
-        Opts::new("command_requests_total", "Total number of command requests"),
-        &["command"]
-    )
-    .unwrap();
-
-    pub static ref COMMAND_DURATION: HistogramVec = HistogramVec::new(
-        HistogramOpts::new("command_duration_seconds", "Command execution duration in seconds"),
-        &["command"]
-    )
-    .unwrap();
-
-    pub static ref COMMAND_ERRORS: IntCounterVec = IntCounterVec::new(
-        Opts::new("command_errors_total", "Total number of command errors"),
-        &["command"]
-    )
-    .unwrap();
+/// How a command invocation ended; the `outcome` label on command metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    /// Bad arguments, missing permissions, a failed check and the like.
+    UserError,
+    InternalError,
+    /// A cooldown, or a rate limit from an API the command called.
+    RateLimited,
+    Timeout,
+}
 
-    // HTTP metrics
-    pub static ref HTTP_REQUESTS: IntCounterVec = IntCounterVec::new(
-        Opts::new("http_requests_total", "Total number of HTTP requests"),
-        &["path", "method"]
-    )
-    .unwrap();
+impl Outcome {
+    pub fn label(self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::UserError => "user_error",
+            Outcome::InternalError => "internal_error",
+            Outcome::RateLimited => "rate_limited",
+            Outcome::Timeout => "timeout",
+        }
+    }
 
-    pub static ref HTTP_DURATION: HistogramVec = HistogramVec::new(
-        HistogramOpts::new("http_duration_seconds", "HTTP request duration in seconds"),
-        &["path", "method"]
-    )
-    .unwrap();
+    /// Classify an error a command returned by walking its source chain.
+    pub fn of_error(error: &(dyn std::error::Error + 'static)) -> Self {
+        let mut current = Some(error);
+        while let Some(error) = current {
+            if error.is::<tokio::time::error::Elapsed>() {
+                return Outcome::Timeout;
+            }
+            if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+                if e.is_timeout() {
+                    return Outcome::Timeout;
+                }
+            }
+            match error.downcast_ref::<MarketError>() {
+                Some(MarketError::RateLimited) => return Outcome::RateLimited,
+                Some(MarketError::UnknownSymbol(_)) => return Outcome::UserError,
+                _ => {}
+            }
+            if let Some(AdviceError::Status(429)) = error.downcast_ref::<AdviceError>() {
+                return Outcome::RateLimited;
+            }
+            current = error.source();
+        }
+        Outcome::InternalError
+    }
 
-    // System metrics
-    pub static ref MEMORY_USAGE: Gauge = Gauge::new(
-        "memory_usage_bytes",
-        "Current memory usage in bytes"
-    )
-    .unwrap();
+    /// Classify an error poise reports for a command invocation.
+    pub fn of_framework_error<U>(error: &FrameworkError<'_, U, crate::Error>) -> Self {
+        match error {
+            FrameworkError::Command { error, .. } => Outcome::of_error(error.as_ref()),
+            FrameworkError::CooldownHit { .. } => Outcome::RateLimited,
+            FrameworkError::ArgumentParse { .. }
+            | FrameworkError::SubcommandRequired { .. }
+            | FrameworkError::MissingUserPermissions { .. }
+            | FrameworkError::NotAnOwner { .. }
+            | FrameworkError::GuildOnly { .. }
+            | FrameworkError::DmOnly { .. }
+            | FrameworkError::NsfwOnly { .. }
+            | FrameworkError::CommandCheckFailed { .. } => Outcome::UserError,
+            _ => Outcome::InternalError,
+        }
+    }
+}
 
-    pub static ref CPU_USAGE: Gauge = Gauge::new(
-        "cpu_usage_percent",
-        "Current CPU usage percentage"
-    )
-    .unwrap();
+/// Timers older than this are assumed abandoned and dropped.
+const STALE_TIMER: Duration = Duration::from_secs(15 * 60);
 
-    pub static ref GUILD_COUNT: IntGauge = IntGauge::new(
-        "guild_count",
-        "Total number of guilds"
-    )
-    .unwrap();
+/// When each in-flight command started, keyed by invocation id (the
+/// interaction or message id), so concurrent runs of one command each get
+/// their own timing.
+#[derive(Debug, Default)]
+pub struct CommandTimers {
+    started: Mutex<HashMap<u64, Instant>>,
+}
 
-    pub static ref MEMBER_COUNT: IntGauge = IntGauge::new(
-        "member_count",
-        "Total number of members"
-    )
-    .unwrap();
+impl CommandTimers {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    // Process metrics
-    pub static ref DB_POOL_CONNECTIONS: IntGauge = register_int_gauge!(
-        "db_pool_connections",
-        "Number of database pool connections"
-    )
-    .unwrap();
+    pub fn start(&self, invocation: u64) {
+        let mut started = self.started.lock().unwrap_or_else(PoisonError::into_inner);
+        started.retain(|_, at| at.elapsed() < STALE_TIMER);
+        started.insert(invocation, Instant::now());
+    }
 
-    pub static ref PROCESS_START_TIME: IntGauge = register_int_gauge!(
-        "process_start_time_seconds",
-        "Process start time in seconds since epoch"
-    )
-    .unwrap();
+    /// How long ago `invocation` started, forgetting it.
+    pub fn finish(&self, invocation: u64) -> Option<Duration> {
+        let mut started = self.started.lock().unwrap_or_else(PoisonError::into_inner);
+        started.remove(&invocation).map(|at| at.elapsed())
+    }
 
-    // Discord metrics
-    pub static ref USER_COUNT: IntGauge = register_int_gauge!(
-        "user_count",
-        "Total number of users"
-    )
-    .unwrap();
+    pub fn len(&self) -> usize {
+        self.started
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
 
-    pub static ref CHANNEL_COUNT: IntGauge = register_int_gauge!(
-        "channel_count",
-        "Total number of channels"
-    )
-    .unwrap();
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
 
-    // Guild metrics
-    pub static ref TEXT_CHANNEL_COUNT: IntGauge = register_int_gauge!(
-        "text_channel_count",
-        "Total number of text channels"
-    )
-    .unwrap();
+fn opts(name: &str, help: &str) -> Opts {
+    Opts::new(name, help).namespace(NAMESPACE)
+}
 
-    pub static ref VOICE_CHANNEL_COUNT: IntGauge = register_int_gauge!(
-        "voice_channel_count",
-        "Total number of voice channels"
-    )
-    .unwrap();
+fn counter_vec(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    IntCounterVec::new(opts(name, help), labels).expect("valid counter")
+}
 
-    pub static ref CATEGORY_COUNT: IntGauge = register_int_gauge!(
-        "category_count",
-        "Total number of categories"
-    )
-    .unwrap();
+fn histogram_vec(name: &str, help: &str, labels: &[&str]) -> HistogramVec {
+    HistogramVec::new(HistogramOpts::new(name, help).namespace(NAMESPACE), labels)
+        .expect("valid histogram")
+}
 
-    pub static ref ROLE_COUNT: IntGauge = register_int_gauge!(
-        "role_count",
-        "Total number of roles"
-    )
-    .unwrap();
+fn int_gauge(name: &str, help: &str) -> IntGauge {
+    IntGauge::with_opts(opts(name, help)).expect("valid gauge")
+}
 
-    pub static ref EMOJI_COUNT: IntGauge = register_int_gauge!(
-        "emoji_count",
-        "Total number of emojis"
-    )
-    .unwrap();
+fn guild_gauge(name: &str, help: &str) -> IntGaugeVec {
+    IntGaugeVec::new(opts(name, help), &["guild_id"]).expect("valid gauge")
+}
 
-    pub static ref BOOST_COUNT: IntGauge = register_int_gauge!(
-        "boost_count",
-        "Total number of boosts"
-    )
-    .unwrap();
+lazy_static! {
+    /// The registry `/metrics` exposes. Every metric below is registered
+    /// the first time it is touched, so nothing is ever missing from it.
+    pub static ref REGISTRY: Registry = {
+        let registry = Registry::new();
+        register_metrics(&registry).expect("metric names are unique");
+        registry
+    };
 
-    pub static ref BOOST_LEVEL: IntGauge = register_int_gauge!(
-        "boost_level",
-        "Current boost level"
+    // Command metrics
+    pub static ref COMMAND_REQUESTS: IntCounterVec = counter_vec(
+        "command_requests_total",
+        "Total number of command requests",
+        &["command"]
+    );
+    pub static ref COMMAND_ERRORS: IntCounterVec = counter_vec(
+        "command_errors_total",
+        "Total number of failed command invocations",
+        &["command", "outcome"]
+    );
+    pub static ref COMMAND_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("command_duration_seconds", "Command execution duration in seconds")
+            .namespace(NAMESPACE)
+            .buckets(command_buckets_from_env()),
+        &["command", "outcome"]
     )
-    .unwrap();
+    .expect("valid histogram");
 
     // Interaction metrics
-    pub static ref INTERACTION_REQUESTS: IntCounterVec = register_int_counter_vec!(
+    pub static ref INTERACTION_REQUESTS: IntCounterVec = counter_vec(
         "interaction_requests_total",
         "Total number of interaction requests",
         &["type"]
-    )
-    .unwrap();
-
-    pub static ref INTERACTION_ERRORS: IntCounterVec = register_int_counter_vec!(
+    );
+    pub static ref INTERACTION_ERRORS: IntCounterVec = counter_vec(
         "interaction_errors_total",
         "Total number of interaction errors",
         &["type"]
-    )
-    .unwrap();
-
-    pub static ref INTERACTION_DURATION: HistogramVec = register_histogram_vec!(
+    );
+    pub static ref INTERACTION_DURATION: HistogramVec = histogram_vec(
         "interaction_duration_seconds",
         "Interaction execution duration in seconds",
         &["type"]
+    );
+
+    // HTTP metrics
+    pub static ref HTTP_REQUESTS: IntCounterVec = counter_vec(
+        "http_requests_total",
+        "Total number of HTTP requests",
+        &["path", "method"]
+    );
+    pub static ref HTTP_DURATION: HistogramVec = histogram_vec(
+        "http_request_duration_seconds",
+        "HTTP request duration in seconds",
+        &["path", "method"]
+    );
+
+    // Process metrics
+    pub static ref PROCESS_START_TIME: IntGauge = int_gauge(
+        "process_start_time_seconds",
+        "Process start time in seconds since epoch"
+    );
+    pub static ref MEMORY_USAGE: IntGauge = int_gauge(
+        "memory_usage_bytes",
+        "Memory usage of the bot process in bytes"
+    );
+    pub static ref CPU_USAGE: Gauge = Gauge::with_opts(opts(
+        "cpu_usage_percent",
+        "CPU usage of the bot process in percent"
+    ))
+    .expect("valid gauge");
+    pub static ref DB_POOL_CONNECTIONS: IntGauge = int_gauge(
+        "db_pool_connections",
+        "Number of database pool connections"
+    );
+
+    // Discord metrics
+    pub static ref GUILD_COUNT: IntGauge = int_gauge(
+        "discord_guild_count",
+        "Number of Discord guilds (servers) the bot is in"
+    );
+    pub static ref USER_COUNT: IntGauge = int_gauge(
+        "discord_user_count",
+        "Number of Discord users visible to the bot"
+    );
+    pub static ref CHANNEL_COUNT: IntGauge = int_gauge(
+        "discord_channel_count",
+        "Number of Discord channels visible to the bot"
+    );
+    pub static ref MEMBER_COUNT: IntGauge = int_gauge(
+        "discord_member_count",
+        "Number of members across all guilds"
+    );
+
+    // Guild metrics
+    pub static ref GUILD_MEMBER_COUNT: IntGaugeVec = guild_gauge(
+        "guild_member_count",
+        "Number of members in each guild"
+    );
+    pub static ref GUILD_CHANNEL_COUNT: IntGaugeVec = guild_gauge(
+        "guild_channel_count",
+        "Number of channels in each guild"
+    );
+    pub static ref GUILD_ROLE_COUNT: IntGaugeVec = guild_gauge(
+        "guild_role_count",
+        "Number of roles in each guild"
+    );
+    pub static ref GUILD_ONLINE_COUNT: IntGaugeVec = guild_gauge(
+        "guild_online_count",
+        "Number of online members in each guild"
+    );
+    pub static ref GUILD_CREATION_TIME: IntGaugeVec = guild_gauge(
+        "guild_creation_time_seconds",
+        "Guild creation time in seconds since epoch"
+    );
+    pub static ref GUILD_HUMAN_COUNT: IntGaugeVec = guild_gauge(
+        "guild_human_count",
+        "Number of human members in each guild"
+    );
+    pub static ref GUILD_BOT_COUNT: IntGaugeVec = guild_gauge(
+        "guild_bot_count",
+        "Number of bot members in each guild"
+    );
+    pub static ref GUILD_TEXT_CHANNEL_COUNT: IntGaugeVec = guild_gauge(
+        "guild_text_channel_count",
+        "Number of text channels in each guild"
+    );
+    pub static ref GUILD_VOICE_CHANNEL_COUNT: IntGaugeVec = guild_gauge(
+        "guild_voice_channel_count",
+        "Number of voice channels in each guild"
+    );
+    pub static ref GUILD_CATEGORY_CHANNEL_COUNT: IntGaugeVec = guild_gauge(
+        "guild_category_channel_count",
+        "Number of category channels in each guild"
+    );
+    pub static ref GUILD_EMOJI_COUNT: IntGaugeVec = guild_gauge(
+        "guild_emoji_count",
+        "Number of emojis in each guild"
+    );
+    pub static ref GUILD_STICKER_COUNT: IntGaugeVec = guild_gauge(
+        "guild_sticker_count",
+        "Number of stickers in each guild"
+    );
+    pub static ref GUILD_BOOST_COUNT: IntGaugeVec = guild_gauge(
+        "guild_boost_count",
+        "Number of boosts in each guild"
+    );
+    pub static ref GUILD_PREMIUM_TIER: IntGaugeVec = guild_gauge(
+        "guild_premium_tier",
+        "Premium tier of each guild"
+    );
+    pub static ref GUILD_OWNER_ID: IntGaugeVec = guild_gauge(
+        "guild_owner_id",
+        "Owner user id of each guild"
+    );
+    pub static ref GUILD_AFK_TIMEOUT: IntGaugeVec = guild_gauge(
+        "guild_afk_timeout_seconds",
+        "AFK timeout in seconds for each guild"
+    );
+
+    pub static ref GUILD_INFO: IntGaugeVec = IntGaugeVec::new(
+        opts("guild_info", "Always 1, labelled with each guild's name"),
+        &["guild_id", "name"]
     )
-    .unwrap();
-}
-
-pub fn register_metrics() -> Result<(), Box<dyn Error>> {
-    REGISTRY.register(Box::new(COMMAND_REQUESTS.clone()))?;
-    REGISTRY.register(Box::new(COMMAND_DURATION.clone()))?;
-    REGISTRY.register(Box::new(COMMAND_ERRORS.clone()))?;
-    REGISTRY.register(Box::new(HTTP_REQUESTS.clone()))?;
-    REGISTRY.register(Box::new(HTTP_DURATION.clone()))?;
-    REGISTRY.register(Box::new(MEMORY_USAGE.clone()))?;
-    REGISTRY.register(Box::new(CPU_USAGE.clone()))?;
-    REGISTRY.register(Box::new(GUILD_COUNT.clone()))?;
-    REGISTRY.register(Box::new(MEMBER_COUNT.clone()))?;
+    .expect("valid gauge");
+    pub static ref UNTRACKED_GUILD_COUNT: IntGauge = int_gauge(
+        "untracked_guild_count",
+        "Number of guilds left out of the per-guild metrics by METRICS_MAX_GUILDS"
+    );
+
+    /// Guilds that currently have series, with the name on their info series.
+    static ref GUILD_SERIES: Mutex<HashMap<u64, String>> = Mutex::new(HashMap::new());
+    static ref MAX_GUILD_SERIES: usize = max_guild_series_from_env();
+    /// Kept between calls so CPU usage can be measured over the interval.
+    static ref SYSTEM: Mutex<System> = Mutex::new(System::new());
+}
+
+/// Register every metric in `registry`. [`REGISTRY`] does this itself;
+/// this is separate so the set can be checked against a fresh registry.
+pub fn register_metrics(registry: &Registry) -> prometheus::Result<()> {
+    registry.register(Box::new(COMMAND_REQUESTS.clone()))?;
+    registry.register(Box::new(COMMAND_ERRORS.clone()))?;
+    registry.register(Box::new(COMMAND_DURATION.clone()))?;
+    registry.register(Box::new(INTERACTION_REQUESTS.clone()))?;
+    registry.register(Box::new(INTERACTION_ERRORS.clone()))?;
+    registry.register(Box::new(INTERACTION_DURATION.clone()))?;
+    registry.register(Box::new(HTTP_REQUESTS.clone()))?;
+    registry.register(Box::new(HTTP_DURATION.clone()))?;
+    registry.register(Box::new(PROCESS_START_TIME.clone()))?;
+    registry.register(Box::new(MEMORY_USAGE.clone()))?;
+    registry.register(Box::new(CPU_USAGE.clone()))?;
+    registry.register(Box::new(DB_POOL_CONNECTIONS.clone()))?;
+    registry.register(Box::new(GUILD_COUNT.clone()))?;
+    registry.register(Box::new(USER_COUNT.clone()))?;
+    registry.register(Box::new(CHANNEL_COUNT.clone()))?;
+    registry.register(Box::new(MEMBER_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_MEMBER_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_CHANNEL_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_ROLE_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_ONLINE_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_CREATION_TIME.clone()))?;
+    registry.register(Box::new(GUILD_HUMAN_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_BOT_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_TEXT_CHANNEL_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_VOICE_CHANNEL_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_CATEGORY_CHANNEL_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_EMOJI_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_STICKER_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_BOOST_COUNT.clone()))?;
+    registry.register(Box::new(GUILD_PREMIUM_TIER.clone()))?;
+    registry.register(Box::new(GUILD_OWNER_ID.clone()))?;
+    registry.register(Box::new(GUILD_AFK_TIMEOUT.clone()))?;
+    registry.register(Box::new(GUILD_INFO.clone()))?;
+    registry.register(Box::new(UNTRACKED_GUILD_COUNT.clone()))?;
     Ok(())
 }
 
+/// Everything in [`REGISTRY`] in the Prometheus text exposition format.
+pub fn prometheus_metrics() -> String {
+    TextEncoder::new()
+        .encode_to_string(&REGISTRY.gather())
+        .unwrap_or_else(|e| format!("# failed to encode metrics: {}\n", e))
+}
+
 pub fn record_http_request(method: &str, path: &str) {
     HTTP_REQUESTS.with_label_values(&[path, method]).inc();
 }
 
 pub fn record_http_duration(method: &str, path: &str, duration: f64) {
-    HTTP_DURATION.with_label_values(&[path, method]).observe(duration);
+    HTTP_DURATION
+        .with_label_values(&[path, method])
+        .observe(duration);
 }
 
-pub fn record_command_execution(command: &str) {
-    COMMAND_REQUESTS.with_label_values(&[command]).inc();
+/// Count and time a request to the web server. Add it with
+/// `Router::route_layer` so only requests that matched a route are seen.
+pub async fn track_http(path: MatchedPath, request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    record_http_request(method.as_str(), path.as_str());
+    record_http_duration(
+        method.as_str(),
+        path.as_str(),
+        start.elapsed().as_secs_f64(),
+    );
+    response
 }
 
-pub fn record_command_error(command: &str) {
-    COMMAND_ERRORS.with_label_values(&[command]).inc();
+pub fn record_command_execution(command: &str) {
+    COMMAND_REQUESTS.with_label_values(&[command]).inc();
 }
 
-pub fn record_command_duration(command: &str, duration: f64) {
-    COMMAND_DURATION.with_label_values(&[command]).observe(duration);
+/// Record how an invocation ended: its latency, if it was timed, and a
+/// failure for anything but [`Outcome::Ok`].
+pub fn record_command_outcome(command: &str, outcome: Outcome, elapsed: Option<Duration>) {
+    if let Some(elapsed) = elapsed {
+        COMMAND_DURATION
+            .with_label_values(&[command, outcome.label()])
+            .observe(elapsed.as_secs_f64());
+    }
+    if outcome != Outcome::Ok {
+        COMMAND_ERRORS
+            .with_label_values(&[command, outcome.label()])
+            .inc();
+    }
 }
 
 pub fn update_guild_count(count: i64) {
@@ -231,25 +513,223 @@ pub fn update_member_count(count: i64) {
 }
 
 pub fn record_interaction(interaction_type: &str) {
-    INTERACTION_REQUESTS.with_label_values(&[interaction_type]).inc();
+    INTERACTION_REQUESTS
+        .with_label_values(&[interaction_type])
+        .inc();
 }
 
 pub fn record_interaction_error(interaction_type: &str) {
-    INTERACTION_ERRORS.with_label_values(&[interaction_type]).inc();
+    INTERACTION_ERRORS
+        .with_label_values(&[interaction_type])
+        .inc();
 }
 
 pub fn record_interaction_duration(interaction_type: &str, duration: f64) {
-    INTERACTION_DURATION.with_label_values(&[interaction_type]).observe(duration);
+    INTERACTION_DURATION
+        .with_label_values(&[interaction_type])
+        .observe(duration);
+}
+
+/// Set the process gauges once at startup.
+pub fn set_process_metrics(db_pool: &Pool<ConnectionManager<PgConnection>>) {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    PROCESS_START_TIME.set(started as i64);
+    DB_POOL_CONNECTIONS.set(db_pool.state().connections as i64);
+}
+
+/// Refresh memory, CPU and pool gauges. CPU usage is measured since the
+/// previous call, so this should run on a steady interval.
+pub fn update_resource_metrics(db_pool: &Pool<ConnectionManager<PgConnection>>) {
+    DB_POOL_CONNECTIONS.set(db_pool.state().connections as i64);
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        return;
+    };
+    let mut system = SYSTEM.lock().unwrap_or_else(PoisonError::into_inner);
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    if let Some(process) = system.process(pid) {
+        MEMORY_USAGE.set(process.memory() as i64);
+        CPU_USAGE.set(f64::from(process.cpu_usage()));
+    }
+}
+
+/// Refresh the bot-wide Discord gauges from the cache.
+pub fn update_discord_metrics(ctx: &Context) {
+    GUILD_COUNT.set(ctx.cache.guild_count() as i64);
+    USER_COUNT.set(ctx.cache.user_count() as i64);
+    let (mut channels, mut members) = (0, 0);
+    for guild_id in ctx.cache.guilds() {
+        if let Some(guild) = ctx.cache.guild(guild_id) {
+            channels += guild.channels.len() as i64;
+            members += guild.member_count as i64;
+        }
+    }
+    CHANNEL_COUNT.set(channels);
+    MEMBER_COUNT.set(members);
+}
+
+/// Every per-guild gauge, for removing a guild's series.
+fn guild_gauges() -> [&'static IntGaugeVec; 16] {
+    [
+        &GUILD_MEMBER_COUNT,
+        &GUILD_CHANNEL_COUNT,
+        &GUILD_ROLE_COUNT,
+        &GUILD_ONLINE_COUNT,
+        &GUILD_CREATION_TIME,
+        &GUILD_HUMAN_COUNT,
+        &GUILD_BOT_COUNT,
+        &GUILD_TEXT_CHANNEL_COUNT,
+        &GUILD_VOICE_CHANNEL_COUNT,
+        &GUILD_CATEGORY_CHANNEL_COUNT,
+        &GUILD_EMOJI_COUNT,
+        &GUILD_STICKER_COUNT,
+        &GUILD_BOOST_COUNT,
+        &GUILD_PREMIUM_TIER,
+        &GUILD_OWNER_ID,
+        &GUILD_AFK_TIMEOUT,
+    ]
+}
+
+/// The guilds to give series to: the `cap` largest by member count, ties
+/// broken by id so the choice is stable between refreshes.
+fn select_guilds(mut sizes: Vec<(u64, u64)>, cap: usize) -> Vec<u64> {
+    sizes.sort_by(|(a_id, a_members), (b_id, b_members)| {
+        b_members.cmp(a_members).then(a_id.cmp(b_id))
+    });
+    sizes.into_iter().take(cap).map(|(id, _)| id).collect()
+}
+
+/// Refresh the guild gauges from the cache, dropping series of guilds that
+/// have left the cache or fallen outside the cap.
+pub fn update_guild_metrics(ctx: &Context) {
+    let sizes: Vec<(u64, u64)> = ctx
+        .cache
+        .guilds()
+        .into_iter()
+        .filter_map(|id| {
+            ctx.cache
+                .guild(id)
+                .map(|guild| (id.get(), guild.member_count))
+        })
+        .collect();
+    let total = sizes.len();
+    let selected = select_guilds(sizes, *MAX_GUILD_SERIES);
+    UNTRACKED_GUILD_COUNT.set((total - selected.len()) as i64);
+
+    let mut series = GUILD_SERIES.lock().unwrap_or_else(PoisonError::into_inner);
+    let stale: Vec<u64> = series
+        .keys()
+        .filter(|id| !selected.contains(id))
+        .copied()
+        .collect();
+    for id in stale {
+        remove_series(&mut series, id);
+    }
+    for id in selected {
+        if let Some(guild) = ctx.cache.guild(GuildId::new(id)) {
+            record_guild(&mut series, id, &guild.name, &guild_values(&guild));
+        }
+    }
+}
+
+/// Drop every series of a guild the bot has left.
+pub fn remove_guild_metrics(guild_id: GuildId) {
+    let mut series = GUILD_SERIES.lock().unwrap_or_else(PoisonError::into_inner);
+    remove_series(&mut series, guild_id.get());
+}
+
+fn remove_series(series: &mut HashMap<u64, String>, id: u64) {
+    let label = id.to_string();
+    for gauge in guild_gauges() {
+        let _ = gauge.remove_label_values(&[&label]);
+    }
+    if let Some(name) = series.remove(&id) {
+        let _ = GUILD_INFO.remove_label_values(&[&label, &name]);
+    }
+}
+
+fn record_guild(
+    series: &mut HashMap<u64, String>,
+    id: u64,
+    name: &str,
+    values: &[(&'static IntGaugeVec, i64)],
+) {
+    let label = id.to_string();
+    for (gauge, value) in values {
+        gauge.with_label_values(&[&label]).set(*value);
+    }
+    if let Some(old) = series.insert(id, name.to_string()) {
+        if old != name {
+            let _ = GUILD_INFO.remove_label_values(&[&label, &old]);
+        }
+    }
+    GUILD_INFO.with_label_values(&[&label, name]).set(1);
+}
+
+/// The value of each per-guild gauge for `guild`.
+fn guild_values(guild: &Guild) -> Vec<(&'static IntGaugeVec, i64)> {
+    let channels_of = |kind: ChannelType| {
+        guild
+            .channels
+            .values()
+            .filter(|channel| channel.kind == kind)
+            .count() as i64
+    };
+    let bots = guild
+        .members
+        .values()
+        .filter(|member| member.user.bot)
+        .count() as i64;
+    let online = guild
+        .presences
+        .values()
+        .filter(|presence| presence.status == OnlineStatus::Online)
+        .count() as i64;
+    let afk_timeout = guild
+        .afk_metadata
+        .as_ref()
+        .map_or(0, |afk| i64::from(u16::from(afk.afk_timeout)));
+    vec![
+        (&*GUILD_MEMBER_COUNT, guild.member_count as i64),
+        (&*GUILD_CHANNEL_COUNT, guild.channels.len() as i64),
+        (&*GUILD_ROLE_COUNT, guild.roles.len() as i64),
+        (&*GUILD_ONLINE_COUNT, online),
+        (
+            &*GUILD_CREATION_TIME,
+            guild.id.created_at().unix_timestamp(),
+        ),
+        (&*GUILD_HUMAN_COUNT, guild.members.len() as i64 - bots),
+        (&*GUILD_BOT_COUNT, bots),
+        (&*GUILD_TEXT_CHANNEL_COUNT, channels_of(ChannelType::Text)),
+        (&*GUILD_VOICE_CHANNEL_COUNT, channels_of(ChannelType::Voice)),
+        (
+            &*GUILD_CATEGORY_CHANNEL_COUNT,
+            channels_of(ChannelType::Category),
+        ),
+        (&*GUILD_EMOJI_COUNT, guild.emojis.len() as i64),
+        (&*GUILD_STICKER_COUNT, guild.stickers.len() as i64),
+        (
+            &*GUILD_BOOST_COUNT,
+            guild.premium_subscription_count.unwrap_or(0) as i64,
+        ),
+        (
+            &*GUILD_PREMIUM_TIER,
+            i64::from(u8::from(guild.premium_tier)),
+        ),
+        (&*GUILD_OWNER_ID, guild.owner_id.get() as i64),
+        (&*GUILD_AFK_TIMEOUT, afk_timeout),
+    ]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
 
     #[test]
     fn test_metrics_registration() {
-        assert!(register_metrics().is_ok());
+        assert!(register_metrics(&Registry::new()).is_ok());
     }
 
     #[test]
@@ -257,12 +737,23 @@ mod tests {
         COMMAND_REQUESTS.with_label_values(&["test"]).inc();
         assert_eq!(COMMAND_REQUESTS.with_label_values(&["test"]).get(), 1);
 
-        COMMAND_DURATION.with_label_values(&["test"]).observe(0.5);
-        let duration = COMMAND_DURATION.with_label_values(&["test"]).get_sample_sum();
+        COMMAND_DURATION
+            .with_label_values(&["test", "ok"])
+            .observe(0.5);
+        let duration = COMMAND_DURATION
+            .with_label_values(&["test", "ok"])
+            .get_sample_sum();
         assert!(duration > 0.4 && duration < 0.6);
 
-        COMMAND_ERRORS.with_label_values(&["test"]).inc();
-        assert_eq!(COMMAND_ERRORS.with_label_values(&["test"]).get(), 1);
+        COMMAND_ERRORS
+            .with_label_values(&["test", "internal_error"])
+            .inc();
+        assert_eq!(
+            COMMAND_ERRORS
+                .with_label_values(&["test", "internal_error"])
+                .get(),
+            1
+        );
     }
 
     #[test]
@@ -270,15 +761,19 @@ mod tests {
         HTTP_REQUESTS.with_label_values(&["/test", "GET"]).inc();
         assert_eq!(HTTP_REQUESTS.with_label_values(&["/test", "GET"]).get(), 1);
 
-        HTTP_DURATION.with_label_values(&["/test", "GET"]).observe(0.5);
-        let duration = HTTP_DURATION.with_label_values(&["/test", "GET"]).get_sample_sum();
+        HTTP_DURATION
+            .with_label_values(&["/test", "GET"])
+            .observe(0.5);
+        let duration = HTTP_DURATION
+            .with_label_values(&["/test", "GET"])
+            .get_sample_sum();
         assert!(duration > 0.4 && duration < 0.6);
     }
 
     #[test]
     fn test_system_metrics() {
-        MEMORY_USAGE.set(1024.0);
-        assert_eq!(MEMORY_USAGE.get(), 1024.0);
+        MEMORY_USAGE.set(1024);
+        assert_eq!(MEMORY_USAGE.get(), 1024);
 
         CPU_USAGE.set(50.0);
         assert_eq!(CPU_USAGE.get(), 50.0);
@@ -298,8 +793,181 @@ mod tests {
         INTERACTION_ERRORS.with_label_values(&["test"]).inc();
         assert_eq!(INTERACTION_ERRORS.with_label_values(&["test"]).get(), 1);
 
-        INTERACTION_DURATION.with_label_values(&["test"]).observe(0.5);
-        let duration = INTERACTION_DURATION.with_label_values(&["test"]).get_sample_sum();
+        INTERACTION_DURATION
+            .with_label_values(&["test"])
+            .observe(0.5);
+        let duration = INTERACTION_DURATION
+            .with_label_values(&["test"])
+            .get_sample_sum();
         assert!(duration > 0.4 && duration < 0.6);
     }
+
+    #[test]
+    fn test_record_helpers() {
+        record_http_request("GET", "/helpers");
+        assert_eq!(
+            HTTP_REQUESTS.with_label_values(&["/helpers", "GET"]).get(),
+            1
+        );
+
+        record_command_execution("test_command");
+        assert_eq!(
+            COMMAND_REQUESTS.with_label_values(&["test_command"]).get(),
+            1
+        );
+
+        record_command_outcome(
+            "test_command",
+            Outcome::Ok,
+            Some(Duration::from_millis(1500)),
+        );
+        let duration = COMMAND_DURATION
+            .with_label_values(&["test_command", "ok"])
+            .get_sample_sum();
+        assert!(duration > 1.4 && duration < 1.6);
+        assert_eq!(
+            COMMAND_ERRORS
+                .with_label_values(&["test_command", "ok"])
+                .get(),
+            0
+        );
+
+        record_command_outcome("test_command", Outcome::Timeout, None);
+        assert_eq!(
+            COMMAND_ERRORS
+                .with_label_values(&["test_command", "timeout"])
+                .get(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_track_http() {
+        use axum::body::Body;
+        use axum::routing::get;
+        use tower::ServiceExt;
+
+        let app = axum::Router::new()
+            .route("/tracked/:id", get(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn(track_http));
+        for uri in ["/tracked/1", "/tracked/2", "/untracked"] {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            HTTP_REQUESTS
+                .with_label_values(&["/tracked/:id", "GET"])
+                .get(),
+            2
+        );
+        assert_eq!(
+            HTTP_DURATION
+                .with_label_values(&["/tracked/:id", "GET"])
+                .get_sample_count(),
+            2
+        );
+        assert_eq!(
+            HTTP_REQUESTS
+                .with_label_values(&["/untracked", "GET"])
+                .get(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_outcome_of_error() {
+        let rate_limited: crate::Error = Box::new(MarketError::RateLimited);
+        assert_eq!(
+            Outcome::of_error(rate_limited.as_ref()),
+            Outcome::RateLimited
+        );
+        let unknown: crate::Error = Box::new(MarketError::UnknownSymbol("XYZ".to_string()));
+        assert_eq!(Outcome::of_error(unknown.as_ref()), Outcome::UserError);
+        let busy: crate::Error = Box::new(AdviceError::Status(429));
+        assert_eq!(Outcome::of_error(busy.as_ref()), Outcome::RateLimited);
+        let other: crate::Error = "database on fire".into();
+        assert_eq!(Outcome::of_error(other.as_ref()), Outcome::InternalError);
+    }
+
+    #[tokio::test]
+    async fn test_outcome_of_timeout() {
+        let elapsed = tokio::time::timeout(Duration::from_millis(1), std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        let error: crate::Error = Box::new(elapsed);
+        assert_eq!(Outcome::of_error(error.as_ref()), Outcome::Timeout);
+    }
+
+    #[test]
+    fn test_parse_buckets() {
+        assert_eq!(parse_buckets("0.1, 0.5,1,5"), Ok(vec![0.1, 0.5, 1.0, 5.0]));
+        assert!(parse_buckets("").is_err());
+        assert!(parse_buckets("1,0.5").is_err());
+        assert!(parse_buckets("0,1").is_err());
+        assert!(parse_buckets("fast").is_err());
+        let defaults: Vec<String> = DEFAULT_COMMAND_BUCKETS.iter().map(f64::to_string).collect();
+        assert_eq!(
+            parse_buckets(&defaults.join(",")).as_deref(),
+            Ok(DEFAULT_COMMAND_BUCKETS)
+        );
+    }
+
+    #[test]
+    fn test_command_timers_are_per_invocation() {
+        let timers = CommandTimers::new();
+        timers.start(1);
+        timers.start(2);
+        assert_eq!(timers.len(), 2);
+        assert!(timers.finish(1).is_some());
+        assert!(timers.finish(1).is_none());
+        assert!(timers.finish(2).is_some());
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn test_exposition_uses_one_namespace() {
+        record_command_execution("exposition");
+        let text = prometheus_metrics();
+        assert!(text.contains("bot_command_requests_total{command=\"exposition\"} 1"));
+        // Metrics nobody has touched yet are still exposed.
+        assert!(text.contains("# TYPE bot_untracked_guild_count gauge"));
+        let names = text
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.is_empty());
+        for line in names {
+            assert!(line.starts_with("bot_"), "{}", line);
+        }
+        assert!(CONTENT_TYPE.starts_with("text/plain; version=0.0.4"));
+    }
+
+    #[test]
+    fn test_select_guilds() {
+        let sizes = vec![(1, 10), (2, 500), (3, 10), (4, 80)];
+        assert_eq!(select_guilds(sizes.clone(), 3), vec![2, 4, 1]);
+        assert_eq!(select_guilds(sizes.clone(), 10), vec![2, 4, 1, 3]);
+        assert!(select_guilds(sizes, 0).is_empty());
+    }
+
+    #[test]
+    fn test_guild_series_are_labelled_and_removed() {
+        let mut series = HashMap::new();
+        record_guild(&mut series, 42, "Old name", &[(&*GUILD_ROLE_COUNT, 7)]);
+        record_guild(&mut series, 43, "Other", &[(&*GUILD_ROLE_COUNT, 3)]);
+        assert_eq!(GUILD_ROLE_COUNT.with_label_values(&["42"]).get(), 7);
+        assert_eq!(GUILD_ROLE_COUNT.with_label_values(&["43"]).get(), 3);
+
+        // A rename replaces the info series rather than adding one.
+        record_guild(&mut series, 42, "New name", &[(&*GUILD_ROLE_COUNT, 8)]);
+        let text = prometheus_metrics();
+        assert!(text.contains("bot_guild_info{guild_id=\"42\",name=\"New name\"} 1"));
+        assert!(!text.contains("Old name"));
+
+        remove_series(&mut series, 42);
+        let text = prometheus_metrics();
+        assert!(!text.contains("guild_id=\"42\""));
+        assert!(text.contains("bot_guild_role_count{guild_id=\"43\"} 3"));
+        assert_eq!(series.len(), 1);
+    }
 }
@@ -1,7 +1,7 @@
 use crate::schema::{
     command_history, command_logs, command_stats, descriptions, interaction_logs, interaction_stats, rate_limits,
 };
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, NaiveTime};
 use diesel::prelude::*;
 use diesel::{AsChangeset, Insertable, Queryable};
 use serde::{Deserialize, Serialize};
@@ -149,6 +149,163 @@ pub struct Description {
     pub guild_id: i64,
     pub user_id: i64,
     pub timestamp: NaiveDateTime,
+    pub reads: i64,
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::descriptions)]
+pub struct NewDescription<'a> {
+    pub key: &'a str,
+    pub value: &'a str,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub timestamp: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::description_revisions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DescriptionRevision {
+    pub id: i64,
+    pub guild_id: i64,
+    pub key: String,
+    pub rev: i32,
+    pub value: String,
+    pub user_id: i64,
+    pub timestamp: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::description_revisions)]
+pub struct NewDescriptionRevision<'a> {
+    pub guild_id: i64,
+    pub key: &'a str,
+    pub rev: i32,
+    pub value: &'a str,
+    pub user_id: i64,
+    pub timestamp: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::factoid_settings)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct FactoidSettings {
+    pub guild_id: i64,
+    pub enabled: bool,
+    pub cooldown_seconds: i32,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::factoid_channels)]
+pub struct NewFactoidChannel {
+    pub guild_id: i64,
+    pub channel_id: i64,
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::response_pools)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ResponsePoolItem {
+    pub id: i64,
+    pub guild_id: i64,
+    pub pool: String,
+    pub item: String,
+    pub user_id: i64,
+    pub created_at: NaiveDateTime,
+    pub weight: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::response_pools)]
+pub struct NewResponsePoolItem<'a> {
+    pub guild_id: i64,
+    pub pool: &'a str,
+    pub item: &'a str,
+    pub user_id: i64,
+    pub created_at: NaiveDateTime,
+    pub weight: i32,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::price_alerts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PriceAlert {
+    pub id: i64,
+    pub user_id: i64,
+    pub channel_id: Option<i64>,
+    pub symbol: String,
+    pub direction: String,
+    pub threshold: f64,
+    pub created_at: NaiveDateTime,
+    pub triggered_at: Option<NaiveDateTime>,
+    pub triggered_price: Option<f64>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::price_alerts)]
+pub struct NewPriceAlert<'a> {
+    pub user_id: i64,
+    pub channel_id: Option<i64>,
+    pub symbol: &'a str,
+    pub direction: &'a str,
+    pub threshold: f64,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::paper_positions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PaperPosition {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub symbol: String,
+    pub shares: i64,
+    pub cost_cents: i64,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::paper_trades)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct PaperTrade {
+    pub id: i64,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub symbol: String,
+    pub side: String,
+    pub shares: i64,
+    pub price_cents: i64,
+    pub executed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::paper_trades)]
+pub struct NewPaperTrade<'a> {
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub symbol: &'a str,
+    pub side: &'a str,
+    pub shares: i64,
+    pub price_cents: i64,
+    pub executed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::scheduled_posts)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ScheduledPost {
+    pub id: i64,
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub kind: String,
+    pub post_time: NaiveTime,
+    pub timezone: String,
+    pub weekdays: i16,
+    pub skip_holidays: bool,
+    pub symbols: String,
+    pub created_by: i64,
+    pub updated_at: NaiveDateTime,
+    pub last_posted_at: Option<NaiveDateTime>,
 }
 
 #[cfg(test)]
@@ -198,6 +355,7 @@ mod tests {
             guild_id: 123,
             user_id: 456,
             timestamp: now,
+            reads: 0,
         };
         assert_eq!(desc.key, "test_key");
         assert_eq!(desc.value, "test_value");
@@ -205,6 +363,24 @@ mod tests {
         assert_eq!(desc.user_id, 456);
     }
 
+    #[test]
+    fn test_description_revision_struct() {
+        let now = Utc::now().naive_utc();
+        let revision = DescriptionRevision {
+            id: 1,
+            guild_id: 123,
+            key: "test_key".to_string(),
+            rev: 2,
+            value: "old_value".to_string(),
+            user_id: 456,
+            timestamp: now,
+        };
+        assert_eq!(revision.key, "test_key");
+        assert_eq!(revision.rev, 2);
+        assert_eq!(revision.value, "old_value");
+        assert_eq!(revision.user_id, 456);
+    }
+
     #[test]
     fn test_interaction_log_struct() {
         let now = Utc::now().naive_utc();
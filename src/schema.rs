@@ -1,6 +1,14 @@
 use diesel::table;
 use diesel::allow_tables_to_appear_in_same_query;
 
+table! {
+    ball_settings (guild_id) {
+        guild_id -> Int8,
+        consistent -> Bool,
+        updated_at -> Timestamp,
+    }
+}
+
 table! {
     descriptions (id) {
         id -> Int8,
@@ -9,6 +17,136 @@ table! {
         guild_id -> Int8,
         user_id -> Int8,
         timestamp -> Timestamp,
+        reads -> Int8,
+    }
+}
+
+table! {
+    description_revisions (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        key -> Varchar,
+        rev -> Int4,
+        value -> Varchar,
+        user_id -> Int8,
+        timestamp -> Timestamp,
+    }
+}
+
+table! {
+    factoid_settings (guild_id) {
+        guild_id -> Int8,
+        enabled -> Bool,
+        cooldown_seconds -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    factoid_channels (guild_id, channel_id) {
+        guild_id -> Int8,
+        channel_id -> Int8,
+    }
+}
+
+table! {
+    paper_accounts (guild_id, user_id) {
+        guild_id -> Int8,
+        user_id -> Int8,
+        cash_cents -> Int8,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    paper_positions (guild_id, user_id, symbol) {
+        guild_id -> Int8,
+        user_id -> Int8,
+        symbol -> Varchar,
+        shares -> Int8,
+        cost_cents -> Int8,
+    }
+}
+
+table! {
+    paper_trades (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        user_id -> Int8,
+        symbol -> Varchar,
+        side -> Varchar,
+        shares -> Int8,
+        price_cents -> Int8,
+        executed_at -> Timestamp,
+    }
+}
+
+table! {
+    price_alerts (id) {
+        id -> Int8,
+        user_id -> Int8,
+        channel_id -> Nullable<Int8>,
+        symbol -> Varchar,
+        direction -> Varchar,
+        threshold -> Float8,
+        created_at -> Timestamp,
+        triggered_at -> Nullable<Timestamp>,
+        triggered_price -> Nullable<Float8>,
+    }
+}
+
+table! {
+    response_pools (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        pool -> Varchar,
+        item -> Varchar,
+        user_id -> Int8,
+        created_at -> Timestamp,
+        weight -> Int4,
+    }
+}
+
+table! {
+    response_pool_settings (guild_id, pool) {
+        guild_id -> Int8,
+        pool -> Varchar,
+        replace_defaults -> Bool,
+        selection -> Varchar,
+    }
+}
+
+table! {
+    scheduled_posts (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        channel_id -> Int8,
+        kind -> Varchar,
+        post_time -> Time,
+        timezone -> Varchar,
+        weekdays -> Int2,
+        skip_holidays -> Bool,
+        symbols -> Text,
+        created_by -> Int8,
+        updated_at -> Timestamp,
+        last_posted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    shuffle_bags (guild_id, pool) {
+        guild_id -> Int8,
+        pool -> Varchar,
+        remaining -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    watchlists (user_id, symbol) {
+        user_id -> Int8,
+        symbol -> Varchar,
+        added_at -> Timestamp,
     }
 }
 
@@ -76,10 +214,23 @@ diesel::joinable!(command_history -> descriptions (guild_id));
 diesel::joinable!(interaction_logs -> descriptions (guild_id));
 
 allow_tables_to_appear_in_same_query!(
+    ball_settings,
     command_history,
     command_stats,
+    description_revisions,
     descriptions,
+    factoid_channels,
+    factoid_settings,
     interaction_logs,
     interaction_stats,
+    paper_accounts,
+    paper_positions,
+    paper_trades,
+    price_alerts,
     rate_limits,
+    response_pool_settings,
+    response_pools,
+    scheduled_posts,
+    shuffle_bags,
+    watchlists,
 );
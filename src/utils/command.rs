@@ -1,6 +1,6 @@
 use crate::metrics::{
-    COMMAND_DURATION, COMMAND_ERRORS, COMMAND_REQUESTS,
-    HTTP_DURATION, HTTP_REQUESTS,
+    record_command_outcome, Outcome, COMMAND_DURATION, COMMAND_REQUESTS, HTTP_DURATION,
+    HTTP_REQUESTS,
 };
 use crate::Error;
 use poise::Context;
@@ -18,7 +18,7 @@ pub async fn log_command_usage(
     args: &str,
 ) -> Result<(), Error> {
     // Increment command counter
-    command::COUNTER.with_label_values(&[command_name]).inc();
+    COMMAND_REQUESTS.with_label_values(&[command_name]).inc();
 
     // Log command usage to database
     if let Some(guild_id) = ctx.guild_id() {
@@ -37,26 +37,24 @@ pub async fn log_command_usage(
 
 /// Log command failure to metrics
 pub fn log_command_failure(command_name: &str) {
-    command::FAILURES.with_label_values(&[command_name]).inc();
+    record_command_outcome(command_name, Outcome::InternalError, None);
 }
 
 /// Start a command duration timer
 pub fn start_command_timer(command_name: &str) -> prometheus::HistogramTimer {
-    command::DURATION
-        .with_label_values(&[command_name])
+    COMMAND_DURATION
+        .with_label_values(&[command_name, Outcome::Ok.label()])
         .start_timer()
 }
 
 /// Log HTTP request to metrics
 pub fn log_http_request(endpoint: &str, method: &str) {
-    crate::metrics::http::REQUESTS
-        .with_label_values(&[endpoint, method])
-        .inc();
+    HTTP_REQUESTS.with_label_values(&[endpoint, method]).inc();
 }
 
 /// Start an HTTP request duration timer
 pub fn start_http_timer(endpoint: &str, method: &str) -> prometheus::HistogramTimer {
-    crate::metrics::http::DURATION
+    HTTP_DURATION
         .with_label_values(&[endpoint, method])
         .start_timer()
 }
@@ -0,0 +1,495 @@
+//! Dice expressions such as `3d6+2`, `d20`, `4d6kh3` or `2d10-1d4`.
+//!
+//! An expression is a sum of terms, each either a flat modifier or a group of
+//! `NdS` dice. `d%` is a hundred-sided die, and a group may keep only its
+//! highest (`khK`) or lowest (`klK`) K dice.
+
+use rand::Rng;
+use std::fmt;
+
+/// Most dice a single expression may roll.
+pub const MAX_DICE: u32 = 100;
+/// Most sides a single die may have.
+pub const MAX_SIDES: u32 = 1000;
+/// Most terms an expression may contain.
+pub const MAX_TERMS: usize = 20;
+/// Largest flat modifier accepted.
+pub const MAX_MODIFIER: i64 = 1_000_000;
+
+/// Error returned when a dice expression can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiceError {
+    Empty,
+    UnexpectedChar(char, usize),
+    UnexpectedEnd,
+    NumberTooLarge,
+    NoDice,
+    TooManyDice,
+    InvalidSides(u32),
+    InvalidKeep(u32),
+    TooManyTerms,
+}
+
+impl fmt::Display for DiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiceError::Empty => write!(f, "Give me something to roll, like 3d6+2"),
+            DiceError::UnexpectedChar(c, pos) => {
+                write!(f, "Unexpected '{}' at position {}", c, pos)
+            }
+            DiceError::UnexpectedEnd => write!(f, "The expression ends too early"),
+            DiceError::NumberTooLarge => write!(f, "That number is too large"),
+            DiceError::NoDice => write!(f, "Roll at least one die"),
+            DiceError::TooManyDice => write!(f, "At most {} dice can be rolled at once", MAX_DICE),
+            DiceError::InvalidSides(sides) => write!(
+                f,
+                "A die needs between 1 and {} sides, not {}",
+                MAX_SIDES, sides
+            ),
+            DiceError::InvalidKeep(keep) => {
+                write!(f, "Can't keep {} dice from that group", keep)
+            }
+            DiceError::TooManyTerms => write!(f, "At most {} terms are allowed", MAX_TERMS),
+        }
+    }
+}
+
+impl std::error::Error for DiceError {}
+
+/// Which dice of a group count towards the total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Highest(u32),
+    Lowest(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Dice {
+        count: u32,
+        sides: u32,
+        keep: Option<Keep>,
+    },
+    Modifier(i64),
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Dice { count, sides, keep } => {
+                write!(f, "{}d{}", count, sides)?;
+                match keep {
+                    Some(Keep::Highest(n)) => write!(f, "kh{}", n),
+                    Some(Keep::Lowest(n)) => write!(f, "kl{}", n),
+                    None => Ok(()),
+                }
+            }
+            Term::Modifier(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A parsed dice expression: signed terms that are summed together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiceExpr {
+    terms: Vec<(bool, Term)>,
+}
+
+/// The dice rolled for one term and what they contributed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermRoll {
+    pub negative: bool,
+    pub term: Term,
+    /// Each die rolled, and whether it counted towards the total.
+    pub rolls: Vec<(u32, bool)>,
+    pub value: i64,
+}
+
+/// The outcome of rolling a [`DiceExpr`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiceRoll {
+    pub terms: Vec<TermRoll>,
+    pub total: i64,
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some((_, c)) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn peek(&mut self) -> Option<(usize, char)> {
+        self.skip_whitespace();
+        self.chars
+            .peek()
+            .map(|(pos, c)| (*pos, c.to_ascii_lowercase()))
+    }
+
+    fn number(&mut self) -> Result<Option<u64>, DiceError> {
+        self.skip_whitespace();
+        let mut value: Option<u64> = None;
+        while let Some(&(_, c)) = self.chars.peek() {
+            let Some(digit) = c.to_digit(10) else {
+                break;
+            };
+            self.chars.next();
+            value = Some(
+                value
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add(u64::from(digit)))
+                    .ok_or(DiceError::NumberTooLarge)?,
+            );
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<Term, DiceError> {
+        let count = self.number()?;
+        match self.peek() {
+            Some((_, 'd')) => {
+                self.chars.next();
+            }
+            _ => {
+                let value = count.ok_or_else(|| self.unexpected())?;
+                if value > MAX_MODIFIER as u64 {
+                    return Err(DiceError::NumberTooLarge);
+                }
+                return Ok(Term::Modifier(value as i64));
+            }
+        }
+        let count = u32::try_from(count.unwrap_or(1)).map_err(|_| DiceError::TooManyDice)?;
+        if count == 0 {
+            return Err(DiceError::NoDice);
+        }
+        if count > MAX_DICE {
+            return Err(DiceError::TooManyDice);
+        }
+        let sides = if let Some((_, '%')) = self.peek() {
+            self.chars.next();
+            100
+        } else {
+            let sides = self.number()?.ok_or_else(|| self.unexpected())?;
+            u32::try_from(sides).unwrap_or(u32::MAX)
+        };
+        if sides == 0 || sides > MAX_SIDES {
+            return Err(DiceError::InvalidSides(sides));
+        }
+        let keep = match self.peek() {
+            Some((_, 'k')) => {
+                self.chars.next();
+                let highest = match self.peek() {
+                    Some((_, 'h')) => true,
+                    Some((_, 'l')) => false,
+                    _ => return Err(self.unexpected()),
+                };
+                self.chars.next();
+                let n = self.number()?.ok_or_else(|| self.unexpected())?;
+                let n = u32::try_from(n).unwrap_or(u32::MAX);
+                if n == 0 || n > count {
+                    return Err(DiceError::InvalidKeep(n));
+                }
+                Some(if highest {
+                    Keep::Highest(n)
+                } else {
+                    Keep::Lowest(n)
+                })
+            }
+            _ => None,
+        };
+        Ok(Term::Dice { count, sides, keep })
+    }
+
+    fn unexpected(&mut self) -> DiceError {
+        match self.chars.peek() {
+            Some(&(pos, c)) => DiceError::UnexpectedChar(c, pos),
+            None => DiceError::UnexpectedEnd,
+        }
+    }
+}
+
+impl DiceExpr {
+    /// Parse an expression such as `3d6+2`. Whitespace and case are ignored.
+    pub fn parse(source: &str) -> Result<Self, DiceError> {
+        if source.trim().is_empty() {
+            return Err(DiceError::Empty);
+        }
+        let mut parser = Parser {
+            chars: source.char_indices().peekable(),
+        };
+        let mut terms = Vec::new();
+        let mut negative = match parser.peek() {
+            Some((_, '-')) => {
+                parser.chars.next();
+                true
+            }
+            Some((_, '+')) => {
+                parser.chars.next();
+                false
+            }
+            _ => false,
+        };
+        loop {
+            terms.push((negative, parser.term()?));
+            if terms.len() > MAX_TERMS {
+                return Err(DiceError::TooManyTerms);
+            }
+            negative = match parser.peek() {
+                Some((_, '+')) => false,
+                Some((_, '-')) => true,
+                Some(_) => return Err(parser.unexpected()),
+                None => break,
+            };
+            parser.chars.next();
+        }
+        let dice: u32 = terms
+            .iter()
+            .map(|(_, term)| match term {
+                Term::Dice { count, .. } => *count,
+                Term::Modifier(_) => 0,
+            })
+            .sum();
+        if dice > MAX_DICE {
+            return Err(DiceError::TooManyDice);
+        }
+        Ok(Self { terms })
+    }
+
+    /// Roll every die in the expression.
+    pub fn roll<R: Rng + ?Sized>(&self, rng: &mut R) -> DiceRoll {
+        let terms: Vec<TermRoll> = self
+            .terms
+            .iter()
+            .map(|&(negative, term)| roll_term(negative, term, rng))
+            .collect();
+        let total = terms
+            .iter()
+            .map(|t| if t.negative { -t.value } else { t.value })
+            .sum();
+        DiceRoll { terms, total }
+    }
+}
+
+impl fmt::Display for DiceExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (negative, term)) in self.terms.iter().enumerate() {
+            match (i, negative) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            write!(f, "{}", term)?;
+        }
+        Ok(())
+    }
+}
+
+fn roll_term<R: Rng + ?Sized>(negative: bool, term: Term, rng: &mut R) -> TermRoll {
+    let (count, sides, keep) = match term {
+        Term::Modifier(value) => {
+            return TermRoll {
+                negative,
+                term,
+                rolls: Vec::new(),
+                value,
+            }
+        }
+        Term::Dice { count, sides, keep } => (count, sides, keep),
+    };
+    let values: Vec<u32> = (0..count).map(|_| rng.gen_range(1..=sides)).collect();
+    let mut kept = vec![true; values.len()];
+    if let Some(keep) = keep {
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        let n = match keep {
+            Keep::Highest(n) => {
+                order.sort_by_key(|&i| std::cmp::Reverse(values[i]));
+                n
+            }
+            Keep::Lowest(n) => {
+                order.sort_by_key(|&i| values[i]);
+                n
+            }
+        };
+        for &i in &order[n as usize..] {
+            kept[i] = false;
+        }
+    }
+    let value = values
+        .iter()
+        .zip(&kept)
+        .filter(|(_, kept)| **kept)
+        .map(|(v, _)| i64::from(*v))
+        .sum();
+    TermRoll {
+        negative,
+        term,
+        rolls: values.into_iter().zip(kept).collect(),
+        value,
+    }
+}
+
+impl DiceRoll {
+    /// Show each term's dice, with dropped dice struck through, e.g.
+    /// `[4, 2, 6] + 2 = 14`.
+    pub fn breakdown(&self) -> String {
+        let mut out = String::new();
+        for (i, term) in self.terms.iter().enumerate() {
+            match (i, term.negative) {
+                (0, true) => out.push('-'),
+                (0, false) => {}
+                (_, true) => out.push_str(" - "),
+                (_, false) => out.push_str(" + "),
+            }
+            if term.rolls.is_empty() {
+                out.push_str(&term.value.to_string());
+                continue;
+            }
+            let dice: Vec<String> = term
+                .rolls
+                .iter()
+                .map(|(value, kept)| {
+                    if *kept {
+                        value.to_string()
+                    } else {
+                        format!("~~{}~~", value)
+                    }
+                })
+                .collect();
+            out.push_str(&format!("[{}]", dice.join(", ")));
+        }
+        out.push_str(&format!(" = {}", self.total));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn dice(count: u32, sides: u32) -> Term {
+        Term::Dice {
+            count,
+            sides,
+            keep: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_simple() {
+        let expr = DiceExpr::parse("3d6+2").unwrap();
+        assert_eq!(
+            expr.terms,
+            vec![(false, dice(3, 6)), (false, Term::Modifier(2))]
+        );
+        assert_eq!(expr.to_string(), "3d6 + 2");
+    }
+
+    #[test]
+    fn test_parse_variants() {
+        assert_eq!(
+            DiceExpr::parse("d20").unwrap().terms,
+            vec![(false, dice(1, 20))]
+        );
+        assert_eq!(
+            DiceExpr::parse("D%").unwrap().terms,
+            vec![(false, dice(1, 100))]
+        );
+        assert_eq!(
+            DiceExpr::parse(" 2d10 - 1d4 ").unwrap().terms,
+            vec![(false, dice(2, 10)), (true, dice(1, 4))]
+        );
+        assert_eq!(
+            DiceExpr::parse("4d6kh3").unwrap().terms,
+            vec![(
+                false,
+                Term::Dice {
+                    count: 4,
+                    sides: 6,
+                    keep: Some(Keep::Highest(3))
+                }
+            )]
+        );
+        assert_eq!(
+            DiceExpr::parse("-1+d4").unwrap().terms,
+            vec![(true, Term::Modifier(1)), (false, dice(1, 4))]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(DiceExpr::parse(""), Err(DiceError::Empty));
+        assert_eq!(DiceExpr::parse("3d"), Err(DiceError::UnexpectedEnd));
+        assert_eq!(
+            DiceExpr::parse("3x6"),
+            Err(DiceError::UnexpectedChar('x', 1))
+        );
+        assert_eq!(DiceExpr::parse("3d6+"), Err(DiceError::UnexpectedEnd));
+        assert_eq!(DiceExpr::parse("0d6"), Err(DiceError::NoDice));
+        assert_eq!(DiceExpr::parse("3d0"), Err(DiceError::InvalidSides(0)));
+        assert_eq!(DiceExpr::parse("101d6"), Err(DiceError::TooManyDice));
+        assert_eq!(DiceExpr::parse("60d6+60d6"), Err(DiceError::TooManyDice));
+        assert_eq!(DiceExpr::parse("2d6kh3"), Err(DiceError::InvalidKeep(3)));
+        assert_eq!(
+            DiceExpr::parse("99999999999999999999"),
+            Err(DiceError::NumberTooLarge)
+        );
+    }
+
+    #[test]
+    fn test_roll_within_bounds() {
+        let expr = DiceExpr::parse("3d6+2").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let roll = expr.roll(&mut rng);
+            assert!((5..=20).contains(&roll.total));
+            assert_eq!(roll.terms[0].rolls.len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_keep_highest() {
+        let expr = DiceExpr::parse("4d6kh3").unwrap();
+        let mut rng = StdRng::seed_from_u64(9);
+        let roll = expr.roll(&mut rng);
+        let rolls = &roll.terms[0].rolls;
+        assert_eq!(rolls.iter().filter(|(_, kept)| *kept).count(), 3);
+        let dropped = rolls.iter().find(|(_, kept)| !kept).unwrap().0;
+        assert!(rolls.iter().all(|(value, _)| *value >= dropped));
+        let kept_sum: i64 = rolls
+            .iter()
+            .filter(|(_, kept)| *kept)
+            .map(|(v, _)| i64::from(*v))
+            .sum();
+        assert_eq!(roll.total, kept_sum);
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let roll = DiceRoll {
+            terms: vec![
+                TermRoll {
+                    negative: false,
+                    term: dice(3, 6),
+                    rolls: vec![(4, true), (2, false), (6, true)],
+                    value: 10,
+                },
+                TermRoll {
+                    negative: true,
+                    term: Term::Modifier(1),
+                    rolls: Vec::new(),
+                    value: 1,
+                },
+            ],
+            total: 9,
+        };
+        assert_eq!(roll.breakdown(), "[4, ~~2~~, 6] - 1 = 9");
+    }
+}
@@ -1,6 +1,6 @@
 pub mod command;
+pub mod dice;
 pub mod guild;
-pub mod metrics;
 pub mod random;
 pub mod system;
 pub mod time;
@@ -210,6 +210,12 @@ mod tests {
     use std::sync::Arc;
     use tokio::sync::RwLock;
     use crate::interactions::InteractionTracker;
+    use crate::logging::{LogLevel, DEFAULT_FILTER};
+    use crate::metrics::CommandTimers;
+    use crate::commands::advice::client::{AdviceCache, AdviceConfig, HttpAdviceClient};
+    use crate::commands::stonks::market::{FixtureProvider, MarketData};
+    use crate::commands::stonks::quote::QuoteCache;
+    use crate::commands::stonks::symbols::SymbolDirectory;
 
     fn create_test_pool() -> Pool<ConnectionManager<PgConnection>> {
         let database_url = "postgres://localhost/testbot_test";
@@ -230,11 +236,17 @@ mod tests {
 
         Data {
             db_pool: Arc::new(pool),
-            command_timers: HashMap::new(),
+            command_timers: CommandTimers::new(),
+            log_level: LogLevel::new(DEFAULT_FILTER).unwrap().1,
             guilds: Arc::new(HashMap::new()),
             users: Arc::new(HashMap::new()),
             channels: Arc::new(HashMap::new()),
             interaction_tracker: RwLock::new(InteractionTracker::new(Arc::new(create_test_pool()))),
+            advice_client: HttpAdviceClient::new(AdviceConfig::default()).unwrap(),
+            advice_cache: AdviceCache::new(),
+            market_data: MarketData::Fixture(FixtureProvider::new()),
+            quote_cache: QuoteCache::default(),
+            symbols: SymbolDirectory::bundled(),
         }
     }
 
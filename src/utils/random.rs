@@ -0,0 +1,430 @@
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Largest weight accepted in `item:weight` syntax.
+pub const MAX_WEIGHT: u32 = 100;
+
+/// Error returned when a list of choices can't be split.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitError {
+    UnclosedQuote(char),
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::UnclosedQuote(quote) => write!(f, "Missing closing {}", quote),
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+/// Split user input into choices. If the input contains a comma outside of
+/// quotes, it is split on commas; otherwise on whitespace. Either way,
+/// `"double"` or `'single'` quotes group text and `\` escapes the next
+/// character. Empty choices are dropped.
+pub fn split_choices(input: &str) -> Result<Vec<String>, SplitError> {
+    Ok(split_tokens(input)?
+        .into_iter()
+        .map(|(choice, _)| choice)
+        .collect())
+}
+
+/// Split user input like [`split_choices`], reading a `:N` weight off the
+/// end of each choice as [`parse_weighted`] does. Quoted or escaped text is
+/// never a weight, so `"Thai:3"` is one choice named `Thai:3`, while
+/// `"Thai food":3` is `Thai food` three times as likely.
+pub fn split_weighted_choices(input: &str) -> Result<Vec<(String, u32)>, SplitError> {
+    Ok(split_tokens(input)?
+        .into_iter()
+        .map(
+            |(mut choice, literal)| match weight_suffix(&choice[literal..]) {
+                Some((colon, weight)) if literal + colon > 0 => {
+                    choice.truncate(literal + colon);
+                    (choice, weight)
+                }
+                _ => (choice, 1),
+            },
+        )
+        .collect())
+}
+
+/// Choices, each with the length of its leading part that was quoted or
+/// escaped.
+fn split_tokens(input: &str) -> Result<Vec<(String, usize)>, SplitError> {
+    let comma_mode = has_unquoted_comma(input);
+    let mut choices = Vec::new();
+    let mut current = String::new();
+    let mut literal = 0;
+    let mut quoted = false;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(chars.next().unwrap_or('\\'));
+                literal = current.len();
+            }
+            '"' | '\'' => {
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some(close) if close == c => break,
+                        Some('\\') => current.push(chars.next().unwrap_or('\\')),
+                        Some(other) => current.push(other),
+                        None => return Err(SplitError::UnclosedQuote(c)),
+                    }
+                }
+                literal = current.len();
+            }
+            ',' if comma_mode => {
+                push_choice(&mut choices, &mut current, &mut literal, &mut quoted, true)
+            }
+            c if c.is_whitespace() && !comma_mode => {
+                push_choice(&mut choices, &mut current, &mut literal, &mut quoted, false)
+            }
+            c => current.push(c),
+        }
+    }
+    push_choice(
+        &mut choices,
+        &mut current,
+        &mut literal,
+        &mut quoted,
+        comma_mode,
+    );
+    Ok(choices)
+}
+
+fn push_choice(
+    choices: &mut Vec<(String, usize)>,
+    current: &mut String,
+    literal: &mut usize,
+    quoted: &mut bool,
+    trim: bool,
+) {
+    let choice = std::mem::take(current);
+    let mut literal = std::mem::take(literal);
+    let choice = if trim && !*quoted {
+        literal = literal.saturating_sub(choice.len() - choice.trim_start().len());
+        choice.trim().to_string()
+    } else {
+        choice
+    };
+    if !choice.is_empty() || *quoted {
+        let literal = literal.min(choice.len());
+        choices.push((choice, literal));
+    }
+    *quoted = false;
+}
+
+fn has_unquoted_comma(input: &str) -> bool {
+    let mut chars = input.chars();
+    let mut quote: Option<char> = None;
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                chars.next();
+            }
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (',', None) => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Split an `item:weight` token into its parts. Tokens without a valid weight
+/// suffix are returned whole with a weight of 1.
+pub fn parse_weighted(token: &str) -> (&str, u32) {
+    match weight_suffix(token) {
+        Some((colon, weight)) if colon > 0 => (&token[..colon], weight),
+        _ => (token, 1),
+    }
+}
+
+/// The weight in a valid `:N` suffix, and where its `:` is.
+fn weight_suffix(token: &str) -> Option<(usize, u32)> {
+    let (item, weight) = token.rsplit_once(':')?;
+    if weight.is_empty() || !weight.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let weight = weight.parse::<u32>().ok()?;
+    (1..=MAX_WEIGHT)
+        .contains(&weight)
+        .then_some((item.len(), weight))
+}
+
+/// Pick an item with probability proportional to its weight.
+pub fn weighted_choice<'a, T, R: Rng + ?Sized>(
+    items: &'a [(T, u32)],
+    rng: &mut R,
+) -> Option<&'a T> {
+    let dist = WeightedIndex::new(items.iter().map(|(_, weight)| *weight)).ok()?;
+    Some(&items[dist.sample(rng)].0)
+}
+
+/// Pick an item with probability proportional to its weight, using `seed`
+/// directly instead of an RNG. The same seed and items always give the same
+/// pick, whatever version of `rand` is in use.
+pub fn seeded_choice<T>(items: &[(T, u32)], seed: u64) -> Option<&T> {
+    let total: u64 = items.iter().map(|(_, weight)| u64::from(*weight)).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut target = seed % total;
+    for (item, weight) in items {
+        let weight = u64::from(*weight);
+        if target < weight {
+            return Some(item);
+        }
+        target -= weight;
+    }
+    None
+}
+
+/// 64-bit FNV-1a hash. Unlike `DefaultHasher` its output never changes
+/// between builds, so it is safe to derive persistent seeds from.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Draw up to `n` distinct items without replacement, each draw weighted
+/// by the remaining items' weights.
+pub fn weighted_sample<'a, T, R: Rng + ?Sized>(
+    items: &'a [(T, u32)],
+    n: usize,
+    rng: &mut R,
+) -> Vec<&'a T> {
+    let mut pool: Vec<(&'a T, u32)> = items.iter().map(|(item, weight)| (item, *weight)).collect();
+    let mut picked = Vec::new();
+    while picked.len() < n && !pool.is_empty() {
+        let Ok(dist) = WeightedIndex::new(pool.iter().map(|(_, weight)| *weight)) else {
+            break;
+        };
+        picked.push(pool.swap_remove(dist.sample(rng)).0);
+    }
+    picked
+}
+
+/// Serves every item (repeated by weight) once in random order before any repeats.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShuffleBag {
+    remaining: Vec<String>,
+}
+
+impl ShuffleBag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn remaining(&self) -> &[String] {
+        &self.remaining
+    }
+
+    /// Draw the next item, refilling the bag from `items` once it runs out.
+    /// Entries that are no longer in `items` are discarded.
+    pub fn draw<R: Rng + ?Sized>(
+        &mut self,
+        items: &[(String, u32)],
+        rng: &mut R,
+    ) -> Option<String> {
+        self.remaining
+            .retain(|entry| items.iter().any(|(item, _)| item == entry));
+        if self.remaining.is_empty() {
+            self.remaining = items
+                .iter()
+                .flat_map(|(item, weight)| std::iter::repeat_n(item.clone(), *weight as usize))
+                .collect();
+            self.remaining.shuffle(rng);
+        }
+        self.remaining.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use std::collections::HashMap;
+
+    fn items(pairs: &[(&str, u32)]) -> Vec<(String, u32)> {
+        pairs.iter().map(|(s, w)| (s.to_string(), *w)).collect()
+    }
+
+    #[test]
+    fn test_split_whitespace() {
+        assert_eq!(
+            split_choices("foo  bar baz").unwrap(),
+            vec!["foo", "bar", "baz"]
+        );
+        assert!(split_choices("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_quotes() {
+        assert_eq!(
+            split_choices(r#""Thai food" pizza 'fish and chips'"#).unwrap(),
+            vec!["Thai food", "pizza", "fish and chips"]
+        );
+        assert_eq!(
+            split_choices(r#"it\'s "say \"hi\"""#).unwrap(),
+            vec!["it's", "say \"hi\""]
+        );
+        assert_eq!(split_choices(r#""" a"#).unwrap(), vec!["", "a"]);
+        assert_eq!(split_choices("\"Thai:3\" x").unwrap(), vec!["Thai:3", "x"]);
+    }
+
+    #[test]
+    fn test_split_commas() {
+        assert_eq!(
+            split_choices("Thai food, pizza ,, fish and chips").unwrap(),
+            vec!["Thai food", "pizza", "fish and chips"]
+        );
+        assert_eq!(split_choices(r#""a, b" c"#).unwrap(), vec!["a, b", "c"]);
+        assert_eq!(split_choices(r#""a, b", c"#).unwrap(), vec!["a, b", "c"]);
+    }
+
+    #[test]
+    fn test_split_unclosed_quote() {
+        assert_eq!(split_choices("\"oops"), Err(SplitError::UnclosedQuote('"')));
+    }
+
+    #[test]
+    fn test_weighted_sample_distinct() {
+        let items = items(&[("a", 1), ("b", 5), ("c", 1)]);
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut picked: Vec<&String> = weighted_sample(&items, 3, &mut rng);
+        picked.sort();
+        assert_eq!(picked, vec!["a", "b", "c"]);
+        assert_eq!(weighted_sample(&items, 10, &mut rng).len(), 3);
+        assert!(weighted_sample(&items, 0, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_seeded_choice() {
+        let weighted = items(&[("a", 1), ("b", 3), ("c", 1)]);
+        let picks: Vec<&String> = (0..5)
+            .map(|seed| seeded_choice(&weighted, seed).unwrap())
+            .collect();
+        assert_eq!(picks, vec!["a", "b", "b", "b", "c"]);
+        assert_eq!(seeded_choice(&weighted, 7).unwrap(), "b");
+        assert!(seeded_choice(&items(&[("a", 0)]), 1).is_none());
+        assert!(seeded_choice::<String>(&[], 1).is_none());
+    }
+
+    #[test]
+    fn test_fnv1a_known_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_parse_weighted() {
+        assert_eq!(parse_weighted("pizza:3"), ("pizza", 3));
+        assert_eq!(parse_weighted("pizza"), ("pizza", 1));
+        assert_eq!(parse_weighted("pizza:"), ("pizza:", 1));
+        assert_eq!(parse_weighted(":3"), (":3", 1));
+        assert_eq!(parse_weighted("pizza:0"), ("pizza:0", 1));
+        assert_eq!(parse_weighted("pizza:-2"), ("pizza:-2", 1));
+        assert_eq!(parse_weighted("pizza:1000"), ("pizza:1000", 1));
+        assert_eq!(parse_weighted("a:b:2"), ("a:b", 2));
+    }
+
+    #[test]
+    fn test_split_weighted_choices() {
+        assert_eq!(
+            split_weighted_choices("pizza:3 tacos").unwrap(),
+            items(&[("pizza", 3), ("tacos", 1)])
+        );
+        assert_eq!(
+            split_weighted_choices(r#""Thai food":2 "Thai:3" 'x':4"#).unwrap(),
+            items(&[("Thai food", 2), ("Thai:3", 1), ("x", 4)])
+        );
+        assert_eq!(
+            split_weighted_choices(r"Thai\:3 a:b:2").unwrap(),
+            items(&[("Thai:3", 1), ("a:b", 2)])
+        );
+        assert_eq!(
+            split_weighted_choices(r#""a, b":2, c:5"#).unwrap(),
+            items(&[("a, b", 2), ("c", 5)])
+        );
+    }
+
+    #[test]
+    fn test_weighted_choice_empty() {
+        let empty: Vec<(String, u32)> = Vec::new();
+        assert!(weighted_choice(&empty, &mut StdRng::seed_from_u64(0)).is_none());
+    }
+
+    #[test]
+    fn test_weighted_choice_respects_weights() {
+        let items = items(&[("heavy", 9), ("light", 1)]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for _ in 0..1000 {
+            let pick = weighted_choice(&items, &mut rng).unwrap();
+            *counts.entry(pick.as_str()).or_default() += 1;
+        }
+        assert!(counts["heavy"] > counts["light"] * 4);
+    }
+
+    #[test]
+    fn test_shuffle_bag_serves_everything_before_repeating() {
+        let items = items(&[("a", 1), ("b", 1), ("c", 1)]);
+        let mut bag = ShuffleBag::new();
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut first_round: Vec<String> = (0..3)
+            .map(|_| bag.draw(&items, &mut rng).unwrap())
+            .collect();
+        first_round.sort();
+        assert_eq!(first_round, vec!["a", "b", "c"]);
+        assert!(bag.remaining().is_empty());
+        assert!(bag.draw(&items, &mut rng).is_some());
+        assert_eq!(bag.remaining().len(), 2);
+    }
+
+    #[test]
+    fn test_shuffle_bag_repeats_by_weight() {
+        let items = items(&[("a", 2), ("b", 1)]);
+        let mut bag = ShuffleBag::new();
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut round: Vec<String> = (0..3)
+            .map(|_| bag.draw(&items, &mut rng).unwrap())
+            .collect();
+        round.sort();
+        assert_eq!(round, vec!["a", "a", "b"]);
+    }
+
+    #[test]
+    fn test_shuffle_bag_drops_removed_items() {
+        let mut bag = ShuffleBag::new();
+        let mut rng = StdRng::seed_from_u64(11);
+        bag.draw(&items(&[("a", 1), ("b", 1), ("c", 1)]), &mut rng);
+        let pick = bag.draw(&items(&[("z", 1)]), &mut rng).unwrap();
+        assert_eq!(pick, "z");
+    }
+
+    #[test]
+    fn test_shuffle_bag_empty_items() {
+        let mut bag = ShuffleBag::new();
+        assert!(bag.draw(&[], &mut StdRng::seed_from_u64(0)).is_none());
+    }
+
+    #[test]
+    fn test_shuffle_bag_round_trips_through_json() {
+        let mut bag = ShuffleBag::new();
+        bag.draw(&items(&[("a", 1), ("b", 1)]), &mut StdRng::seed_from_u64(0));
+        let json = serde_json::to_string(&bag).unwrap();
+        let restored: ShuffleBag = serde_json::from_str(&json).unwrap();
+        assert_eq!(bag, restored);
+    }
+}